@@ -1,14 +1,34 @@
 use axum::extract::Request;
+use axum::http::header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::Extension;
-use axum::{debug_handler, extract::Path, response::Json, routing::get, Router};
+use axum::routing::post;
+use axum::{debug_handler, response::Json, routing::get, Router};
 use chrono::{DateTime, Local};
+use database::budget::{select_verdicts_from_project, VerdictRecord};
+use database::export::{export_records, ExportFormat};
+use database::energy::{select_energy_from_dimension, Energy};
+use database::listing::{
+    select_dimension_summaries, Cursor, DimensionFilter, DimensionSummary, ListParams, Page,
+};
+use database::sci::{select_sci_from_dimension, Sci};
+use database::summary::{select_summary_from_dimension, Summary};
+use database::tokens::{Permission, TokenScopes};
+use database::compare::{compare_pipelines, Comparison};
 use database::database::{
-    select_metrics_from_dimension, select_project_name_from_dimension, Record,
+    select_filtered_metrics_from_dimension, select_metrics_between_labels,
+    select_project_name_from_dimension, select_vcs_from_dimension, Aggregation, Bucket,
+    MetricsFilter, Record, VcsRecord,
 };
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Row};
 use std::collections::HashSet;
 use uuid::Uuid;
+use crate::auth::{authenticate, authorize_dimension, authorize_project};
+use crate::error::{ApiError, ApiPath, ApiQuery};
+use crate::ingest::{post_device, post_dimension, post_event, post_metrics, post_session};
 use crate::utils::format_uri_to_dimension;
 
 #[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
@@ -33,6 +53,8 @@ pub struct ProcessRecord {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ApiResponse {
     pub project_name: String,
+    pub vcs: Option<VcsRecord>,
+    pub energy: Option<Energy>,
     pub processes: Vec<ProcessRecord>,
 }
 
@@ -90,50 +112,395 @@ impl ApiResponseBuilder {
             .collect();
         ApiResponseBuilder(ApiResponse {
             project_name: project_name.to_owned(),
+            vcs: None,
+            energy: None,
             processes,
         })
     }
 
+    pub fn vcs(mut self, vcs: Option<VcsRecord>) -> Self {
+        self.0.vcs = vcs;
+        self
+    }
+
+    pub fn energy(mut self, energy: Energy) -> Self {
+        self.0.energy = Some(energy);
+        self
+    }
+
     pub fn build(self) -> ApiResponse {
         self.0
     }
 }
 
+fn parse_query_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ApiError> {
+    value
+        .parse()
+        .map_err(|_| ApiError::BadRequest(format!("Invalid value for {}: {}.", key, value)))
+}
+
+fn parse_query_timestamp(key: &str, value: &str) -> Result<DateTime<Local>, ApiError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|timestamp| timestamp.into())
+        .map_err(|_| {
+            ApiError::BadRequest(format!(
+                "Invalid value for {}: {}, expected an RFC 3339 timestamp.",
+                key, value
+            ))
+        })
+}
+
+/* Query parameters are read as pairs, as `metric` can be repeated, e.g.
+ * "?from=2024-11-05T11:00:00Z&metric=cpu_load&metric=ram_usage&exe=cargo&bucket=1m&agg=max". */
+fn parse_metrics_filter(pairs: Vec<(String, String)>) -> Result<MetricsFilter, ApiError> {
+    let mut filter = MetricsFilter::default();
+    let mut bucket = None;
+    let mut aggregation = None;
+    for (key, value) in pairs {
+        match key.as_str() {
+            "from" => filter.from = Some(parse_query_timestamp(&key, &value)?),
+            "to" => filter.to = Some(parse_query_timestamp(&key, &value)?),
+            "metric" => filter.metrics.push(value),
+            "exe" => filter.exe = Some(value),
+            "pid" => filter.pid = Some(parse_query_value(&key, &value)?),
+            "limit" => filter.limit = Some(parse_query_value(&key, &value)?),
+            "bucket" => bucket = Some(value),
+            "agg" => {
+                aggregation = Some(
+                    Aggregation::parse_str(&value)
+                        .map_err(|err| ApiError::BadRequest(err.to_string()))?,
+                )
+            }
+            _ => return Err(ApiError::BadRequest(format!("Unknown query parameter: {}.", key))),
+        }
+    }
+
+    filter.bucket = match (bucket, aggregation) {
+        (Some(bucket), aggregation) => Some(
+            Bucket::parse_str(&bucket, aggregation.unwrap_or_default())
+                .map_err(|err| ApiError::BadRequest(err.to_string()))?,
+        ),
+        (None, Some(_)) => {
+            return Err(ApiError::BadRequest(
+                "An aggregation needs a bucket to aggregate values in.".to_string(),
+            ))
+        }
+        (None, None) => None,
+    };
+    Ok(filter)
+}
+
+/* Records are exported when asked with "?format=csv" or "?format=parquet", or else with the
+ * Accept header; responses are JSON otherwise. */
+fn export_format(
+    query_pairs: &mut Vec<(String, String)>,
+    headers: &HeaderMap,
+) -> Result<Option<ExportFormat>, ApiError> {
+    let format_query = query_pairs
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "format")
+        .map(|(_, value)| value.to_owned());
+    query_pairs.retain(|(key, _)| key != "format");
+
+    match format_query.as_deref() {
+        Some("json") => Ok(None),
+        Some(format_str) => ExportFormat::parse_str(format_str)
+            .map(Some)
+            .map_err(|err| ApiError::BadRequest(err.to_string())),
+        None => Ok(headers
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .and_then(|accept| {
+                accept
+                    .split(',')
+                    .filter_map(|media_range| media_range.split(';').next())
+                    .find_map(|media_type| ExportFormat::from_content_type(media_type.trim()))
+            })),
+    }
+}
+
 #[debug_handler]
 pub async fn get_dimension(
     Extension(db_pool): Extension<PgPool>,
-    Path(dimension_id): Path<Uuid>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(dimension_id): ApiPath<Uuid>,
+    ApiQuery(mut query_pairs): ApiQuery<Vec<(String, String)>>,
     request: Request,
-) -> Json<ApiResponse> {
+) -> Result<Response, ApiError> {
 
+    let format = export_format(&mut query_pairs, request.headers())?;
+    let filter = parse_metrics_filter(query_pairs)?;
     let uri = request.uri();
     let dimension = format_uri_to_dimension(uri);
+    authorize_dimension(&db_pool, &token_scopes, &dimension, dimension_id, Permission::Read).await?;
 
     let project_name = select_project_name_from_dimension(
-        db_pool.acquire().await.unwrap(),
+        db_pool.acquire().await?,
         &dimension,
         dimension_id,
     )
-    .await
-    .unwrap()
+    .await?
     .get::<&str, &str>("name")
     .to_owned();
 
-    let rows =
-        select_metrics_from_dimension(db_pool.acquire().await.unwrap(), &dimension, dimension_id)
-            .await
-            .unwrap();
-    let response = ApiResponseBuilder::new(&rows, &project_name).build();
-    Json(response)
+    let rows = select_filtered_metrics_from_dimension(
+        db_pool.acquire().await?,
+        &dimension,
+        dimension_id,
+        &filter,
+    )
+    .await?;
+
+    if let Some(format) = format {
+        let exported = export_records(&rows, format)?;
+        let content_disposition = format!(
+            "attachment; filename=\"{}-{}.{}\"",
+            dimension, dimension_id, format
+        );
+        return Ok((
+            [
+                (CONTENT_TYPE, format.content_type().to_string()),
+                (CONTENT_DISPOSITION, content_disposition),
+            ],
+            exported,
+        )
+            .into_response());
+    }
+
+    let vcs =
+        select_vcs_from_dimension(db_pool.acquire().await?, &dimension, dimension_id).await?;
+    let energy =
+        select_energy_from_dimension(db_pool.acquire().await?, &dimension, dimension_id).await?;
+
+    let response = ApiResponseBuilder::new(&rows, &project_name)
+        .vcs(vcs)
+        .energy(energy)
+        .build();
+    Ok(Json(response).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SliceParams {
+    pub from: String,
+    pub to: String,
+}
+
+/* Metrics of a run between two of its custom events, identified by their labels. */
+#[debug_handler]
+pub async fn get_run_slice(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(run_id): ApiPath<Uuid>,
+    ApiQuery(params): ApiQuery<SliceParams>,
+) -> Result<Json<ApiResponse>, ApiError> {
+    authorize_dimension(&db_pool, &token_scopes, "run", run_id, Permission::Read).await?;
+
+    let project_name = select_project_name_from_dimension(db_pool.acquire().await?, "run", run_id)
+        .await?
+        .get::<&str, &str>("name")
+        .to_owned();
+
+    let rows = select_metrics_between_labels(
+        db_pool.acquire().await?,
+        run_id,
+        &params.from,
+        &params.to,
+    )
+    .await?
+    .ok_or_else(|| {
+        ApiError::NotFound(format!(
+            "No custom events labeled \"{}\" and \"{}\" found in run {}.",
+            params.from, params.to, run_id
+        ))
+    })?;
+
+    Ok(Json(ApiResponseBuilder::new(&rows, &project_name).build()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareParams {
+    pub base: Uuid,
+    pub head: Uuid,
+}
+
+#[debug_handler]
+pub async fn get_comparison(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiQuery(params): ApiQuery<CompareParams>,
+) -> Result<Json<Comparison>, ApiError> {
+    for pipeline_id in [params.base, params.head] {
+        authorize_dimension(&db_pool, &token_scopes, "pipeline", pipeline_id, Permission::Read)
+            .await?;
+    }
+
+    let comparison = compare_pipelines(&db_pool, params.base, params.head)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound("No metrics found for one of the compared pipelines.".to_string())
+        })?;
+
+    Ok(Json(comparison))
+}
+
+#[debug_handler]
+pub async fn get_budget_verdicts(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(project_id): ApiPath<Uuid>,
+) -> Result<Json<Vec<VerdictRecord>>, ApiError> {
+    authorize_project(&token_scopes, project_id, Permission::Read)?;
+
+    let verdicts = select_verdicts_from_project(db_pool.acquire().await?, project_id).await?;
+
+    Ok(Json(verdicts))
+}
+
+#[debug_handler]
+pub async fn get_sci(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(dimension_id): ApiPath<Uuid>,
+    request: Request,
+) -> Result<Json<Sci>, ApiError> {
+    let dimension = format_uri_to_dimension(request.uri());
+    authorize_dimension(&db_pool, &token_scopes, &dimension, dimension_id, Permission::Read).await?;
+
+    let sci = select_sci_from_dimension(db_pool.acquire().await?, &dimension, dimension_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No SCI score for {} {}.", dimension, dimension_id)))?;
+
+    Ok(Json(sci))
 }
 
+/* Totals of a dimension, overall and per process, for clients not to sum every sample. */
+#[debug_handler]
+pub async fn get_summary(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(dimension_id): ApiPath<Uuid>,
+    request: Request,
+) -> Result<Json<Summary>, ApiError> {
+    let dimension = format_uri_to_dimension(request.uri());
+    authorize_dimension(&db_pool, &token_scopes, &dimension, dimension_id, Permission::Read).await?;
+
+    let summary =
+        select_summary_from_dimension(db_pool.acquire().await?, &dimension, dimension_id).await?;
+
+    Ok(Json(summary))
+}
+
+fn validate_cursor(params: &ListParams) -> Result<(), ApiError> {
+    match &params.cursor {
+        Some(cursor) => Cursor::decode(cursor)
+            .map(|_| ())
+            .map_err(|_| ApiError::BadRequest(format!("Invalid cursor: {}.", cursor))),
+        None => Ok(()),
+    }
+}
+
+/* Only the projects the token can read are listed. */
+#[debug_handler]
+pub async fn get_projects(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> Result<Json<Page<DimensionSummary>>, ApiError> {
+    validate_cursor(&params)?;
+    let project_ids = token_scopes.projects.keys().copied().collect();
+
+    let page = select_dimension_summaries(
+        db_pool.acquire().await?,
+        "projects",
+        &DimensionFilter::Ids(project_ids),
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
+}
+
+/* Children of a dimension, e.g. pipelines of a project for "/projects/:project_id/pipelines":
+ * the parent is the first segment of the URI, the children the last one. */
+#[debug_handler]
+pub async fn get_children(
+    Extension(db_pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiPath(parent_id): ApiPath<Uuid>,
+    ApiQuery(params): ApiQuery<ListParams>,
+    request: Request,
+) -> Result<Json<Page<DimensionSummary>>, ApiError> {
+    validate_cursor(&params)?;
+    let parent_dimension = format_uri_to_dimension(request.uri());
+    let children_table = request
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+
+    match parent_dimension.as_str() {
+        "project" => authorize_project(&token_scopes, parent_id, Permission::Read)?,
+        _ => {
+            authorize_dimension(
+                &db_pool,
+                &token_scopes,
+                &parent_dimension,
+                parent_id,
+                Permission::Read,
+            )
+            .await?
+        }
+    }
+
+    let page = select_dimension_summaries(
+        db_pool.acquire().await?,
+        &children_table,
+        &DimensionFilter::Parent {
+            dimension: parent_dimension,
+            id: parent_id,
+        },
+        &params,
+    )
+    .await?;
+
+    Ok(Json(page))
+}
+
+/* Every route but the welcome one needs a token. */
 pub fn app() -> Router {
     Router::new()
-        .route("/", get(|| async { "Welcome to the Carenage API!\n" }))
+        .route("/projects", get(get_projects))
         .route("/runs/:run_id", get(get_dimension))
         .route("/projects/:project_id", get(get_dimension))
         .route("/workflows/:workflow_id", get(get_dimension))
         .route("/pipelines/:pipeline_id", get(get_dimension))
         .route("/jobs/:job_id", get(get_dimension))
         .route("/tasks/:task_id", get(get_dimension))
+        .route("/compare", get(get_comparison))
+        .route("/projects/:project_id/budgets", get(get_budget_verdicts))
+        .route("/runs/:run_id/sci", get(get_sci))
+        .route("/runs/:run_id/slice", get(get_run_slice))
+        .route("/pipelines/:pipeline_id/sci", get(get_sci))
+        .route("/projects/:project_id/summary", get(get_summary))
+        .route("/workflows/:workflow_id/summary", get(get_summary))
+        .route("/pipelines/:pipeline_id/summary", get(get_summary))
+        .route("/jobs/:job_id/summary", get(get_summary))
+        .route("/runs/:run_id/summary", get(get_summary))
+        .route("/tasks/:task_id/summary", get(get_summary))
+        .route("/projects/:project_id/workflows", get(get_children))
+        .route("/projects/:project_id/pipelines", get(get_children))
+        .route("/workflows/:workflow_id/pipelines", get(get_children))
+        .route("/pipelines/:pipeline_id/jobs", get(get_children))
+        .route("/jobs/:job_id/runs", get(get_children))
+        .route("/runs/:run_id/tasks", get(get_children))
+        .route("/ingest/dimensions/:dimension", post(post_dimension))
+        .route("/ingest/devices", post(post_device))
+        .route("/ingest/sessions", post(post_session))
+        .route("/ingest/events", post(post_event))
+        .route("/ingest/metrics", post(post_metrics))
+        .route_layer(middleware::from_fn(authenticate))
+        .route("/", get(|| async { "Welcome to the Carenage API!\n" }))
 }
@@ -0,0 +1,83 @@
+use axum::extract::Request;
+use axum::http::header::AUTHORIZATION;
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::Extension;
+use crate::error::ApiError;
+use database::database::Ids;
+use database::tokens::{
+    hash_token, refers_to_other_projects, select_project_of_dimension, select_token_scopes,
+    DimensionProject, Permission, TokenScopes,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+}
+
+/* Tokens are looked up by their hash, so that the token itself is never compared. The scopes of
+ * the token are passed on to handlers, which check them against the project of the data. */
+pub async fn authenticate(
+    Extension(db_pool): Extension<PgPool>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let token = bearer_token(request.headers()).ok_or(ApiError::Unauthorized)?;
+
+    let token_scopes = select_token_scopes(db_pool.acquire().await?, &hash_token(token))
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+
+    request.extensions_mut().insert(token_scopes);
+    Ok(next.run(request).await)
+}
+
+pub fn authorize_project(
+    token_scopes: &TokenScopes,
+    project_id: Uuid,
+    permission: Permission,
+) -> Result<(), ApiError> {
+    match token_scopes.allows(project_id, permission) {
+        true => Ok(()),
+        false => Err(ApiError::Forbidden),
+    }
+}
+
+/* A dimension without events belongs to no project yet: there is no data to read from it. */
+pub async fn authorize_dimension(
+    db_pool: &PgPool,
+    token_scopes: &TokenScopes,
+    dimension: &str,
+    dimension_id: Uuid,
+    permission: Permission,
+) -> Result<(), ApiError> {
+    match select_project_of_dimension(db_pool.acquire().await?, dimension, dimension_id).await? {
+        DimensionProject::Project(project_id) => {
+            authorize_project(token_scopes, project_id, permission)
+        }
+        DimensionProject::Conflicting => Err(ApiError::Forbidden),
+        DimensionProject::Unreferenced => Err(ApiError::NotFound(format!(
+            "No {} found with ID {}.",
+            dimension, dimension_id
+        ))),
+    }
+}
+
+/* Data is written to a project along with the dimensions it refers to, which have to belong to
+ * that project or to none yet. */
+pub async fn authorize_ingestion(
+    db_pool: &PgPool,
+    token_scopes: &TokenScopes,
+    ids: &Ids,
+) -> Result<(), ApiError> {
+    authorize_project(token_scopes, ids.project_id, Permission::Write)?;
+    match refers_to_other_projects(db_pool.acquire().await?, ids).await? {
+        true => Err(ApiError::Forbidden),
+        false => Ok(()),
+    }
+}
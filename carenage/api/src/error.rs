@@ -0,0 +1,163 @@
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::error::ErrorKind;
+
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized,
+    Forbidden,
+    NotFound(String),
+    Conflict(String),
+    UnprocessableEntity(String),
+    DatabaseUnavailable,
+    Internal,
+}
+
+/* Body of every error response, e.g.
+ * {"status": 404, "error": "not_found", "message": "No run found with ID ..."} */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::UnprocessableEntity(_) => "unprocessable_entity",
+            ApiError::DatabaseUnavailable => "database_unavailable",
+            ApiError::Internal => "internal_error",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::UnprocessableEntity(message) => message.to_owned(),
+            ApiError::Unauthorized => "A valid API token is needed.".to_string(),
+            ApiError::Forbidden => "The API token does not give access to this project.".to_string(),
+            ApiError::DatabaseUnavailable => "The database is unavailable.".to_string(),
+            ApiError::Internal => "An internal error occurred.".to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            status: self.status().as_u16(),
+            error: self.error().to_string(),
+            message: self.message(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/* A row missing for an ID is an unknown resource. Requests breaking a constraint of the database
+ * (SQLSTATE class 23) or holding data it refuses (class 22) are answered as client errors: a row
+ * already registered is a conflict, and records referring to unknown dimensions are rejected.
+ * A database that can not be reached leaves the API unavailable, other failures are internal. */
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Resource not found.".to_string()),
+            sqlx::Error::Database(database_error) => {
+                let message = database_error.message().to_owned();
+                match (database_error.kind(), database_error.code().as_deref()) {
+                    (ErrorKind::UniqueViolation, _) => ApiError::Conflict(message),
+                    (_, Some(code)) if code.starts_with("23") => {
+                        ApiError::UnprocessableEntity(message)
+                    }
+                    (_, Some(code)) if code.starts_with("22") => ApiError::BadRequest(message),
+                    _ => {
+                        error!("Database request failed: {}", message);
+                        ApiError::Internal
+                    }
+                }
+            }
+            err @ (sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)) => {
+                error!("Database is unavailable: {}", err);
+                ApiError::DatabaseUnavailable
+            }
+            err => {
+                error!("Database request failed: {}", err);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for ApiError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        match err.downcast::<sqlx::Error>() {
+            Ok(sqlx_error) => ApiError::from(*sqlx_error),
+            Err(err) => {
+                error!("Request failed: {}", err);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection.status() {
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::UnprocessableEntity(rejection.body_text()),
+            _ => ApiError::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+/* Extractors rejecting invalid IDs, query strings and bodies with a JSON error body. */
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct ApiPath<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
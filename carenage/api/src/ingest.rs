@@ -0,0 +1,219 @@
+use crate::auth::{authorize_ingestion, authorize_project};
+use crate::error::{ApiError, ApiJson, ApiPath};
+use axum::http::StatusCode;
+use axum::response::Json;
+use axum::Extension;
+use database::database::{
+    get_project_id, insert_device_metadata, insert_dimension_table_metadata,
+    insert_pipeline_metadata, parse_datetime_local, Ids,
+};
+use database::event::{Event, EventType};
+use database::ingest::{Ingested, SessionRegistration};
+use database::spool::{is_unique_violation, Sample, SpoolRecord};
+use database::tables::{DeviceInventory, Process};
+use database::tokens::{select_project_of_dimension, DimensionProject, Permission, TokenScopes};
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+const DIMENSION_TABLES: [&str; 6] = ["projects", "workflows", "pipelines", "jobs", "runs", "tasks"];
+
+fn authorize_writer(token_scopes: &TokenScopes) -> Result<(), ApiError> {
+    match token_scopes.writes_any_project() {
+        true => Ok(()),
+        false => Err(ApiError::Forbidden),
+    }
+}
+
+fn is_timestamp(value: &Value) -> bool {
+    value
+        .as_str()
+        .is_some_and(|timestamp| parse_datetime_local(timestamp).is_some())
+}
+
+/* Rows ingested through the API keep the ID given along with their metadata, if any, so that a
+ * runner retrying a registration finds the rows it registered before. */
+fn requested_id(metadata: &Value) -> Option<Uuid> {
+    metadata["id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+pub async fn post_dimension(
+    ApiPath(dimension): ApiPath<String>,
+    Extension(pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiJson(metadata): ApiJson<Value>,
+) -> Result<(StatusCode, Json<Ingested>), ApiError> {
+    if !DIMENSION_TABLES.contains(&dimension.as_str()) {
+        return Err(ApiError::BadRequest(format!("Invalid dimension: {}.", dimension)));
+    }
+    let Some(name) = metadata["name"].as_str().map(String::from) else {
+        return Err(ApiError::UnprocessableEntity(
+            "Metadata of a dimension needs a name.".to_string(),
+        ));
+    };
+    if !is_timestamp(&metadata["start_date"])
+        || !(metadata["commit_timestamp"].is_null() || is_timestamp(&metadata["commit_timestamp"]))
+    {
+        return Err(ApiError::UnprocessableEntity(
+            "Dates of a dimension need to be ISO 8601 timestamps.".to_string(),
+        ));
+    }
+
+    /* Projects are registered when issuing their tokens, and can not be created through the API. */
+    if dimension == "projects" {
+        let project_id = match get_project_id(pool.acquire().await?, &name).await {
+            Err(sqlx::Error::RowNotFound) => return Err(ApiError::Forbidden),
+            project_id => project_id?,
+        };
+        authorize_project(&token_scopes, project_id, Permission::Write)?;
+        return Ok((StatusCode::OK, Json(Ingested { id: project_id })));
+    }
+    /* Other dimensions are registered for the project given along with their metadata. */
+    let Some(project_id) = metadata["project_id"]
+        .as_str()
+        .and_then(|project_id| Uuid::parse_str(project_id).ok())
+    else {
+        return Err(ApiError::UnprocessableEntity(
+            "Metadata of a dimension needs the ID of its project.".to_string(),
+        ));
+    };
+    authorize_project(&token_scopes, project_id, Permission::Write)?;
+
+    let id = requested_id(&metadata);
+    let connection = pool.acquire().await?;
+    let dimension_row = match dimension.as_str() {
+        "pipelines" => insert_pipeline_metadata(connection, metadata).await,
+        _ => insert_dimension_table_metadata(connection, &dimension, metadata).await,
+    };
+    /* A dimension registered again is left as it is, unless events of another project refer to it. */
+    let dimension_row = match (dimension_row, id) {
+        (Err(err), Some(id)) if is_unique_violation(&err) => {
+            let dimension_project = select_project_of_dimension(
+                pool.acquire().await?,
+                dimension.trim_end_matches('s'),
+                id,
+            )
+            .await?;
+            return match dimension_project {
+                DimensionProject::Unreferenced => Ok((StatusCode::OK, Json(Ingested { id }))),
+                DimensionProject::Project(dimension_project_id)
+                    if dimension_project_id == project_id =>
+                {
+                    Ok((StatusCode::OK, Json(Ingested { id })))
+                }
+                _ => Err(ApiError::Forbidden),
+            };
+        }
+        (dimension_row, _) => dimension_row?,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Ingested {
+            id: dimension_row.get("id"),
+        }),
+    ))
+}
+
+pub async fn post_device(
+    Extension(pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiJson(inventory): ApiJson<Value>,
+) -> Result<(StatusCode, Json<Ingested>), ApiError> {
+    authorize_writer(&token_scopes)?;
+    if serde_json::from_value::<DeviceInventory>(inventory.clone()).is_err() {
+        return Err(ApiError::UnprocessableEntity(
+            "Invalid device inventory.".to_string(),
+        ));
+    }
+
+    let id = requested_id(&inventory);
+    let connection = pool.acquire().await?;
+    let device_row = match (insert_device_metadata(connection, inventory).await, id) {
+        (Err(err), Some(id)) if is_unique_violation(&err) => {
+            return Ok((StatusCode::OK, Json(Ingested { id })));
+        }
+        (device_row, _) => device_row?,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Ingested {
+            id: device_row.get("id"),
+        }),
+    ))
+}
+
+pub async fn post_session(
+    Extension(pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiJson(registration): ApiJson<SessionRegistration>,
+) -> Result<(StatusCode, Json<Ids>), ApiError> {
+    let mut ids = Ids {
+        project_id: registration.project_id,
+        workflow_id: registration.workflow_id,
+        pipeline_id: registration.pipeline_id,
+        job_id: registration.job_id,
+        run_id: registration.run_id,
+        task_id: registration.task_id,
+        device_id: registration.device_id,
+        process_id: Uuid::nil(),
+    };
+    authorize_ingestion(&pool, &token_scopes, &ids).await?;
+
+    let connection = pool.acquire().await?;
+    let process_insertion = registration
+        .process
+        .insert_with_id(registration.process_id, connection)
+        .await;
+    ids.process_id = match (process_insertion, registration.process_id) {
+        (Err(err), Some(process_id)) if is_unique_violation(&*err) => {
+            return Ok((StatusCode::OK, Json(Ids { process_id, ..ids })));
+        }
+        (process_row, _) => Process::get_id(process_row?),
+    };
+
+    Ok((StatusCode::CREATED, Json(ids)))
+}
+
+/* A stop event also closes the dimensions of its run, at the time of the event. Events already
+ * ingested are not inserted again, so that runners can retry sending them. */
+pub async fn post_event(
+    Extension(pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiJson(event): ApiJson<Event>,
+) -> Result<(StatusCode, Json<Ingested>), ApiError> {
+    authorize_ingestion(&pool, &token_scopes, &event.ids()).await?;
+
+    let id = event.id;
+    let record = match event.event_type {
+        EventType::Stop => SpoolRecord::Stop { event },
+        _ => SpoolRecord::Event { event },
+    };
+    record.insert(&pool).await?;
+
+    Ok((StatusCode::CREATED, Json(Ingested { id })))
+}
+
+pub async fn post_metrics(
+    Extension(pool): Extension<PgPool>,
+    Extension(token_scopes): Extension<TokenScopes>,
+    ApiJson(samples): ApiJson<Vec<Sample>>,
+) -> Result<(StatusCode, Json<Vec<Ingested>>), ApiError> {
+    for sample in &samples {
+        authorize_ingestion(&pool, &token_scopes, &sample.event.ids()).await?;
+    }
+
+    let mut ingested = vec![];
+    for sample in samples {
+        let id = sample.event.id;
+        SpoolRecord::Sample(Box::new(sample))
+            .insert(&pool)
+            .await?;
+        ingested.push(Ingested { id });
+    }
+
+    Ok((StatusCode::CREATED, Json(ingested)))
+}
@@ -1,2 +1,5 @@
 pub mod api;
+pub mod auth;
+pub mod error;
+pub mod ingest;
 pub mod utils;
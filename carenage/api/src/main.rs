@@ -20,7 +20,9 @@ async fn main() {
         .await
         .expect("Failed to connect to database");
 
-    let app = app().layer(Extension(db_pool)).layer(
+    let app = app()
+        .layer(Extension(db_pool))
+        .layer(
         TraceLayer::new_for_http()
             .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
             .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
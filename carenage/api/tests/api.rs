@@ -1,16 +1,49 @@
-use api::api::{get_dimension, ApiResponseBuilder};
+use api::api::{
+    get_budget_verdicts, get_children, get_comparison, get_dimension, get_projects,
+    get_run_slice, get_sci, get_summary, ApiResponseBuilder,
+};
+use api::api::app;
+use api::error::ErrorBody;
+use api::ingest::{post_dimension, post_event, post_metrics, post_session};
 use axum::Extension;
 use axum::{
-    body::Body,
+    body::{to_bytes, Body},
     http::{Request, StatusCode},
-    routing::get,
+    routing::{get, post},
     Router,
 };
+use chrono::{Duration, Local};
 use database::database::select_metrics_from_dimension;
-use sqlx::PgPool;
+use database::event::{Event, EventType};
+use database::ingest::{Ingested, SessionRegistration};
+use database::listing::{DimensionSummary, Page};
+use database::metrics::Metrics;
+use database::spool::Sample;
+use database::summary::Summary;
+use database::tables::ProcessBuilder;
+use database::tokens::{
+    generate_token, hash_token, insert_token, register_project, revoke_token, Permission,
+    TokenScopes,
+};
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use sqlx::{PgPool, Row};
 use tower::ServiceExt;
 use uuid::uuid;
 
+const FIXTURE_PROJECT_ID: uuid::Uuid = uuid!("95dfae11-5cad-41d9-bcf9-fa6564c22dd6");
+
+fn project_scopes(project_id: uuid::Uuid, permission: Permission) -> TokenScopes {
+    TokenScopes {
+        token_id: uuid::Uuid::new_v4(),
+        projects: HashMap::from([(project_id, permission)]),
+    }
+}
+
+fn fixture_project_scopes(permission: Permission) -> TokenScopes {
+    project_scopes(FIXTURE_PROJECT_ID, permission)
+}
+
 #[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
 fn it_formats_all_metrics_received_for_a_given_run_into_api_response_struct(
     pool: PgPool,
@@ -34,7 +67,8 @@ fn it_formats_all_metrics_received_for_a_given_run_into_api_response_struct(
 async fn it_returns_a_200_response_for_a_given_run_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/runs/:run_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
 
@@ -66,11 +100,230 @@ async fn it_returns_an_error_if_database_connection_fails(db_pool: PgPool) {
     assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
 }
 
+async fn error_body(response: axum::response::Response) -> ErrorBody {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_a_404_json_error_for_an_unknown_run_id(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let run_id = uuid::Uuid::new_v4();
+    let request = Request::builder()
+        .uri(format!("/runs/{run_id}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = error_body(response).await;
+    assert_eq!(body.status, 404);
+    assert_eq!(body.error, "not_found");
+    assert_eq!(body.message, format!("No run found with ID {run_id}."));
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_a_400_json_error_for_an_invalid_dimension_id(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .route("/ingest/dimensions/:dimension", post(post_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Write)));
+
+    let request = Request::builder()
+        .uri("/runs/not-a-uuid")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(error_body(response).await.error, "bad_request");
+
+    let request = ingestion_request(
+        "/ingest/dimensions/flows",
+        &json!({ "name": "flow", "start_date": Local::now().to_string() }),
+    );
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = error_body(response).await;
+    assert_eq!(body.error, "bad_request");
+    assert_eq!(body.message, "Invalid dimension: flows.");
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_a_503_json_error_when_the_database_is_unavailable(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool.clone()))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+    db_pool.close().await;
+
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let request = Request::builder()
+        .uri(format!("/runs/{run_id}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = error_body(response).await;
+    assert_eq!(body.status, 503);
+    assert_eq!(body.error, "database_unavailable");
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_returns_a_400_json_error_for_data_refused_by_the_database(db_pool: PgPool) {
+    let project_id = register_project(&db_pool, &"hubblo/ingested-project".to_string())
+        .await
+        .unwrap();
+    let app = ingestion_app(db_pool, project_scopes(project_id, Permission::Write));
+    let request = ingestion_request(
+        "/ingest/dimensions/jobs",
+        &json!({
+            "name": "build".repeat(100),
+            "start_date": Local::now().to_string(),
+            "project_id": project_id
+        }),
+    );
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = error_body(response).await;
+    assert_eq!(body.error, "bad_request");
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_filters_the_metrics_of_a_run_with_query_parameters(db_pool: PgPool) {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let records =
+        select_metrics_from_dimension(db_pool.acquire().await.unwrap(), "run", run_id)
+            .await
+            .unwrap();
+    let exe = records[0].exe.clone();
+    let metric_names: Vec<String> = records
+        .iter()
+        .map(|record| record.metric.clone())
+        .collect::<std::collections::BTreeSet<String>>()
+        .into_iter()
+        .take(2)
+        .collect();
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let url = format!(
+        "/runs/{run_id}?metric={}&metric={}&exe={}&from=2000-01-01T00:00:00Z",
+        metric_names[0], metric_names[1], exe
+    );
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let api_response: api::api::ApiResponse = serde_json::from_slice(&body).unwrap();
+    assert!(!api_response.processes.is_empty());
+    for process_record in &api_response.processes {
+        assert_eq!(process_record.process.process_exe, exe);
+        assert_eq!(process_record.metrics.len(), 2);
+        for process_metrics in &process_record.metrics {
+            assert!(metric_names.contains(&process_metrics.metric_name));
+        }
+    }
+
+    let url = format!("/runs/{run_id}?from=yesterday");
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_downsamples_the_metrics_of_a_run_by_time_bucket(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let url = format!("/runs/{run_id}?bucket=1h&agg=max");
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let api_response: api::api::ApiResponse = serde_json::from_slice(&body).unwrap();
+    for process_record in &api_response.processes {
+        for process_metrics in &process_record.metrics {
+            assert!(process_metrics.metric_values.len() <= 2);
+        }
+    }
+
+    for invalid_query in ["bucket=1w", "bucket=1m&agg=median", "agg=max"] {
+        let url = format!("/runs/{run_id}?{invalid_query}");
+        let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_exports_the_metrics_of_a_run_as_csv_or_parquet(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let url = format!("/runs/{run_id}?format=csv&metric=cpu_usage_percentage");
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/csv");
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let exported = String::from_utf8(body.to_vec()).unwrap();
+    let mut lines = exported.lines();
+    assert_eq!(
+        lines.next(),
+        Some("timestamp,pid,exe,cmdline,metric,value,unit")
+    );
+    assert!(lines.all(|line| line.contains(",cpu_usage_percentage,") && line.ends_with(",%")));
+
+    let request = Request::builder()
+        .uri(format!("/runs/{run_id}"))
+        .header("Accept", "application/vnd.apache.parquet")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.apache.parquet"
+    );
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(body.starts_with(b"PAR1"));
+
+    let url = format!("/runs/{run_id}?format=xml");
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
 #[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
 async fn it_returns_a_200_response_for_a_given_project_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/projects/:project_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let project_id = uuid!("95dfae11-5cad-41d9-bcf9-fa6564c22dd6");
 
@@ -87,7 +340,8 @@ async fn it_returns_a_200_response_for_a_given_project_id(db_pool: PgPool) {
 async fn it_returns_a_200_response_for_a_given_workflow_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/workflows/:workflow_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let workflow_id = uuid!("03c06a5e-a139-4a9e-a770-f69821b10faf");
 
@@ -104,7 +358,8 @@ async fn it_returns_a_200_response_for_a_given_workflow_id(db_pool: PgPool) {
 async fn it_returns_a_200_response_for_a_given_pipeline_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/pipelines/:pipeline_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
 
@@ -121,7 +376,8 @@ async fn it_returns_a_200_response_for_a_given_pipeline_id(db_pool: PgPool) {
 async fn it_returns_a_200_response_for_a_given_job_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/jobs/:job_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let job_id = uuid!("6579f658-9286-493e-a3ed-0d92afa09edd");
 
@@ -138,7 +394,8 @@ async fn it_returns_a_200_response_for_a_given_job_id(db_pool: PgPool) {
 async fn it_returns_a_200_response_for_a_given_task_id(db_pool: PgPool) {
     let app = Router::new()
         .route("/tasks/:task_id", get(get_dimension))
-        .layer(Extension(db_pool));
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
 
     let task_id = uuid!("83e9b273-9aa9-4996-8141-751b91aa98b2");
 
@@ -150,3 +407,552 @@ async fn it_returns_a_200_response_for_a_given_task_id(db_pool: PgPool) {
 
     assert_eq!(response.status(), StatusCode::OK);
 }
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_a_200_response_when_comparing_two_pipelines_with_metrics(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/compare", get(get_comparison))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+
+    let url = format!("/compare?base={pipeline_id}&head={pipeline_id}");
+
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_a_404_response_when_comparing_a_pipeline_without_metrics(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/compare", get(get_comparison))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let base_id = uuid!("80d53828-dcb8-4f45-aa4c-bc666e3ee54c");
+    let head_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+
+    let url = format!("/compare?base={base_id}&head={head_id}");
+
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_the_budget_verdicts_of_a_project(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/projects/:project_id/budgets", get(get_budget_verdicts))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let project_id = uuid!("95dfae11-5cad-41d9-bcf9-fa6564c22dd6");
+
+    let url = format!("/projects/{project_id}/budgets");
+
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_the_sci_score_of_a_run_and_of_a_pipeline(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id/sci", get(get_sci))
+        .route("/pipelines/:pipeline_id/sci", get(get_sci))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+
+    for url in [format!("/runs/{run_id}/sci"), format!("/pipelines/{pipeline_id}/sci")] {
+        let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_the_summary_of_a_pipeline(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/pipelines/:pipeline_id/summary", get(get_summary))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+    let pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+
+    let url = format!("/pipelines/{pipeline_id}/summary");
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let summary: Summary = serde_json::from_slice(&body).unwrap();
+    let emissions = &summary.overall["total_operational_emission_kgc02eq"];
+    assert_eq!(emissions.unit.as_deref(), Some("kgCO2eq"));
+    assert!(emissions.total > 0.0);
+    assert!(!summary.processes.is_empty());
+
+    let url = format!("/pipelines/{}/summary", uuid::Uuid::new_v4());
+    let request = Request::builder().uri(url).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_returns_the_metrics_of_a_run_between_two_labels(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id/slice", get(get_run_slice))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let request = Request::builder()
+        .uri(format!("/runs/{run_id}/slice?from=build%20done&to=tests%20done"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::builder()
+        .uri(format!("/runs/{run_id}/slice?from=build%20done&to=deploy"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_lists_only_the_projects_the_token_can_read(db_pool: PgPool) {
+    for (token_scopes, listed_projects) in [
+        (fixture_project_scopes(Permission::Read), vec![FIXTURE_PROJECT_ID]),
+        (project_scopes(uuid::Uuid::new_v4(), Permission::Read), vec![]),
+    ] {
+        let app = Router::new()
+            .route("/projects", get(get_projects))
+            .layer(Extension(db_pool.clone()))
+            .layer(Extension(token_scopes));
+
+        let request = Request::builder().uri("/projects").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page: Page<DimensionSummary> = serde_json::from_slice(&body).unwrap();
+        let project_ids: Vec<uuid::Uuid> = page.items.iter().map(|project| project.id).collect();
+        assert_eq!(project_ids, listed_projects);
+    }
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_lists_the_children_of_a_dimension(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/projects/:project_id/pipelines", get(get_children))
+        .route("/pipelines/:pipeline_id/jobs", get(get_children))
+        .layer(Extension(db_pool))
+        .layer(Extension(fixture_project_scopes(Permission::Read)));
+
+    let request = Request::builder()
+        .uri(format!("/projects/{FIXTURE_PROJECT_ID}/pipelines?sort=start_date&order=asc&limit=10"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page: Page<DimensionSummary> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.next_cursor, None);
+
+    let request = Request::builder()
+        .uri("/pipelines/9d807f09-e006-4808-9fa2-70f67432d37b/jobs")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::builder()
+        .uri(format!("/projects/{FIXTURE_PROJECT_ID}/pipelines?cursor=not-a-cursor"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = Request::builder()
+        .uri(format!("/projects/{}/pipelines", uuid::Uuid::new_v4()))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+fn ingestion_app(db_pool: PgPool, token_scopes: TokenScopes) -> Router {
+    Router::new()
+        .route("/ingest/dimensions/:dimension", post(post_dimension))
+        .route("/ingest/events", post(post_event))
+        .route("/ingest/metrics", post(post_metrics))
+        .route("/ingest/sessions", post(post_session))
+        .layer(Extension(db_pool))
+        .layer(Extension(token_scopes))
+}
+
+fn ingestion_request(uri: &str, body: &impl serde::Serialize) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap()))
+        .unwrap()
+}
+
+fn authenticated_request(uri: &str, token: Option<&str>) -> Request<Body> {
+    let mut request = Request::builder().uri(uri);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+async fn select_fixture_ids(db_pool: &PgPool) -> Vec<uuid::Uuid> {
+    let query = sqlx::query("SELECT * FROM project_ids()")
+        .fetch_all(db_pool)
+        .await
+        .unwrap();
+    query[0].get("project_ids")
+}
+
+fn event_for_fixture_run(vec_ids: &[uuid::Uuid], event_type: EventType) -> Event {
+    Event {
+        id: uuid::Uuid::new_v4(),
+        timestamp: Local::now() - Duration::minutes(1),
+        project_id: vec_ids[0],
+        workflow_id: vec_ids[1],
+        pipeline_id: vec_ids[2],
+        job_id: vec_ids[3],
+        run_id: vec_ids[4],
+        task_id: vec_ids[5],
+        process_id: vec_ids[6],
+        device_id: vec_ids[7],
+        event_type,
+        user_label: None,
+    }
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_authenticates_requests_with_issued_tokens_until_they_are_revoked(db_pool: PgPool) {
+    let app = app().layer(Extension(db_pool.clone()));
+    let run_url = "/runs/e51076c8-5c47-4a47-a146-04625e77a6ae";
+
+    let token = generate_token();
+    let token_id = insert_token(
+        &db_pool,
+        "dashboard",
+        &hash_token(&token),
+        &HashMap::from([(FIXTURE_PROJECT_ID, Permission::Read)]),
+    )
+    .await
+    .unwrap();
+
+    let response = app.clone().oneshot(authenticated_request("/", None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    for given_token in [None, Some("crn_guess")] {
+        let response = app
+            .clone()
+            .oneshot(authenticated_request(run_url, given_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let response = app
+        .clone()
+        .oneshot(authenticated_request(run_url, Some(&token)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert!(revoke_token(db_pool.acquire().await.unwrap(), token_id)
+        .await
+        .unwrap());
+    let response = app
+        .oneshot(authenticated_request(run_url, Some(&token)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/metrics.sql"))]
+async fn it_forbids_reading_a_project_outside_of_the_token_scopes(db_pool: PgPool) {
+    let app = Router::new()
+        .route("/runs/:run_id", get(get_dimension))
+        .layer(Extension(db_pool))
+        .layer(Extension(project_scopes(uuid::Uuid::new_v4(), Permission::Write)));
+
+    let request = Request::builder()
+        .uri("/runs/e51076c8-5c47-4a47-a146-04625e77a6ae")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/dimensions.sql"))]
+async fn it_forbids_ingestion_without_write_permission_on_the_project(db_pool: PgPool) {
+    let vec_ids = select_fixture_ids(&db_pool).await;
+    let event = event_for_fixture_run(&vec_ids, EventType::Start);
+
+    for token_scopes in [
+        project_scopes(vec_ids[0], Permission::Read),
+        project_scopes(uuid::Uuid::new_v4(), Permission::Write),
+    ] {
+        let app = ingestion_app(db_pool.clone(), token_scopes);
+        let request = ingestion_request("/ingest/events", &event);
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/dimensions.sql"))]
+async fn it_ingests_a_batch_of_samples_then_the_stop_event_of_a_run(db_pool: PgPool) {
+    let vec_ids = select_fixture_ids(&db_pool).await;
+    let app = ingestion_app(db_pool.clone(), project_scopes(vec_ids[0], Permission::Write));
+    let samples: Vec<Sample> = vec![
+        Sample {
+            event: event_for_fixture_run(&vec_ids, EventType::Regular),
+            process: ProcessBuilder::new(6042, "cargo", "cargo test", "running").build(),
+            metrics: Metrics {
+                energy_consumed_wh: 0.5,
+                ..Default::default()
+            },
+        },
+        Sample {
+            event: event_for_fixture_run(&vec_ids, EventType::Regular),
+            process: ProcessBuilder::new(6043, "rustc", "rustc --edition=2021", "running").build(),
+            metrics: Metrics::default(),
+        },
+    ];
+
+    let request = ingestion_request("/ingest/metrics", &samples);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let ingested: Vec<Ingested> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        ingested,
+        vec![
+            Ingested {
+                id: samples[0].event.id
+            },
+            Ingested {
+                id: samples[1].event.id
+            }
+        ]
+    );
+
+    let stop_event = event_for_fixture_run(&vec_ids, EventType::Stop);
+    let request = ingestion_request("/ingest/events", &stop_event);
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let energy_metrics = sqlx::query(
+        "SELECT value FROM metrics WHERE event_id = ($1) AND metric = 'energy_consumed_wh'",
+    )
+    .bind(samples[0].event.id)
+    .fetch_all(&db_pool)
+    .await
+    .unwrap();
+    assert_eq!(energy_metrics.len(), 1);
+    let run_stop_date = sqlx::query("SELECT stop_date FROM runs WHERE id = ($1)")
+        .bind(stop_event.run_id)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    assert!(run_stop_date
+        .get::<Option<chrono::DateTime<Local>>, &str>("stop_date")
+        .is_some());
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_only_accepts_projects_registered_with_a_token_and_valid_metadata(db_pool: PgPool) {
+    let project_name = "hubblo/ingested-project".to_string();
+    let project_id = register_project(&db_pool, &project_name).await.unwrap();
+    assert_eq!(
+        register_project(&db_pool, &project_name).await.unwrap(),
+        project_id
+    );
+    let app = ingestion_app(db_pool, project_scopes(project_id, Permission::Write));
+
+    let request = ingestion_request(
+        "/ingest/dimensions/projects",
+        &json!({ "name": project_name, "start_date": Local::now().to_string() }),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let ingested: Ingested = serde_json::from_slice(&body).unwrap();
+    assert_eq!(ingested.id, project_id);
+
+    let request = ingestion_request(
+        "/ingest/dimensions/projects",
+        &json!({ "name": "hubblo/unregistered", "start_date": Local::now().to_string() }),
+    );
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let invalid_metadata: Value = json!({ "name": "build", "start_date": "yesterday" });
+    let request = ingestion_request("/ingest/dimensions/jobs", &invalid_metadata);
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let request = ingestion_request("/ingest/dimensions/devices", &invalid_metadata);
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/dimensions.sql"))]
+async fn it_forbids_ingesting_data_referring_to_dimensions_of_another_project(db_pool: PgPool) {
+    let vec_ids = select_fixture_ids(&db_pool).await;
+    let start_event = event_for_fixture_run(&vec_ids, EventType::Start);
+    let app = ingestion_app(db_pool.clone(), project_scopes(vec_ids[0], Permission::Write));
+    let response = app
+        .oneshot(ingestion_request("/ingest/events", &start_event))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let other_project_id = register_project(&db_pool, &"hubblo/other-project".to_string())
+        .await
+        .unwrap();
+    let app = ingestion_app(db_pool.clone(), project_scopes(other_project_id, Permission::Write));
+    let mut event = event_for_fixture_run(&vec_ids, EventType::Regular);
+    event.project_id = other_project_id;
+    let sample = Sample {
+        event: event.clone(),
+        process: ProcessBuilder::new(6042, "cargo", "cargo test", "running").build(),
+        metrics: Metrics::default(),
+    };
+    let registration = SessionRegistration {
+        project_id: other_project_id,
+        workflow_id: vec_ids[1],
+        pipeline_id: vec_ids[2],
+        job_id: vec_ids[3],
+        run_id: vec_ids[4],
+        task_id: vec_ids[5],
+        device_id: vec_ids[7],
+        process: ProcessBuilder::new(6041, "carenage", "carenage start", "running").build(),
+        process_id: None,
+    };
+
+    for request in [
+        ingestion_request("/ingest/events", &event),
+        ingestion_request("/ingest/metrics", &vec![sample]),
+        ingestion_request("/ingest/sessions", &registration),
+    ] {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let events = sqlx::query("SELECT id FROM events WHERE project_id = ($1)")
+        .bind(other_project_id)
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+#[sqlx::test(fixtures("../../database/fixtures/dimensions.sql"))]
+async fn it_forbids_reading_a_dimension_referred_to_by_several_projects(db_pool: PgPool) {
+    let vec_ids = select_fixture_ids(&db_pool).await;
+    let other_project_id = register_project(&db_pool, &"hubblo/other-project".to_string())
+        .await
+        .unwrap();
+    let mut other_project_event = event_for_fixture_run(&vec_ids, EventType::Regular);
+    other_project_event.project_id = other_project_id;
+    for event in [
+        event_for_fixture_run(&vec_ids, EventType::Start),
+        other_project_event,
+    ] {
+        event.insert(db_pool.acquire().await.unwrap()).await.unwrap();
+    }
+
+    for project_id in [vec_ids[0], other_project_id] {
+        let app = Router::new()
+            .route("/runs/:run_id", get(get_dimension))
+            .layer(Extension(db_pool.clone()))
+            .layer(Extension(project_scopes(project_id, Permission::Read)));
+        let request = Request::builder()
+            .uri(format!("/runs/{}", vec_ids[4]))
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_registers_dimensions_for_the_project_given_with_their_metadata(db_pool: PgPool) {
+    let project_id = register_project(&db_pool, &"hubblo/ingested-project".to_string())
+        .await
+        .unwrap();
+    let app = ingestion_app(db_pool, project_scopes(project_id, Permission::Write));
+    let start_date = Local::now().to_string();
+
+    for (project_id, status) in [
+        (None, StatusCode::UNPROCESSABLE_ENTITY),
+        (Some(uuid::Uuid::new_v4()), StatusCode::FORBIDDEN),
+        (Some(project_id), StatusCode::CREATED),
+    ] {
+        let request = ingestion_request(
+            "/ingest/dimensions/jobs",
+            &json!({ "name": "build", "start_date": start_date, "project_id": project_id }),
+        );
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_registers_a_dimension_posted_again_with_its_id_once(db_pool: PgPool) {
+    let project_id = register_project(&db_pool, &"hubblo/ingested-project".to_string())
+        .await
+        .unwrap();
+    let app = ingestion_app(db_pool.clone(), project_scopes(project_id, Permission::Write));
+    let job_id = uuid::Uuid::new_v4();
+    let job = json!({
+        "id": job_id,
+        "name": "build",
+        "start_date": Local::now().to_string(),
+        "project_id": project_id
+    });
+
+    for status in [StatusCode::CREATED, StatusCode::OK] {
+        let response = app
+            .clone()
+            .oneshot(ingestion_request("/ingest/dimensions/jobs", &job))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let ingested: Ingested = serde_json::from_slice(&body).unwrap();
+        assert_eq!(ingested.id, job_id);
+    }
+    let jobs = sqlx::query("SELECT id FROM jobs")
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(jobs.len(), 1);
+}
@@ -1,4 +1,11 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use database::attribution::AttributionMode;
+use database::ci::CiPlatform;
+use database::compare::DEFAULT_COMPARED_METRICS;
+use database::export::ExportFormat;
+use database::tokens::Permission;
+use std::path::PathBuf;
+use uuid::Uuid;
 
 #[derive(Parser)]
 pub struct Cli {
@@ -8,6 +15,14 @@ pub struct Cli {
     /// Format timestamp to Unix epoch, in seconds
     #[arg(short, long)]
     pub unix: bool,
+
+    /// CI platform to read metadata from: "gitlab", "github", "jenkins" or "local"; detected from the environment if absent
+    #[arg(long, value_parser = parse_ci_platform)]
+    pub ci: Option<CiPlatform>,
+
+    /// Session of the carenaged to command; defaults to $CARENAGE_SESSION, or to the CI job, or out of CI to the PID of the calling shell
+    #[arg(long, global = true)]
+    pub session: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -15,6 +30,187 @@ pub struct StartArgs {
     /// Time step in seconds between events
     #[arg(short, long, default_value_t = 5)]
     pub step: u64,
+
+    #[command(flatten)]
+    pub attribution: AttributionArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct StopArgs {
+    /// Budget file the run is evaluated against, if present
+    #[arg(long, default_value = "carenage.toml")]
+    pub budget: PathBuf,
+
+    #[command(flatten)]
+    pub functional_unit: FunctionalUnitArgs,
+
+    #[command(flatten)]
+    pub reports: ReportArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct RunArgs {
+    /// Time step in seconds between events
+    #[arg(short, long, default_value_t = 5)]
+    pub step: u64,
+
+    #[command(flatten)]
+    pub attribution: AttributionArgs,
+
+    /// Budget file the run is evaluated against, if present
+    #[arg(long, default_value = "carenage.toml")]
+    pub budget: PathBuf,
+
+    #[command(flatten)]
+    pub functional_unit: FunctionalUnitArgs,
+
+    #[command(flatten)]
+    pub reports: ReportArgs,
+
+    /// Command to measure, with its arguments, given after `--`
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct EventArgs {
+    /// Label of the custom event, marking a phase of the CI job
+    pub label: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct StatusArgs {
+    /// Print the status as JSON instead of text
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct CompareArgs {
+    /// ID of the pipeline to compare against, usually the latest one of the target branch
+    #[arg(long)]
+    pub base: Uuid,
+
+    /// ID of the pipeline to compare
+    #[arg(long)]
+    pub head: Uuid,
+
+    /// Metric checked against thresholds, can be repeated
+    #[arg(long = "metric", default_values_t = DEFAULT_COMPARED_METRICS.map(String::from))]
+    pub metrics: Vec<String>,
+
+    /// Maximum relative increase allowed, in percent; 10% if no threshold is given
+    #[arg(long)]
+    pub max_increase_percent: Option<f64>,
+
+    /// Maximum absolute increase allowed, in the unit of the metric
+    #[arg(long)]
+    pub max_increase: Option<f64>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// ID of the run to export the metrics of
+    #[arg(long)]
+    pub run: Uuid,
+
+    /// Format of the export: "csv" or "parquet"
+    #[arg(long, default_value = "csv", value_parser = parse_export_format)]
+    pub format: ExportFormat,
+
+    /// File to write the export to; printed to the standard output if absent
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct TokenArgs {
+    #[command(subcommand)]
+    pub action: TokenAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenAction {
+    /// Issue a token giving access to the data of the given projects; it is only printed once
+    Issue(IssueTokenArgs),
+
+    /// Revoke a token, refused by the API from then on
+    Revoke(RevokeTokenArgs),
+
+    /// List issued tokens, revoked ones included
+    List,
+}
+
+#[derive(Parser, Debug)]
+pub struct IssueTokenArgs {
+    /// Name telling the token apart from others, e.g. "gitlab-runner"
+    #[arg(long)]
+    pub name: String,
+
+    /// Project the token gives access to, e.g. "hubblo/carenage"; can be repeated
+    #[arg(long = "project", required = true)]
+    pub projects: Vec<String>,
+
+    /// Permission on the projects: "read" their data, or also "write" it through ingestion
+    #[arg(long, default_value = "read", value_parser = parse_permission)]
+    pub permission: Permission,
+}
+
+#[derive(Parser, Debug)]
+pub struct RevokeTokenArgs {
+    /// ID of the token, as printed when issued or listed
+    pub token_id: Uuid,
+}
+
+#[derive(Args, Debug)]
+pub struct AttributionArgs {
+    /// Processes to attribute metrics to: "all" processes, or the process "tree" of the CI job
+    #[arg(long, default_value = "all", value_parser = parse_attribution_mode)]
+    pub attribution: AttributionMode,
+
+    /// With the "tree" attribution, aggregate metrics of all other processes in an "other" process
+    #[arg(long)]
+    pub other: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct FunctionalUnitArgs {
+    /// Functional unit the SCI score of the run is computed per, e.g. "test" or "1000 requests"
+    #[arg(long, requires = "functional_unit_count")]
+    pub functional_unit: Option<String>,
+
+    /// Count of functional units performed by the run, e.g. the number of tests executed
+    #[arg(long, requires = "functional_unit")]
+    pub functional_unit_count: Option<f64>,
+}
+
+/* Report artifacts of a CI job, e.g. `artifacts:reports:metrics` and `artifacts:reports:junit` of
+ * GitLab, written once the run is stopped. */
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Write the energy, emissions, primary energy and peak power of the run to a metrics report
+    #[arg(long)]
+    pub metrics_report: Option<PathBuf>,
+
+    /// Write the checks of the budget of the run to a JUnit XML report
+    #[arg(long)]
+    pub junit_report: Option<PathBuf>,
+}
+
+fn parse_attribution_mode(mode_str: &str) -> Result<AttributionMode, String> {
+    AttributionMode::parse_str(mode_str).map_err(|err| err.to_string())
+}
+
+fn parse_permission(permission_str: &str) -> Result<Permission, String> {
+    Permission::parse_str(permission_str).map_err(|err| err.to_string())
+}
+
+fn parse_export_format(format_str: &str) -> Result<ExportFormat, String> {
+    ExportFormat::parse_str(format_str).map_err(|err| err.to_string())
+}
+
+fn parse_ci_platform(platform_str: &str) -> Result<CiPlatform, String> {
+    CiPlatform::parse_str(platform_str).map_err(|err| err.to_string())
 }
 
 #[derive(Subcommand)]
@@ -22,6 +218,36 @@ pub enum Events {
     /// Start carenage, with an optional time step
     Start(StartArgs),
 
-    /// Stop carenage, final event
-    Stop,
+    /// Stop carenage, final event, and evaluate the run against its budget
+    Stop(StopArgs),
+
+    /// Run a command and measure it until it exits, with an optional time step
+    Run(RunArgs),
+
+    /// Take a sample right away and insert a custom event with the given label
+    Event(EventArgs),
+
+    /// Print the state and running totals of the session measured by carenaged
+    Status(StatusArgs),
+
+    /// Take a sample right away, without waiting for the next time step
+    Flush,
+
+    /// Stop sampling until resumed, e.g. while waiting on an external service
+    Pause,
+
+    /// Resume sampling after a pause
+    Resume,
+
+    /// Insert samples spooled by carenaged while the database was unreachable
+    Upload,
+
+    /// Compare metrics of two pipelines, failing if a threshold is exceeded
+    Compare(CompareArgs),
+
+    /// Export the metrics of a run, one metric value of a process per row
+    Export(ExportArgs),
+
+    /// Issue, revoke and list tokens of the API
+    Token(TokenArgs),
 }
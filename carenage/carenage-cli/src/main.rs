@@ -1,18 +1,389 @@
 use clap::Parser;
 use database::{
-    boagent::Config,
+    boagent::{Config, Transport},
+    ci::CiPlatform,
+    budget::{
+        insert_verdict, select_job_name_from_run, select_run_totals, BudgetFile,
+        Verdict, BUDGET_EXCEEDED_EXIT_CODE,
+    },
+    compare::{compare_pipelines, Comparison, Threshold},
+    control::{send_request, ControlRequest, ControlResponse, SESSION_ENV_VAR},
+    database::{get_db_connection_pool, select_metrics_from_dimension},
+    export::export_records,
+    ingest::IngestionClient,
+    report::{render_junit_report, render_metrics_report, select_run_report},
+    sci::{insert_functional_unit, select_sci_from_dimension, FunctionalUnit},
+    spool::upload_spool,
     timestamp::{self, UnixFlag},
+    tokens::{generate_token, hash_token, insert_token, register_project, revoke_token, select_tokens},
 };
-use log::{error, info};
-use std::{fs::File, io::Write, process::Command};
-use sysinfo::{Pid, Signal, System};
+use log::{error, info, warn};
+use uuid::Uuid;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{self, Child, Command, ExitStatus};
 
 pub mod cli;
 
+const DEFAULT_MAX_INCREASE_PERCENT: f64 = 10.0;
+
+fn spawn_carenaged(
+    step: u64,
+    start_timestamp: timestamp::Timestamp,
+    unix: bool,
+    attribution: &cli::AttributionArgs,
+    root_pid: u32,
+    session_id: &str,
+    run_label: Option<&str>,
+) -> Child {
+    let mut carenaged = Command::new("/usr/bin/carenaged");
+    carenaged
+        .arg("--step")
+        .arg(step.to_string())
+        .arg("--start-timestamp")
+        .arg(start_timestamp.to_string())
+        .arg("--attribution")
+        .arg(attribution.attribution.to_string())
+        .arg("--root-pid")
+        .arg(root_pid.to_string())
+        .arg("--session")
+        .arg(session_id);
+
+    if unix {
+        carenaged.arg("--unix");
+    }
+    if attribution.other {
+        carenaged.arg("--other");
+    }
+    if let Some(run_label) = run_label {
+        carenaged.env("RUN_LABEL", run_label);
+    }
+
+    carenaged.spawn().expect("Failed to fork carenaged.")
+}
+
+/* Steps of a CI job may be run by different shells: unless given, the session is the one of the CI
+ * job, so that start, event and stop reach the same carenaged. Out of CI, it is the one of the
+ * shell that called carenage-cli. */
+fn session_id(session: &Option<String>, ci_platform: CiPlatform) -> String {
+    if let Some(session_id) = session.clone().or(std::env::var(SESSION_ENV_VAR).ok()) {
+        return session_id;
+    }
+    let ci_session_id = match ci_platform {
+        CiPlatform::Local => Some(std::os::unix::process::parent_id().to_string()),
+        _ => ci_platform.provider().session_id(),
+    };
+    match ci_session_id {
+        Some(session_id) => session_id,
+        None => {
+            error!(
+                "The {} job could not be identified: its session needs to be given with --session or {}.",
+                ci_platform, SESSION_ENV_VAR
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn command_carenaged(session_id: &str, request: ControlRequest) -> ControlResponse {
+    match send_request(session_id, &request) {
+        Ok(response) => response,
+        Err(err) => {
+            error!("Failed to command carenaged: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/* carenaged answers requests once the metadata of the run and its start event are inserted: a
+ * response to a status request means that the session is sampling. */
+fn wait_for_carenaged(session_id: &str) -> bool {
+    match send_request(session_id, &ControlRequest::Status) {
+        Ok(ControlResponse::Status(_)) => true,
+        Ok(response) => {
+            warn!("Unexpected response of carenaged to a status request: {:?}", response);
+            false
+        }
+        Err(err) => {
+            warn!("carenaged is not ready: {}", err);
+            false
+        }
+    }
+}
+
+/* carenaged responds to a stop request once the last sample and the stop event are inserted, so
+ * that the data is complete once carenage-cli returns. */
+fn stop_carenaged(session_id: &str) -> Option<Uuid> {
+    match send_request(session_id, &ControlRequest::Stop) {
+        Ok(ControlResponse::Status(status)) => Some(status.run_id),
+        Ok(_) => None,
+        Err(err) => {
+            error!("Failed to stop carenaged: {}", err);
+            None
+        }
+    }
+}
+
+/* Following shell conventions, a command terminated by a signal exits with 128 + signal number. */
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or(status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+/* Budgets, reports and SCI scores are computed by carenage-cli from the database: with the API as
+ * transport, the database is not meant to be reachable from where carenage-cli runs. */
+fn database_config() -> Result<Config, Box<dyn std::error::Error>> {
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)?;
+    match config.transport {
+        Transport::Database => Ok(config),
+        Transport::Api { .. } => {
+            Err("it is computed from the database, unavailable with the API transport".into())
+        }
+    }
+}
+
+/* Returns whether the run measured by the stopped carenaged exceeded its budget. The verdict is
+ * stored, so that the budget history of the project is available through the API, and written to
+ * a JUnit report when requested. */
+fn enforce_budget(
+    run_id: Option<Uuid>,
+    budget_path: &Path,
+    junit_report_path: Option<&Path>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let budget_reading = BudgetFile::read(budget_path)
+        .map_err(|err| format!("unable to read {}: {}", budget_path.display(), err))?;
+    let Some(budget_file) = budget_reading else {
+        if junit_report_path.is_some() {
+            warn!("No budget file at {}, the JUnit report is not written.", budget_path.display());
+        }
+        return Ok(false);
+    };
+    let config = database_config()?;
+    let run_id = run_id.ok_or("the run ID of carenaged is unavailable")?;
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+    let verdict_attempt: Result<(Verdict, String), sqlx::Error> = runtime.block_on(async {
+        let db_pool = get_db_connection_pool(&config.database_url).await?;
+        let job_name = select_job_name_from_run(db_pool.acquire().await?, run_id).await?;
+        let totals = select_run_totals(db_pool.acquire().await?, run_id).await?;
+        let verdict = Verdict::evaluate(budget_file.limits_for_job(&job_name), totals);
+        insert_verdict(db_pool.acquire().await?, run_id, &verdict).await?;
+        Ok((verdict, job_name))
+    });
+    let (verdict, job_name) = verdict_attempt?;
+
+    println!("{}", verdict);
+    if let Some(junit_report_path) = junit_report_path {
+        write_report(junit_report_path, &render_junit_report(&verdict, &job_name))?;
+    }
+    Ok(verdict.exceeded)
+}
+
+fn write_report(report_path: &Path, report: &str) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::write(report_path, report)
+        .map_err(|err| format!("unable to write {}: {}", report_path.display(), err))?;
+    info!("Report written to {}.", report_path.display());
+    Ok(())
+}
+
+/* The metrics report is written for CI tools to show the totals of the run next to the job, e.g. in
+ * the merge request widgets of GitLab. The JUnit report is written when enforcing the budget. */
+fn write_metrics_report(
+    run_id: Option<Uuid>,
+    report_args: &cli::ReportArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(metrics_report_path) = &report_args.metrics_report else {
+        return Ok(());
+    };
+    let config = database_config()?;
+    let run_id = run_id.ok_or("the run ID of carenaged is unavailable")?;
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+    let run_report = runtime.block_on(async {
+        let db_pool = get_db_connection_pool(&config.database_url).await?;
+        select_run_report(db_pool.acquire().await?, run_id).await
+    })?;
+
+    write_report(metrics_report_path, &render_metrics_report(&run_report))
+}
+
+/* The functional unit is declared once the run is over, when the count of units performed by
+ * the run is known: it is stored with the run, and the SCI score of the run is printed. */
+fn report_sci(
+    run_id: Option<Uuid>,
+    functional_unit_args: &cli::FunctionalUnitArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(name), Some(count)) = (
+        &functional_unit_args.functional_unit,
+        functional_unit_args.functional_unit_count,
+    ) else {
+        return Ok(());
+    };
+    let functional_unit = FunctionalUnit::new(name, count)?;
+    let config = database_config()?;
+    let run_id = run_id.ok_or("the run ID of carenaged is unavailable")?;
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+    let sci = runtime.block_on(async {
+        let db_pool = get_db_connection_pool(&config.database_url).await?;
+        insert_functional_unit(db_pool.acquire().await?, run_id, &functional_unit).await?;
+        select_sci_from_dimension(db_pool.acquire().await?, "run", run_id).await
+    })?;
+
+    println!("{}", sci.ok_or("no SCI score is available for the run")?);
+    Ok(())
+}
+
+/* Returns the exit code of carenage-cli once the stopped run is reported: requested reports that
+ * can not be written fail the CI job rather than being missing from it. */
+fn report_run(
+    run_id: Option<Uuid>,
+    budget_path: &Path,
+    functional_unit_args: &cli::FunctionalUnitArgs,
+    report_args: &cli::ReportArgs,
+) -> i32 {
+    let mut exit_code = 0;
+    if let Err(err) = report_sci(run_id, functional_unit_args) {
+        error!("Failed to compute the SCI score of the run: {}", err);
+        exit_code = 1;
+    }
+    if let Err(err) = write_metrics_report(run_id, report_args) {
+        error!("Failed to write the metrics report of the run: {}", err);
+        exit_code = 1;
+    }
+    match enforce_budget(run_id, budget_path, report_args.junit_report.as_deref()) {
+        Ok(true) => BUDGET_EXCEEDED_EXIT_CODE,
+        Ok(false) => exit_code,
+        Err(err) => {
+            error!("Failed to evaluate the budget of the run: {}", err);
+            1
+        }
+    }
+}
+
+fn print_comparison(comparison: &Comparison, metrics: &[String]) {
+    println!(
+        "Comparing pipeline {} to pipeline {}:",
+        comparison.head_pipeline_id, comparison.base_pipeline_id
+    );
+    for metric_comparison in comparison
+        .overall
+        .iter()
+        .filter(|metric_comparison| metrics.contains(&metric_comparison.metric))
+    {
+        let relative_delta = metric_comparison
+            .relative_delta
+            .map_or("n/a".to_string(), |delta| format!("{:+.2}%", delta * 100.0));
+        println!(
+            "{}: {} -> {} ({:+}, {})",
+            metric_comparison.metric,
+            metric_comparison.base,
+            metric_comparison.head,
+            metric_comparison.delta,
+            relative_delta
+        );
+    }
+}
+
+/* With the API as transport, the export is made by the API, as the database may not be reachable
+ * from where carenage-cli runs. */
+fn export_run(args: &cli::ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+    let exported = runtime.block_on(async {
+        match &config.transport {
+            Transport::Api { api_url, api_token } => {
+                IngestionClient::new(api_url, api_token)
+                    .get_run_export(args.run, args.format)
+                    .await
+            }
+            Transport::Database => {
+                let db_pool = get_db_connection_pool(&config.database_url).await?;
+                let records =
+                    select_metrics_from_dimension(db_pool.acquire().await?, "run", args.run).await?;
+                if records.is_empty() {
+                    return Err(format!("No metrics found for run {}.", args.run).into());
+                }
+                export_records(&records, args.format)
+            }
+        }
+    })?;
+
+    let mut writer: Box<dyn Write + Send> = match &args.output {
+        Some(output_path) => Box::new(File::create(output_path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    writer.write_all(&exported)?;
+    Ok(())
+}
+
+/* Tokens are managed against the database directly, by whoever holds its credentials. */
+fn manage_tokens(action: &cli::TokenAction) -> Result<(), Box<dyn std::error::Error>> {
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+    runtime.block_on(async {
+        let db_pool = get_db_connection_pool(&config.database_url).await?;
+        match action {
+            cli::TokenAction::Issue(args) => {
+                let mut scopes = HashMap::new();
+                for project_name in &args.projects {
+                    let project_id = register_project(&db_pool, project_name).await?;
+                    scopes.insert(project_id, args.permission);
+                }
+                let token = generate_token();
+                let token_id = insert_token(&db_pool, &args.name, &hash_token(&token), &scopes).await?;
+                println!(
+                    "Issued {} token {} for {}. Store it now, it can not be shown again:",
+                    args.permission,
+                    token_id,
+                    args.projects.join(", ")
+                );
+                println!("{}", token);
+            }
+            cli::TokenAction::Revoke(args) => {
+                match revoke_token(db_pool.acquire().await?, args.token_id).await? {
+                    true => println!("Revoked token {}.", args.token_id),
+                    false => return Err(format!("No active token {}.", args.token_id).into()),
+                }
+            }
+            cli::TokenAction::List => {
+                for token in select_tokens(db_pool.acquire().await?).await? {
+                    let state = token
+                        .revoked_at
+                        .map_or("active".to_string(), |revoked_at| format!("revoked at {}", revoked_at));
+                    println!("{} {} (created at {}, {})", token.id, token.name, token.created_at, state);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
 fn main() {
     let cli = cli::Cli::parse();
     env_logger::init();
 
+    // carenaged and the configuration check read the CI platform override from the environment.
+    if let Some(ci_platform) = cli.ci {
+        std::env::set_var("CI_PLATFORM", ci_platform.to_string());
+    }
+
+    /* Commands that do not reach carenaged do not need a session. */
+    let ci_platform = cli.ci.unwrap_or_else(CiPlatform::detect);
+    let session = || session_id(&cli.session, ci_platform);
+
     match &cli.event {
         Some(cli::Events::Start(args)) => {
             let unix_flag: UnixFlag = cli.unix.into();
@@ -24,6 +395,7 @@ fn main() {
                 info!("Needed environment variables are set.");
             }
 
+            let session_id = session();
             let start_timestamp: timestamp::Timestamp = timestamp::Timestamp::new(unix_flag);
 
             info!(
@@ -35,40 +407,247 @@ fn main() {
                 start_timestamp.to_string()
             );
 
-            let carenaged = Command::new("/usr/bin/carenaged")
-                .arg(args.step.to_string())
-                .arg(start_timestamp.to_string())
-                .arg(cli.unix.to_string())
-                .spawn()
-                .expect("Failed to fork carenaged.");
-
-            let carenaged_pid = carenaged.id().to_string();
+            /* The CI job script is run by the shell that called carenage-cli: its process tree is
+             * the one metrics are attributed to with the "tree" attribution. */
+            let root_pid = std::os::unix::process::parent_id();
 
-            File::create("/tmp/carenagepid")
-                .expect("Failed to create file to save child process PID.")
-                .write_all(carenaged_pid.as_bytes())
-                .expect("Failed to save child process PID in file.");
+            // carenaged is meant to outlive carenage-cli: it is reaped once reparented.
+            #[allow(clippy::zombie_processes)]
+            let _carenaged = spawn_carenaged(
+                args.step,
+                start_timestamp,
+                cli.unix,
+                &args.attribution,
+                root_pid,
+                &session_id,
+                None,
+            );
+            info!("Carenage session is {}.", session_id);
         }
-        Some(cli::Events::Stop) => {
+        Some(cli::Events::Stop(args)) => {
             let unix_flag: UnixFlag = cli.unix.into();
             let stop_timestamp = timestamp::Timestamp::new(unix_flag);
-            let system = System::new_all();
-
             let printable_stop_timestamp = stop_timestamp.to_string();
 
             info!("Carenage stop event.");
             info!("Stop event timestamp is {:?}.", printable_stop_timestamp);
 
-            let pid = std::fs::read_to_string("/tmp/carenagepid")
-                .expect("Failed to open file with saved child process PID.")
-                .parse::<u32>()
-                .expect("Failed to convert carenagepid to u32.");
+            let run_id = stop_carenaged(&session());
+            info!("Carenage daemon stopped.");
+
+            let report_exit_code =
+                report_run(run_id, &args.budget, &args.functional_unit, &args.reports);
+            if report_exit_code != 0 {
+                process::exit(report_exit_code);
+            }
+        }
+        Some(cli::Events::Run(args)) => {
+            let unix_flag: UnixFlag = cli.unix.into();
+            let start_timestamp = timestamp::Timestamp::new(unix_flag);
+            let run_label = args.command.join(" ");
+
+            info!(
+                "Carenage run event for `{}`, time step of {:?} seconds.",
+                run_label, args.step
+            );
+            info!(
+                "Start event timestamp is {:?}.",
+                start_timestamp.to_string()
+            );
+
+            /* carenaged is spawned first and waited for, so that even short commands are
+             * sampled. The measured command being a child of this carenage-cli process, the
+             * process tree metrics are attributed to is rooted here. Unless given, the session
+             * is scoped to this carenage-cli process, so that runs in a row or in parallel do not
+             * collide. */
+            let session_id = cli.session.clone().unwrap_or(process::id().to_string());
+            let mut carenaged = spawn_carenaged(
+                args.step,
+                start_timestamp,
+                cli.unix,
+                &args.attribution,
+                process::id(),
+                &session_id,
+                Some(&run_label),
+            );
+            if !wait_for_carenaged(&session_id) {
+                warn!("Running `{}` without waiting for carenaged.", run_label);
+            }
+
+            let command_status = match Command::new(&args.command[0])
+                .args(&args.command[1..])
+                .spawn()
+            {
+                Ok(mut command) => command.wait(),
+                Err(err) => {
+                    error!("Failed to run `{}`: {}", run_label, err);
+                    stop_carenaged(&session_id);
+                    let _ = carenaged.wait();
+                    process::exit(127);
+                }
+            };
+
+            /* Whatever happens to carenaged, carenage-cli exits with the status of the command. */
+            let run_id = stop_carenaged(&session_id);
+            if let Err(err) = carenaged.wait() {
+                error!("Failed to wait for carenaged to exit: {}", err);
+            }
+            let command_status = match command_status {
+                Ok(command_status) => command_status,
+                Err(err) => {
+                    error!("Failed to wait for `{}` to exit: {}", run_label, err);
+                    process::exit(1);
+                }
+            };
+
+            info!("`{}` exited with {}.", run_label, command_status);
+
+            /* A failure of the measured command takes precedence over an exceeded budget or a
+             * failed report. */
+            let report_exit_code =
+                report_run(run_id, &args.budget, &args.functional_unit, &args.reports);
+            if command_status.success() && report_exit_code != 0 {
+                process::exit(report_exit_code);
+            }
+            process::exit(exit_code(command_status));
+        }
+        Some(cli::Events::Event(args)) => {
+            info!("Carenage custom event \"{}\".", args.label);
 
-            system
-                .process(Pid::from_u32(pid))
-                .expect("Failed to retrieve carenaged process with given PID.")
-                .kill_with(Signal::Term)
-                .expect("Failed to terminate carenaged process with SIGTERM.");
+            /* carenaged responds once the event is inserted, for events sent in a row to be kept
+             * in order. */
+            command_carenaged(
+                &session(),
+                ControlRequest::Event {
+                    label: args.label.clone(),
+                },
+            );
+            info!("Custom event inserted.");
+        }
+        Some(cli::Events::Status(args)) => {
+            let ControlResponse::Status(status) = command_carenaged(&session(), ControlRequest::Status)
+            else {
+                error!("Unexpected response of carenaged to a status request.");
+                process::exit(1);
+            };
+            match args.json {
+                true => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&status).expect("Failed to serialize status.")
+                ),
+                false => println!("{}", status),
+            }
+        }
+        Some(cli::Events::Flush) => {
+            command_carenaged(&session(), ControlRequest::Flush);
+            info!("Sample inserted.");
+        }
+        Some(cli::Events::Pause) => {
+            command_carenaged(&session(), ControlRequest::Pause);
+            info!("Carenage daemon paused.");
+        }
+        Some(cli::Events::Resume) => {
+            command_carenaged(&session(), ControlRequest::Resume);
+            info!("Carenage daemon resumed.");
+        }
+        Some(cli::Events::Upload) => {
+            let project_root_path = std::env::current_dir().unwrap().join("..");
+            let config = Config::check_configuration(&project_root_path)
+                .expect("Configuration fields should be parsable.");
+
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+            let upload_attempt = runtime.block_on(async {
+                match &config.transport {
+                    Transport::Database => {
+                        let db_pool = get_db_connection_pool(&config.database_url).await?;
+                        upload_spool(&db_pool, &config.spool_dir).await
+                    }
+                    Transport::Api { api_url, api_token } => {
+                        let client = IngestionClient::new(api_url, api_token);
+                        upload_spool(&client, &config.spool_dir).await
+                    }
+                }
+            });
+
+            match upload_attempt {
+                Ok(summary) => {
+                    println!(
+                        "Uploaded {} records from {} spool files.",
+                        summary.records, summary.files
+                    );
+                    if summary.invalid_lines > 0 {
+                        warn!("Skipped {} invalid lines.", summary.invalid_lines);
+                    }
+                    if summary.rejected_records > 0 {
+                        warn!(
+                            "Moved {} records rejected by carenage to the .rejected files of {}.",
+                            summary.rejected_records,
+                            config.spool_dir.display()
+                        );
+                    }
+                }
+                Err(err) => {
+                    error!("Failed to upload spooled samples: {}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(cli::Events::Compare(args)) => {
+            let project_root_path = std::env::current_dir().unwrap().join("..");
+            let config = Config::check_configuration(&project_root_path)
+                .expect("Configuration fields should be parsable.");
+
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime.");
+            let comparison_attempt = runtime.block_on(async {
+                let db_pool = get_db_connection_pool(&config.database_url).await?;
+                compare_pipelines(&db_pool, args.base, args.head).await
+            });
+
+            let comparison = match comparison_attempt {
+                Ok(Some(comparison)) => comparison,
+                Ok(None) => {
+                    error!("No metrics found for one of the compared pipelines.");
+                    process::exit(2);
+                }
+                Err(err) => {
+                    error!("Failed to compare pipelines: {}", err);
+                    process::exit(2);
+                }
+            };
+
+            let threshold = match (args.max_increase_percent, args.max_increase) {
+                (None, None) => Threshold {
+                    max_relative_increase: Some(DEFAULT_MAX_INCREASE_PERCENT / 100.0),
+                    max_absolute_increase: None,
+                },
+                (max_increase_percent, max_increase) => Threshold {
+                    max_relative_increase: max_increase_percent.map(|percent| percent / 100.0),
+                    max_absolute_increase: max_increase,
+                },
+            };
+
+            print_comparison(&comparison, &args.metrics);
+
+            let regressions = comparison.regressions(&args.metrics, threshold);
+            if !regressions.is_empty() {
+                for regression in regressions {
+                    error!("{} exceeds the allowed increase.", regression.metric);
+                }
+                process::exit(1);
+            }
+            info!("No regression found.");
+        }
+        Some(cli::Events::Export(args)) => {
+            if let Err(err) = export_run(args) {
+                error!("Failed to export the metrics of the run: {}", err);
+                process::exit(1);
+            }
+        }
+        Some(cli::Events::Token(args)) => {
+            if let Err(err) = manage_tokens(&args.action) {
+                error!("Failed to manage tokens: {}", err);
+                process::exit(1);
+            }
         }
         None => {
             error!("Unknown command.")
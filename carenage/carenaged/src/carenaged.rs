@@ -1,73 +1,140 @@
+use database::attribution::{Attribution, AttributionMode, OTHER_PROCESSES_EXE};
 use database::boagent::{
     deserialize_boagent_json, process_embedded_impacts, query_boagent, Config, HardwareData,
+    Transport,
 };
-use database::ci::GitlabVariables;
-use database::database::{
-    check_process_existence_for_id, collect_processes, get_db_connection_pool, get_process_id, Ids,
-};
+use database::control::{ControlRequest, ControlResponse, SessionStatus, TrackedProcess};
+use database::database::{collect_processes, get_db_connection_pool, Ids};
 use database::event::{Event, EventBuilder, EventType};
-use database::metrics::Metrics;
+use database::metrics::{Metrics, OperationalTotals};
+use database::ingest::IngestionClient;
+use database::spool::{RecordSink, Sample, SessionMetadata, Spool, SpoolRecord, SESSION_DIMENSIONS};
 use database::tables::{CarenageRow, Metadata};
 use database::tables::{Process, ProcessBuilder};
 use database::timestamp::{Timestamp, UnixFlag};
+use crate::exposition::{render_openmetrics, ExpositionLabels};
+#[cfg(feature = "otlp")]
+use crate::otlp::OtlpExporter;
+use chrono::Local;
+use clap::Parser;
 use log::{info, warn};
-use std::env;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::process;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::time::{timeout, Duration};
+use uuid::Uuid;
+
+/* Clients of the control socket are given little time to send their request and read the
+ * response, as sampling and signals wait for them meanwhile. */
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* Arguments are given by carenage-cli when spawning carenaged. */
+#[derive(Parser, Debug)]
+struct DaemonCli {
+    /// Time step in seconds between events
+    #[arg(long)]
+    step: u64,
+
+    /// Start timestamp of the run, in ISO 8601 or Unix epoch format
+    #[arg(long)]
+    start_timestamp: String,
+
+    /// Start timestamp is in Unix epoch format, in seconds
+    #[arg(long)]
+    unix: bool,
+
+    /// Processes to attribute metrics to: "all" processes, or the process "tree" of the CI job
+    #[arg(long, default_value = "all")]
+    attribution: String,
+
+    /// Root of the process tree metrics are attributed to, carenaged itself if absent
+    #[arg(long)]
+    root_pid: Option<i32>,
+
+    /// Aggregate metrics of processes outside of the CI job in an "other" process
+    #[arg(long)]
+    other: bool,
+
+    /// Session carenaged is scoped to, naming its control socket
+    #[arg(long)]
+    session: String,
+}
 
 pub struct DaemonArgs {
     pub time_step: u64,
     pub start_timestamp: Timestamp,
     pub unix_flag: UnixFlag,
+    pub attribution_mode: AttributionMode,
+    pub root_pid: i32,
+    pub other_bucket: bool,
+    pub session_id: String,
 }
 
 impl DaemonArgs {
     pub fn parse_args() -> Result<DaemonArgs, Box<dyn std::error::Error>> {
-        let args: Vec<String> = env::args().collect();
-        let time_step: u64 = args[1].parse()?;
-        let start_time_str = args[2].to_string();
-        let is_unix_set: bool = args[3].parse()?;
-        let unix_flag: UnixFlag = is_unix_set.into();
-        let start_timestamp = Timestamp::parse_str(start_time_str, unix_flag);
+        let args = DaemonCli::try_parse()?;
+        let unix_flag: UnixFlag = args.unix.into();
 
         info!("All needed daemon arguments are available!");
 
         Ok(DaemonArgs {
-            time_step,
-            start_timestamp,
+            time_step: args.step,
+            start_timestamp: Timestamp::parse_str(args.start_timestamp, unix_flag),
             unix_flag,
+            attribution_mode: AttributionMode::parse_str(&args.attribution)?,
+            root_pid: args.root_pid.unwrap_or(process::id() as i32),
+            other_bucket: args.other,
+            session_id: args.session,
         })
     }
 }
 
-pub async fn insert_metadata(
-    gitlab_vars: GitlabVariables,
-    start_timestamp: Timestamp,
-    unix_flag: UnixFlag,
-    config: &Config,
-) -> Result<Ids, Box<dyn std::error::Error>> {
-    let project_rows = CarenageRow::Project.insert(start_timestamp, None, config).await?;
-    let project_id = CarenageRow::Project
-        .get_id(project_rows, Some(&gitlab_vars.project_path))
-        .await?;
-
-    let workflow_rows = CarenageRow::Workflow.insert(start_timestamp, None, config).await?;
-    let workflow_id = CarenageRow::Workflow.get_id(workflow_rows, None).await?;
-
-    let pipeline_rows = CarenageRow::Pipeline.insert(start_timestamp, None, config).await?;
-    let pipeline_id = CarenageRow::Pipeline.get_id(pipeline_rows, None).await?;
-
-    let job_rows = CarenageRow::Job.insert(start_timestamp, None, config).await?;
-    let job_id = CarenageRow::Job.get_id(job_rows, None).await?;
-
-    let run_rows = CarenageRow::Run.insert(start_timestamp, None, config).await?;
-    let run_id = CarenageRow::Run.get_id(run_rows, None).await?;
+/* Each query to Boagent covers the time window since the end of the previous one, so that the
+ * load on Boagent stays constant over long runs. Operational impacts of the windows are summed
+ * into the cumulative impacts of the run, along with the running totals reported by the status
+ * of the session. With the otlp feature, the samples of each window are also exported. */
+pub struct SamplingWindow {
+    pub previous_end: Timestamp,
+    pub cumulative_totals: OperationalTotals,
+    pub sample_count: u64,
+    pub energy_wh: f64,
+    pub tracked_processes: BTreeMap<i32, String>,
+    pub latest_metrics: BTreeMap<i32, LatestMetrics>,
+    pub last_boagent_error: Option<String>,
+    #[cfg(feature = "otlp")]
+    pub exporter: Option<OtlpExporter>,
+}
 
-    let task_rows = CarenageRow::Task.insert(start_timestamp, None, config).await?;
-    let task_id = CarenageRow::Task.get_id(task_rows, None).await?;
+/* Metrics of a process in the last sample taken, served to Prometheus when enabled. */
+#[derive(Clone, Debug)]
+pub struct LatestMetrics {
+    pub exe: String,
+    pub metrics: Metrics,
+}
 
-    let project_root_path = std::env::current_dir().unwrap().join("..");
-    let config = Config::check_configuration(&project_root_path)?;
+impl SamplingWindow {
+    pub fn new(start_timestamp: Timestamp) -> Self {
+        SamplingWindow {
+            previous_end: start_timestamp,
+            cumulative_totals: OperationalTotals::default(),
+            sample_count: 0,
+            energy_wh: 0.0,
+            tracked_processes: BTreeMap::new(),
+            latest_metrics: BTreeMap::new(),
+            last_boagent_error: None,
+            #[cfg(feature = "otlp")]
+            exporter: None,
+        }
+    }
+}
 
+async fn query_hardware(
+    start_timestamp: Timestamp,
+    unix_flag: UnixFlag,
+    config: &Config,
+) -> Result<Value, Box<dyn std::error::Error>> {
     let end_time = Timestamp::new(unix_flag);
     let response = query_boagent(
         &config.boagent_url,
@@ -78,58 +145,125 @@ pub async fn insert_metadata(
         config.lifetime,
     )
     .await?;
-    let deserialized_boagent_response = deserialize_boagent_json(response).await?;
-    let insert_device_data = CarenageRow::Device
-        .insert(start_timestamp, Some(deserialized_boagent_response), &config)
-        .await?;
-    let device_id = CarenageRow::Device.get_id(insert_device_data, None).await?;
+    Ok(deserialize_boagent_json(response).await?)
+}
 
-    let start_process = ProcessBuilder::new(
+fn start_process() -> Process {
+    ProcessBuilder::new(
         process::id() as i32,
         "carenage",
         "carenage start",
         "running",
     )
-    .build();
-
-    let db_pool = get_db_connection_pool(&config.database_url)
-        .await?
-        .acquire()
-        .await?;
-
-    let process_row = Process::insert(&start_process, db_pool).await?;
-    let process_id = Process::get_id(process_row);
-
-    let ids = Ids {
-        project_id,
-        workflow_id,
-        pipeline_id,
-        job_id,
-        run_id,
-        task_id,
-        device_id,
-        process_id,
+    .build()
+}
+
+/* IDs of the session are generated by carenaged, so that it can run while its metadata can not be
+ * registered: the metadata is then spooled, and registered when the spool is uploaded. */
+pub async fn insert_metadata(
+    start_timestamp: Timestamp,
+    unix_flag: UnixFlag,
+    config: &Config,
+) -> Result<Ids, Box<dyn std::error::Error>> {
+    let deserialized_boagent_response = query_hardware(start_timestamp, unix_flag, config).await?;
+    let metadata = SessionMetadata {
+        ids: Ids {
+            project_id: Uuid::new_v4(),
+            workflow_id: Uuid::new_v4(),
+            pipeline_id: Uuid::new_v4(),
+            job_id: Uuid::new_v4(),
+            run_id: Uuid::new_v4(),
+            task_id: Uuid::new_v4(),
+            device_id: Uuid::new_v4(),
+            process_id: Uuid::new_v4(),
+        },
+        dimensions: SESSION_DIMENSIONS
+            .iter()
+            .map(|row| row.serialize(start_timestamp, None, config))
+            .collect(),
+        device: CarenageRow::Device.serialize(
+            start_timestamp,
+            Some(deserialized_boagent_response),
+            config,
+        ),
+        process: start_process(),
+    };
+
+    let registration = match &config.transport {
+        Transport::Database => match get_db_connection_pool(&config.database_url).await {
+            Ok(db_pool) => db_pool.register(&metadata).await,
+            Err(err) => Err(err.into()),
+        },
+        Transport::Api { api_url, api_token } => {
+            IngestionClient::new(api_url, api_token)
+                .register(&metadata)
+                .await
+        }
     };
-    Ok(ids)
+
+    match registration {
+        Ok(ids) => Ok(ids),
+        Err(err) => {
+            warn!("Failed to register metadata of the session: {}", err);
+            let spool = Spool::for_process(&config.spool_dir);
+            spool.append(&SpoolRecord::Metadata(Box::new(metadata.clone())))?;
+            info!("Spooled metadata of the session to {}.", spool.path.display());
+            Ok(metadata.ids)
+        }
+    }
+}
+
+/* Records that can not be sent are spooled rather than lost: they are sent later on with
+ * `carenage-cli upload`. */
+async fn send_or_spool(
+    records: &[SpoolRecord],
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let spool = Spool::for_process(&config.spool_dir);
+
+    /* Records following spooled ones are spooled after them, so that they are uploaded once the
+     * metadata they refer to is registered. */
+    let sending_attempt = match (spool.path.exists(), &config.transport) {
+        (true, _) => Err(format!("records are pending in {}", spool.path.display()).into()),
+        (false, Transport::Database) => match get_db_connection_pool(&config.database_url).await {
+            Ok(db_pool) => db_pool.send(records).await,
+            Err(err) => Err(err.into()),
+        },
+        (false, Transport::Api { api_url, api_token }) => {
+            IngestionClient::new(api_url, api_token).send(records).await
+        }
+    };
+
+    if let Err(err) = sending_attempt {
+        warn!("Failed to send {} records: {}", records.len(), err);
+        for record in records {
+            spool.append(record)?;
+        }
+        info!("Spooled {} records to {}.", records.len(), spool.path.display());
+    }
+    Ok(())
 }
 
 pub async fn insert_event(event: &Event, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let db_pool = get_db_connection_pool(&config.database_url)
-        .await?
-        .acquire();
+    let record = SpoolRecord::Event {
+        event: event.clone(),
+    };
 
-    Event::insert(event, db_pool.await?).await?;
-    Ok(info!("Inserted event data into database."))
+    send_or_spool(&[record], config).await?;
+    info!("Inserted event data into database.");
+    Ok(())
 }
 
 pub async fn query_and_insert_event(
-    mut ids: Ids,
-    start_time: Timestamp,
+    ids: Ids,
+    window: &mut SamplingWindow,
     unix_flag: UnixFlag,
     fetch_hardware: HardwareData,
     event_type: EventType,
+    attribution: &mut Attribution,
     config: &Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let start_time = window.previous_end;
     let end_time = Timestamp::new(unix_flag);
     let response = query_boagent(
         &config.boagent_url,
@@ -139,7 +273,8 @@ pub async fn query_and_insert_event(
         &config.location,
         config.lifetime,
     )
-    .await?;
+    .await
+    .inspect_err(|err| window.last_boagent_error = Some(err.to_string()))?;
     let deserialized_boagent_response = deserialize_boagent_json(response).await?;
 
     let processes_collection_attempt = collect_processes(&deserialized_boagent_response);
@@ -153,33 +288,21 @@ pub async fn query_and_insert_event(
 
     match processes_collection_attempt {
         Ok(Some(processes)) => {
-            for process in processes {
-                let db_pool = get_db_connection_pool(&config.database_url).await?;
-                let process_metadata_already_registered = check_process_existence_for_id(
-                    db_pool.acquire().await?,
-                    &process,
-                    "run",
-                    ids.run_id,
-                )
-                .await?;
+            let (attributed_processes, other_processes) = attribution.split_processes(processes);
+            let mut samples = vec![];
+            let mut latest_metrics = BTreeMap::new();
+            let mut tracked_processes = BTreeMap::new();
+            let interval_duration_s = end_time.seconds_since(start_time);
+            let sampled_at = Local::now();
+            /* The window is only updated once the samples are sent or spooled: a failing tick is
+             * queried again on the next one, and its totals are not counted twice. */
+            let mut cumulative_totals = window.cumulative_totals;
+            cumulative_totals
+                .add(OperationalTotals::from_boagent_response(&deserialized_boagent_response));
+            let mut energy_wh = 0.0;
 
-                let process_id = match process_metadata_already_registered {
-                    true => {
-                        get_process_id(db_pool.acquire().await?, &process, "run", ids.run_id)
-                            .await?
-                    }
-                    false => {
-                        let process_row =
-                            Process::insert(&process, db_pool.acquire().await?).await?;
-                        Process::get_id(process_row)
-                    }
-                };
-
-                ids.process_id = process_id;
-
-                let event = EventBuilder::new(ids, event_type).build();
-                let event_row = Event::insert(&event, db_pool.acquire().await?).await?;
-                let event_id = Event::get_id(event_row);
+            for process in attributed_processes {
+                tracked_processes.insert(process.pid, process.exe.clone());
 
                 let process_response = process_embedded_impacts(
                     &config.boagent_url,
@@ -194,17 +317,273 @@ pub async fn query_and_insert_event(
 
                 let process_data = deserialize_boagent_json(process_response).await?;
 
-                Metrics::build(&process_data, &deserialized_boagent_response)
-                    .insert(event_id, db_pool.acquire().await?)
-                    .await?;
-                info!("Inserted all metrics for query.");
+                let metrics = Metrics::build(&process_data, &deserialized_boagent_response)
+                    .with_interval(interval_duration_s)
+                    .with_cumulative_totals(cumulative_totals);
+                energy_wh += metrics.energy_consumed_wh;
+                latest_metrics.insert(
+                    process.pid,
+                    LatestMetrics {
+                        exe: process.exe.clone(),
+                        metrics: metrics.clone(),
+                    },
+                );
+
+                samples.push(SpoolRecord::Sample(Box::new(Sample {
+                    event: EventBuilder::new(ids, event_type)
+                        .timestamp(sampled_at)
+                        .build(),
+                    process,
+                    metrics,
+                })));
             }
+
+            if attribution.has_other_bucket() && !other_processes.is_empty() {
+                let other_pids: Vec<i32> =
+                    other_processes.iter().map(|process| process.pid).collect();
+                let other_process = ProcessBuilder::new(
+                    0,
+                    OTHER_PROCESSES_EXE,
+                    "processes outside of the CI job",
+                    "aggregated",
+                )
+                .build();
+
+                /* Their energy is not the one of the CI job: it is left out of the running total. */
+                let metrics = Metrics::build_aggregate(&other_pids, &deserialized_boagent_response)
+                    .with_interval(interval_duration_s)
+                    .with_cumulative_totals(cumulative_totals);
+                latest_metrics.insert(
+                    other_process.pid,
+                    LatestMetrics {
+                        exe: other_process.exe.clone(),
+                        metrics: metrics.clone(),
+                    },
+                );
+
+                samples.push(SpoolRecord::Sample(Box::new(Sample {
+                    event: EventBuilder::new(ids, event_type)
+                        .timestamp(sampled_at)
+                        .build(),
+                    process: other_process,
+                    metrics,
+                })));
+                info!(
+                    "Aggregated metrics for {} processes outside of the CI job.",
+                    other_pids.len()
+                );
+            }
+
+            send_or_spool(&samples, config).await?;
+            info!("Inserted all metrics for query.");
+            #[cfg(feature = "otlp")]
+            if let Some(exporter) = &window.exporter {
+                exporter.spawn_export(&samples);
+            }
+            /* Processes gone since the previous sample are no longer tracked nor exposed. */
+            window.tracked_processes = tracked_processes;
+            window.latest_metrics = latest_metrics;
+            window.cumulative_totals = cumulative_totals;
+            window.energy_wh += energy_wh;
+            window.previous_end = end_time;
+            window.sample_count += 1;
         }
         Ok(None) => info!("No processes data received yet from Scaphandre, carrying on!"),
-        Err(_) => warn!(
-            "Some error occured while recovering data from Scaphandre, some data might be missing!"
-        ),
+        Err(err) => {
+            warn!(
+                "Some error occured while recovering data from Scaphandre, some data might be missing!"
+            );
+            window.last_boagent_error = Some(err.to_string());
+        }
+    }
+
+    info!("Boagent query and metrics insertion attempt over.");
+    Ok(())
+}
+
+pub async fn stop_and_insert_event(
+    ids: Ids,
+    window: &mut SamplingWindow,
+    unix_flag: UnixFlag,
+    attribution: &mut Attribution,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    /* A failing last query to Boagent should not prevent the run from being closed: the Stop
+     * event and stop dates are what allows to compute the duration of the run. */
+    if let Err(err) = query_and_insert_event(
+        ids,
+        window,
+        unix_flag,
+        HardwareData::Ignore,
+        EventType::Regular,
+        attribution,
+        config,
+    )
+    .await
+    {
+        warn!("Last Boagent query failed, data for the final interval might be missing: {}", err);
     }
 
-    Ok(info!("Boagent query and metrics insertion attempt over."))
+    let stop_record = SpoolRecord::Stop {
+        event: EventBuilder::new(ids, EventType::Stop).build(),
+    };
+    send_or_spool(&[stop_record], config).await?;
+
+    info!("Inserted stop event and closed all metadata rows.");
+    Ok(())
+}
+
+/* A custom event marks a phase of the CI job: a sample is taken right away, so that the metrics
+ * inserted before the custom event cover the time until it was received. */
+pub async fn sample_and_insert_custom_event(
+    ids: Ids,
+    window: &mut SamplingWindow,
+    unix_flag: UnixFlag,
+    attribution: &mut Attribution,
+    user_label: &str,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(err) = query_and_insert_event(
+        ids,
+        window,
+        unix_flag,
+        HardwareData::Ignore,
+        EventType::Regular,
+        attribution,
+        config,
+    )
+    .await
+    {
+        warn!("Boagent query failed, data before the custom event might be missing: {}", err);
+    }
+
+    let custom_event = EventBuilder::new(ids, EventType::Custom)
+        .user_label(user_label)
+        .build();
+    insert_event(&custom_event, config).await?;
+
+    info!("Inserted custom event labeled \"{}\".", user_label);
+    Ok(())
+}
+
+/* State of the run measured by carenaged, commanded through its control socket. */
+pub struct Session {
+    pub session_id: String,
+    pub ids: Ids,
+    pub window: SamplingWindow,
+    pub attribution: Attribution,
+    pub unix_flag: UnixFlag,
+    pub paused: bool,
+}
+
+impl Session {
+    pub fn new(args: &DaemonArgs, ids: Ids) -> Self {
+        Session {
+            session_id: args.session_id.clone(),
+            ids,
+            window: SamplingWindow::new(args.start_timestamp),
+            attribution: Attribution::new(args.attribution_mode, args.root_pid, args.other_bucket),
+            unix_flag: args.unix_flag,
+            paused: false,
+        }
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        SessionStatus {
+            session_id: self.session_id.clone(),
+            project_id: self.ids.project_id,
+            pipeline_id: self.ids.pipeline_id,
+            run_id: self.ids.run_id,
+            paused: self.paused,
+            sample_count: self.window.sample_count,
+            last_boagent_error: self.window.last_boagent_error.clone(),
+            tracked_processes: self
+                .window
+                .tracked_processes
+                .iter()
+                .map(|(pid, exe)| TrackedProcess {
+                    pid: *pid,
+                    exe: exe.clone(),
+                })
+                .collect(),
+            energy_wh: self.window.energy_wh,
+            emissions_gco2eq: self.window.cumulative_totals.emission_kgc02eq * 1000.0,
+        }
+    }
+
+    pub fn exposition(&self, project_name: &str) -> String {
+        let labels = ExpositionLabels {
+            project: project_name.to_owned(),
+            pipeline: self.ids.pipeline_id.to_string(),
+            job: self.ids.job_id.to_string(),
+        };
+        render_openmetrics(&labels, &self.window.latest_metrics)
+    }
+
+    pub async fn sample(&mut self, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        query_and_insert_event(
+            self.ids,
+            &mut self.window,
+            self.unix_flag,
+            HardwareData::Ignore,
+            EventType::Regular,
+            &mut self.attribution,
+            config,
+        )
+        .await
+    }
+
+    /* Stop requests are handled by the caller, which ends the run. A paused session takes no
+     * sample: the time window of the first sample after resuming starts when resuming. */
+    pub async fn handle(&mut self, request: ControlRequest, config: &Config) -> ControlResponse {
+        let handling_attempt = match request {
+            ControlRequest::Status => return ControlResponse::Status(self.status()),
+            ControlRequest::Flush => self.sample(config).await,
+            ControlRequest::Event { label } => {
+                sample_and_insert_custom_event(
+                    self.ids,
+                    &mut self.window,
+                    self.unix_flag,
+                    &mut self.attribution,
+                    &label,
+                    config,
+                )
+                .await
+            }
+            ControlRequest::Pause => {
+                let pausing_sample = self.sample(config).await;
+                self.paused = true;
+                pausing_sample
+            }
+            ControlRequest::Resume => {
+                self.window.previous_end = Timestamp::new(self.unix_flag);
+                self.paused = false;
+                Ok(())
+            }
+            ControlRequest::Stop => Err("Stop requests end the session.".into()),
+        };
+
+        match handling_attempt {
+            Ok(()) => ControlResponse::Done,
+            Err(err) => ControlResponse::Error {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+pub async fn read_request(stream: &mut UnixStream) -> Result<ControlRequest, Box<dyn std::error::Error>> {
+    let mut request_line = String::new();
+    timeout(CONTROL_TIMEOUT, BufReader::new(stream).read_line(&mut request_line)).await??;
+    Ok(serde_json::from_str(&request_line)?)
+}
+
+pub async fn write_response(
+    stream: &mut UnixStream,
+    response: &ControlResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut response_line = serde_json::to_string(response)?;
+    response_line.push('\n');
+    timeout(CONTROL_TIMEOUT, stream.write_all(response_line.as_bytes())).await??;
+    Ok(())
 }
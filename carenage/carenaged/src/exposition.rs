@@ -0,0 +1,111 @@
+use crate::carenaged::LatestMetrics;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+pub const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+const METRIC_PREFIX: &str = "carenage_";
+
+/* A scraper is given little time to send its request and read the response, as sampling waits
+ * for it meanwhile. */
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* Labels shared by every sample of the run; pid and exe are added per process. */
+pub struct ExpositionLabels {
+    pub project: String,
+    pub pipeline: String,
+    pub job: String,
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/* Every field of `Metrics` is exposed as a gauge, e.g. `carenage_process_power_measured_w`. */
+pub fn render_openmetrics(
+    labels: &ExpositionLabels,
+    latest_metrics: &BTreeMap<i32, LatestMetrics>,
+) -> String {
+    let mut families: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (pid, latest) in latest_metrics {
+        let process_labels = format!(
+            "project=\"{}\",pipeline=\"{}\",job=\"{}\",pid=\"{}\",exe=\"{}\"",
+            escape_label_value(&labels.project),
+            escape_label_value(&labels.pipeline),
+            escape_label_value(&labels.job),
+            pid,
+            escape_label_value(&latest.exe)
+        );
+        let metrics_value =
+            serde_json::to_value(&latest.metrics).expect("Metrics should be serializable.");
+        let Some(metrics) = metrics_value.as_object() else {
+            continue;
+        };
+        for (metric, value) in metrics {
+            let Some(value) = value.as_f64() else {
+                continue;
+            };
+            families
+                .entry(format!("{}{}", METRIC_PREFIX, metric))
+                .or_default()
+                .push(format!("{{{}}} {}", process_labels, value));
+        }
+    }
+
+    let mut exposition = String::new();
+    for (family, samples) in families {
+        exposition.push_str(&format!("# TYPE {} gauge\n", family));
+        for sample in samples {
+            exposition.push_str(&format!("{}{}\n", family, sample));
+        }
+    }
+    exposition.push_str("# EOF\n");
+    exposition
+}
+
+pub async fn accept_scrape(
+    listener: &Option<TcpListener>,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/* Only the request line is read: the exposition is served on /metrics, whatever the headers. */
+pub async fn serve_scrape(
+    mut stream: TcpStream,
+    exposition: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut request_line = String::new();
+    timeout(
+        SCRAPE_TIMEOUT,
+        BufReader::new(&mut stream).read_line(&mut request_line),
+    )
+    .await??;
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, content_type, body) = match path {
+        "/metrics" => ("200 OK", OPENMETRICS_CONTENT_TYPE, exposition),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "Not found.\n"),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    timeout(SCRAPE_TIMEOUT, async {
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    })
+    .await??;
+    Ok(())
+}
@@ -1 +1,4 @@
 pub mod carenaged;
+pub mod exposition;
+#[cfg(feature = "otlp")]
+pub mod otlp;
@@ -1,14 +1,18 @@
-use crate::carenaged::{insert_metadata, insert_event, query_and_insert_event};
-use carenaged::DaemonArgs;
-use database::boagent::{Config, HardwareData};
-use database::ci::GitlabVariables;
+use carenaged::carenaged::{
+    insert_event, insert_metadata, read_request, stop_and_insert_event, write_response,
+    DaemonArgs, Session,
+};
+use carenaged::exposition::{accept_scrape, serve_scrape};
+#[cfg(feature = "otlp")]
+use carenaged::otlp::{resource_attributes, OtlpExporter};
+use database::boagent::Config;
+use database::control::{socket_path, ControlRequest, ControlResponse};
 use database::event::{EventBuilder, EventType};
-use log::{error, info};
+use log::{info, warn};
 use std::process;
+use tokio::net::{TcpListener, UnixListener};
 use tokio::signal::unix::{signal, SignalKind};
-use tokio::time::{self, Duration};
-
-pub mod carenaged;
+use tokio::time::{self, Duration, MissedTickBehavior};
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -18,45 +22,133 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut sigterm = signal(SignalKind::terminate())?;
     let args = DaemonArgs::parse_args()?;
 
+    info!("Session is {}.", args.session_id);
     info!("Time step is : {} seconds.", args.time_step);
     info!("Start timestamp is {}.", args.start_timestamp);
     info!("{}", args.unix_flag);
+    info!("Attribution mode is {}.", args.attribution_mode);
+
+    /* The socket is bound before registering metadata: requests sent in the meantime are queued
+     * until the session is ready. A socket left by a crashed daemon is replaced. */
+    let control_socket_path = socket_path(&args.session_id);
+    let _ = std::fs::remove_file(&control_socket_path);
+    let listener = UnixListener::bind(&control_socket_path)?;
 
-    let gitlab_vars = GitlabVariables::parse_env_variables().expect("Gitlab variables are not available.");
     let project_root_path = std::env::current_dir().unwrap().join("..");
     let config = Config::check_configuration(&project_root_path)
         .expect("Configuration fields should be parsable.");
+    config
+        .ci_platform
+        .parse_env_variables()
+        .expect("CI variables are not available.");
+
+    let metrics_listener = match config.metrics_addr {
+        Some(metrics_addr) => {
+            info!("Serving metrics to Prometheus on {}.", metrics_addr);
+            Some(TcpListener::bind(metrics_addr).await?)
+        }
+        None => None,
+    };
+    if let Some(otlp_endpoint) = &config.otlp_endpoint {
+        match cfg!(feature = "otlp") {
+            true => info!("Exporting samples to the OTLP collector at {}.", otlp_endpoint),
+            false => warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set, but carenaged was built without the otlp feature."),
+        }
+    }
+    /* The resource of the exported samples is the same for the whole run. */
+    #[cfg(feature = "otlp")]
+    let exporter = match &config.otlp_endpoint {
+        Some(otlp_endpoint) => {
+            let ci_metadata = config.ci_platform.parse_env_variables()?;
+            Some(OtlpExporter::new(
+                otlp_endpoint,
+                resource_attributes(&ci_metadata, &config),
+            )?)
+        }
+        None => None,
+    };
 
-    let project_ids = insert_metadata(gitlab_vars, args.start_timestamp, args.unix_flag, &config).await?;
+    let project_ids = insert_metadata(args.start_timestamp, args.unix_flag, &config).await?;
 
     let start_event = EventBuilder::new(project_ids, EventType::Start).build();
     insert_event(&start_event, &config).await?;
 
-    let _query_insert_loop = tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(args.time_step));
-        loop {
-            let _ = query_and_insert_event(
-                project_ids,
-                args.start_timestamp,
-                args.unix_flag,
-                HardwareData::Ignore,
-                EventType::Regular,
-                &config
-            )
-            .await;
-            interval.tick().await;
+    /* Queries are awaited inside the loop rather than in a spawned task: when a stop is requested,
+     * an ongoing insertion is completed before the final one, so that Stop is the last event. */
+    let mut session = Session::new(&args, project_ids);
+    #[cfg(feature = "otlp")]
+    {
+        session.window.exporter = exporter;
+    }
+    let mut interval = time::interval(Duration::from_secs(args.time_step));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let stop_requester = loop {
+        tokio::select! {
+            _ = interval.tick(), if !session.paused => {
+                if let Err(err) = session.sample(&config).await {
+                    warn!("Failed to insert sample: {}", err);
+                }
+            }
+            connection = listener.accept() => {
+                let Ok((mut stream, _)) = connection else {
+                    continue;
+                };
+                let response = match read_request(&mut stream).await {
+                    Ok(ControlRequest::Stop) => {
+                        info!("Received stop request.");
+                        break Some(stream);
+                    }
+                    Ok(request) => session.handle(request, &config).await,
+                    Err(err) => ControlResponse::Error {
+                        message: format!("Invalid request: {}", err),
+                    },
+                };
+                if let Err(err) = write_response(&mut stream, &response).await {
+                    warn!("Failed to respond to request: {}", err);
+                }
+            }
+            connection = accept_scrape(&metrics_listener) => {
+                let Ok((stream, _)) = connection else {
+                    continue;
+                };
+                /* Scrapes are served by tasks of their own, so that a slow scraper does not hold up
+                 * sampling nor requests: the exposition is the one of the moment it was accepted. */
+                let exposition = session.exposition(&config.project_name);
+                tokio::spawn(async move {
+                    if let Err(err) = serve_scrape(stream, &exposition).await {
+                        warn!("Failed to serve metrics: {}", err);
+                    }
+                });
+            }
+            _ = sigterm.recv() => {
+                info!("Received SIGTERM signal.");
+                break None;
+            }
         }
-    });
+    };
 
-    match sigterm.recv().await {
-        Some(()) => {
-            info!("Received SIGTERM signal.");
-            info!("Stopped carenage daemon.");
-            process::exit(0x0100);
-        }
-        _ => {
-            error!("Unable to listen to SIGTERM signal.")
+    let stop_attempt = stop_and_insert_event(
+        project_ids,
+        &mut session.window,
+        args.unix_flag,
+        &mut session.attribution,
+        &config,
+    )
+    .await;
+    let _ = std::fs::remove_file(&control_socket_path);
+
+    if let Some(mut stream) = stop_requester {
+        let response = match &stop_attempt {
+            Ok(()) => ControlResponse::Status(session.status()),
+            Err(err) => ControlResponse::Error {
+                message: err.to_string(),
+            },
+        };
+        if let Err(err) = write_response(&mut stream, &response).await {
+            warn!("Failed to respond to stop request: {}", err);
         }
     }
+    stop_attempt?;
+    info!("Stopped carenage daemon.");
     Ok(())
 }
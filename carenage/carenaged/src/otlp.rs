@@ -0,0 +1,175 @@
+use database::boagent::Config;
+use database::ci::CiMetadata;
+use database::metrics::metric_unit;
+use database::database::Ids;
+use database::spool::{RecordSink, SessionMetadata, SpoolRecord};
+use log::warn;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const METRIC_PREFIX: &str = "carenage.";
+
+const SERVICE_NAME: &str = "carenage";
+
+/* Resource attributes follow the semantic conventions of OpenTelemetry for CI/CD and VCS where
+ * they exist, the dimensions specific to carenage being prefixed with `carenage.`. */
+pub fn resource_attributes(ci_metadata: &CiMetadata, config: &Config) -> Vec<(String, String)> {
+    let attributes = vec![
+        ("service.name", Some(SERVICE_NAME.to_string())),
+        ("host.name", Some(config.device_name.clone())),
+        ("carenage.project.name", Some(config.project_name.clone())),
+        ("carenage.project.path", Some(ci_metadata.project_path.clone())),
+        ("carenage.workflow.name", Some(ci_metadata.workflow_name.clone())),
+        ("cicd.pipeline.name", Some(ci_metadata.pipeline_name.clone())),
+        ("cicd.pipeline.run.id", Some(ci_metadata.pipeline_id.to_string())),
+        ("cicd.pipeline.task.name", Some(ci_metadata.job_name.clone())),
+        ("carenage.task.name", Some(ci_metadata.task_name.clone())),
+        ("cicd.pipeline.run.url.full", ci_metadata.vcs.pipeline_url.clone()),
+        ("vcs.ref.head.name", ci_metadata.vcs.ref_name.clone()),
+        ("vcs.ref.head.revision", ci_metadata.vcs.commit_sha.clone()),
+    ];
+    attributes
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+        .collect()
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/* A collector slower than this to receive an export is given up on. */
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/* Samples are pushed with OTLP/HTTP in its JSON encoding, each field of `Metrics` being a gauge
+ * with a data point per process, e.g. `carenage.process_power_measured_w`. */
+#[derive(Clone)]
+pub struct OtlpExporter {
+    metrics_url: String,
+    resource_attributes: Vec<(String, String)>,
+    client: Client,
+}
+
+impl OtlpExporter {
+    pub fn new(
+        endpoint: &str,
+        resource_attributes: Vec<(String, String)>,
+    ) -> Result<Self, reqwest::Error> {
+        Ok(OtlpExporter {
+            metrics_url: format!("{}/v1/metrics", endpoint.trim_end_matches('/')),
+            resource_attributes,
+            client: Client::builder().timeout(EXPORT_TIMEOUT).build()?,
+        })
+    }
+
+    async fn post(&self, export_request: &Value) -> Result<(), reqwest::Error> {
+        self.client
+            .post(&self.metrics_url)
+            .json(export_request)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /* The collector is a copy of what is sent for observability: exports run in a task of their
+     * own so that sampling does not wait for the collector, and failed ones are not spooled, the
+     * database or the API remaining the reference. */
+    pub fn spawn_export(&self, records: &[SpoolRecord]) {
+        let Some(export_request) = self.export_request(records) else {
+            return;
+        };
+        let exporter = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = exporter.post(&export_request).await {
+                warn!("Failed to export samples to the OTLP collector: {}", err);
+            }
+        });
+    }
+
+    /* Only samples carry metrics: None is returned when there is nothing to push. */
+    pub fn export_request(&self, records: &[SpoolRecord]) -> Option<Value> {
+        let mut data_points: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for record in records {
+            let SpoolRecord::Sample(sample) = record else {
+                continue;
+            };
+            let time_unix_nano = sample
+                .event
+                .timestamp
+                .timestamp_nanos_opt()
+                .unwrap_or_default()
+                .to_string();
+            let attributes = json!([
+                { "key": "process.pid", "value": { "intValue": sample.process.pid.to_string() } },
+                string_attribute("process.executable.path", &sample.process.exe),
+                string_attribute("process.command_line", &sample.process.cmdline),
+                string_attribute("carenage.run.id", &sample.event.run_id.to_string()),
+            ]);
+            let metrics_value =
+                serde_json::to_value(&sample.metrics).expect("Metrics should be serializable.");
+            let Some(metrics) = metrics_value.as_object() else {
+                continue;
+            };
+            for (metric, value) in metrics {
+                let Some(value) = value.as_f64() else {
+                    continue;
+                };
+                data_points.entry(metric.clone()).or_default().push(json!({
+                    "timeUnixNano": time_unix_nano,
+                    "asDouble": value,
+                    "attributes": attributes,
+                }));
+            }
+        }
+        if data_points.is_empty() {
+            return None;
+        }
+
+        let metrics: Vec<Value> = data_points
+            .into_iter()
+            .map(|(metric, data_points)| {
+                json!({
+                    "name": format!("{}{}", METRIC_PREFIX, metric),
+                    "unit": metric_unit(&metric).unwrap_or_default(),
+                    "gauge": { "dataPoints": data_points },
+                })
+            })
+            .collect();
+        let attributes: Vec<Value> = self
+            .resource_attributes
+            .iter()
+            .map(|(key, value)| string_attribute(key, value))
+            .collect();
+
+        Some(json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": attributes },
+                "scopeMetrics": [{
+                    "scope": { "name": "carenaged", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": metrics,
+                }],
+            }],
+        }))
+    }
+}
+
+impl RecordSink for OtlpExporter {
+    async fn send(&self, records: &[SpoolRecord]) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(export_request) = self.export_request(records) else {
+            return Ok(());
+        };
+        self.post(&export_request).await?;
+        Ok(())
+    }
+
+    /* The collector only receives samples, labelled with the IDs carenaged has. */
+    async fn register(
+        &self,
+        metadata: &SessionMetadata,
+    ) -> Result<Ids, Box<dyn std::error::Error>> {
+        Ok(metadata.ids)
+    }
+}
@@ -1,12 +1,23 @@
-use carenaged::carenaged::{insert_event, insert_metadata, query_and_insert_event};
-use chrono::Local;
-use database::boagent::{Config, HardwareData};
-use database::ci::GitlabVariables;
+use carenaged::carenaged::{
+    insert_event, insert_metadata, query_and_insert_event, read_request,
+    sample_and_insert_custom_event, stop_and_insert_event, LatestMetrics, SamplingWindow, Session,
+};
+use carenaged::exposition::{accept_scrape, serve_scrape, OPENMETRICS_CONTENT_TYPE};
+use chrono::{DateTime, Local};
+use database::attribution::Attribution;
+use database::boagent::{Config, HardwareData, Transport};
+use database::ci::CiPlatform;
+use database::control::{ControlRequest, ControlResponse};
+use database::database::{get_db_connection_pool, Ids};
 use database::event::{EventBuilder, EventType};
+use database::metrics::{Metrics, OperationalTotals};
 use database::timestamp::{Timestamp, UnixFlag};
 use mockito::{Matcher, Server};
+use sqlx::Row;
 use std::env;
 use std::fs::canonicalize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
 mod common;
 
 #[tokio::test]
@@ -36,9 +47,8 @@ async fn it_inserts_project_metadata_when_needed_gitlab_variables_are_available(
     let project_root_path = std::env::current_dir().unwrap().join("..");
     let config = Config::check_configuration(&project_root_path)
         .expect("Configuration fields should be parsable.");
-    let gitlab_vars = GitlabVariables::parse_env_variables().unwrap();
 
-    let insert_result = insert_metadata(gitlab_vars, now, UnixFlag::Unset, &config).await;
+    let insert_result = insert_metadata(now, UnixFlag::Unset, &config).await;
 
     assert!(insert_result.is_ok())
 }
@@ -49,10 +59,10 @@ async fn it_fails_when_needed_gitlab_variables_are_not_available() {
     let project_root_path = std::env::current_dir().unwrap().join("..");
     let config = Config::check_configuration(&project_root_path)
         .expect("Configuration fields should be parsable.");
-    let gitlab_vars = GitlabVariables::parse_env_variables().unwrap();
+    CiPlatform::Gitlab.parse_env_variables().unwrap();
     let now = Timestamp::ISO8601(Some(Local::now()));
 
-    let _insert_result = insert_metadata(gitlab_vars, now, UnixFlag::Unset, &config).await;
+    let _insert_result = insert_metadata(now, UnixFlag::Unset, &config).await;
 }
 #[tokio::test]
 async fn it_returns_all_uuids_of_metadata_tables_to_be_used_by_events_table_as_primary_keys() {
@@ -80,9 +90,8 @@ async fn it_returns_all_uuids_of_metadata_tables_to_be_used_by_events_table_as_p
     let project_root_path = std::env::current_dir().unwrap().join("..");
     let config = Config::check_configuration(&project_root_path)
         .expect("Configuration fields should be parsable.");
-    let gitlab_vars = GitlabVariables::parse_env_variables().unwrap();
 
-    let insert_result = insert_metadata(gitlab_vars, now, UnixFlag::Unset, &config).await;
+    let insert_result = insert_metadata(now, UnixFlag::Unset, &config).await;
 
     assert!(insert_result.is_ok())
 }
@@ -91,7 +100,6 @@ async fn it_returns_all_uuids_of_metadata_tables_to_be_used_by_events_table_as_p
 async fn it_inserts_start_event_to_events_table() {
     common::setup();
     let now = Timestamp::new(UnixFlag::Unset);
-    let gitlab_vars = GitlabVariables::parse_env_variables().unwrap();
 
     let mut boagent_server = Server::new_async().await;
     let url = boagent_server.url();
@@ -115,7 +123,7 @@ async fn it_inserts_start_event_to_events_table() {
     let project_root_path = std::env::current_dir().unwrap().join("..");
     let config = Config::check_configuration(&project_root_path)
         .expect("Configuration fields should be parsable.");
-    let project_ids = insert_metadata(gitlab_vars, now, UnixFlag::Unset, &config)
+    let project_ids = insert_metadata(now, UnixFlag::Unset, &config)
         .await
         .unwrap();
     let start_event = EventBuilder::new(project_ids, EventType::Start).build();
@@ -127,15 +135,14 @@ async fn it_inserts_start_event_to_events_table() {
 async fn it_inserts_all_events_and_metrics_for_processes() {
     common::setup();
     let now = Timestamp::new(UnixFlag::Unset);
-    let gitlab_vars = GitlabVariables::parse_env_variables().unwrap();
-    let project_root_path = std::env::current_dir().unwrap().join("..");
-    let config = Config::check_configuration(&project_root_path)
-        .expect("Configuration fields should be parsable.");
 
     let mut boagent_server = Server::new_async().await;
     let url = boagent_server.url();
     let mock_boagent_path = canonicalize("../mocks/query_boagent_response_before_process_embedded_impacts.json").unwrap();
     env::set_var("BOAGENT_URL", url);
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
 
     let _mock_boagent_query_with_hardware = boagent_server
         .mock("GET", "/query")
@@ -184,20 +191,572 @@ async fn it_inserts_all_events_and_metrics_for_processes() {
         .create_async()
         .await;
 
-    let project_ids = insert_metadata(gitlab_vars, now, UnixFlag::Unset, &config)
+    let project_ids = insert_metadata(now, UnixFlag::Unset, &config)
         .await
         .unwrap();
     let start_event = EventBuilder::new(project_ids, EventType::Start).build();
     let _ = insert_event(&start_event, &config).await;
 
+    let mut window = SamplingWindow::new(now);
+    window.tracked_processes.insert(-1, "/exited/process".to_string());
     let query_and_insert = query_and_insert_event(
         project_ids,
-        now,
+        &mut window,
         UnixFlag::Unset,
         HardwareData::Ignore,
         EventType::Regular,
+        &mut Attribution::AllProcesses,
         &config
     )
     .await;
     assert!(query_and_insert.is_ok());
+    assert_ne!(window.previous_end, now);
+    assert!(window.cumulative_totals.emission_kgc02eq > 0.0);
+    assert_eq!(window.sample_count, 1);
+    assert_eq!(window.tracked_processes.len(), 10);
+    assert!(!window.tracked_processes.contains_key(&-1));
+    assert!(window.last_boagent_error.is_none());
+
+    let db_pool = get_db_connection_pool(&config.database_url).await.unwrap();
+    let tick_timestamps: i64 = sqlx::query_scalar(
+        "SELECT COUNT(DISTINCT timestamp) FROM events WHERE run_id = $1 AND event_type = 'regular'",
+    )
+    .bind(project_ids.run_id)
+    .fetch_one(&db_pool)
+    .await
+    .unwrap();
+    assert_eq!(tick_timestamps, 1);
+}
+
+#[tokio::test]
+async fn it_counts_the_totals_of_a_failed_tick_once_it_succeeds() {
+    let now = Timestamp::new(UnixFlag::Unset);
+    let mut boagent_server = Server::new_async().await;
+    let mut api_server = Server::new_async().await;
+    let mock_boagent_path = canonicalize("../mocks/query_boagent_response_before_process_embedded_impacts.json").unwrap();
+    let _mock_boagent_query = boagent_server
+        .mock("GET", "/query")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_body_from_file(&mock_boagent_path)
+        .create_async()
+        .await;
+    let _mock_boagent_process_embedded_impacts = boagent_server
+        .mock("GET", "/process_embedded_impacts")
+        .match_query(Matcher::Any)
+        .with_status(200)
+        .with_body_from_file(canonicalize("../mocks/process6042.json").unwrap())
+        .create_async()
+        .await;
+    let _mock_failing_ingestion = api_server
+        .mock("POST", "/ingest/metrics")
+        .with_status(503)
+        .create_async()
+        .await;
+    let spool_dir = std::env::temp_dir().join(format!("carenage_tick_spool_{}", Uuid::new_v4()));
+    std::fs::write(&spool_dir, "").unwrap();
+    let mut config = Config {
+        boagent_url: boagent_server.url(),
+        database_url: String::new(),
+        location: "FRA".to_string(),
+        lifetime: 5,
+        device_name: "unknown".to_string(),
+        project_name: "hubblo/carenage".to_string(),
+        run_label: None,
+        ci_platform: CiPlatform::Local,
+        spool_dir: spool_dir.clone(),
+        transport: Transport::Api {
+            api_url: api_server.url(),
+            api_token: "secret".to_string(),
+        },
+        metrics_addr: None,
+        otlp_endpoint: None,
+    };
+    let ids = Ids {
+        project_id: Uuid::nil(),
+        workflow_id: Uuid::nil(),
+        pipeline_id: Uuid::nil(),
+        job_id: Uuid::nil(),
+        run_id: Uuid::nil(),
+        task_id: Uuid::nil(),
+        process_id: Uuid::nil(),
+        device_id: Uuid::nil(),
+    };
+    let mut window = SamplingWindow::new(now);
+
+    /* Samples can neither be sent nor spooled, the spool directory being a file. */
+    let failed_tick = query_and_insert_event(
+        ids,
+        &mut window,
+        UnixFlag::Unset,
+        HardwareData::Ignore,
+        EventType::Regular,
+        &mut Attribution::AllProcesses,
+        &config,
+    )
+    .await;
+    std::fs::remove_file(&spool_dir).unwrap();
+    assert!(failed_tick.is_err());
+    assert_eq!(window.cumulative_totals, OperationalTotals::default());
+    assert_eq!(window.energy_wh, 0.0);
+    assert_eq!(window.sample_count, 0);
+    assert_eq!(window.previous_end, now);
+
+    config.spool_dir = std::env::temp_dir().join(format!("carenage_tick_spool_{}", Uuid::new_v4()));
+    let retried_tick = query_and_insert_event(
+        ids,
+        &mut window,
+        UnixFlag::Unset,
+        HardwareData::Ignore,
+        EventType::Regular,
+        &mut Attribution::AllProcesses,
+        &config,
+    )
+    .await;
+    std::fs::remove_dir_all(&config.spool_dir).unwrap();
+    assert!(retried_tick.is_ok());
+    let boagent_response: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&mock_boagent_path).unwrap()).unwrap();
+    assert_eq!(
+        window.cumulative_totals,
+        OperationalTotals::from_boagent_response(&boagent_response)
+    );
+    assert_eq!(window.sample_count, 1);
+}
+
+#[tokio::test]
+async fn it_inserts_stop_event_and_updates_stop_dates_of_all_metadata_rows() {
+    common::setup();
+    let now = Timestamp::new(UnixFlag::Unset);
+
+    let mut boagent_server = Server::new_async().await;
+    let url = boagent_server.url();
+    let mock_boagent_path =
+        canonicalize("../mocks/query_boagent_response_before_process_embedded_impacts.json")
+            .unwrap();
+    env::set_var("BOAGENT_URL", url);
+    let _mock_boagent_query = boagent_server
+        .mock("GET", "/query")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("start_time".to_string(), now.to_string()),
+            Matcher::UrlEncoded("verbose".to_string(), "true".to_string()),
+            Matcher::UrlEncoded("location".to_string(), "FRA".to_string()),
+            Matcher::UrlEncoded("measure_power".to_string(), "true".to_string()),
+            Matcher::UrlEncoded("lifetime".to_string(), "5".to_string()),
+        ]))
+        .with_status(200)
+        .with_body_from_file(mock_boagent_path)
+        .create_async()
+        .await;
+    let mock_process_impacts_path = canonicalize("../mocks/process6042.json").unwrap();
+    let _mock_boagent_process_embedded_impacts = boagent_server
+        .mock("GET", "/process_embedded_impacts")
+        .match_query(Matcher::UrlEncoded(
+            "start_time".to_string(),
+            now.to_string(),
+        ))
+        .with_status(200)
+        .with_body_from_file(mock_process_impacts_path)
+        .create_async()
+        .await;
+
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
+    let project_ids = insert_metadata(now, UnixFlag::Unset, &config)
+        .await
+        .unwrap();
+
+    let stop = stop_and_insert_event(
+        project_ids,
+        &mut SamplingWindow::new(now),
+        UnixFlag::Unset,
+        &mut Attribution::AllProcesses,
+        &config,
+    )
+    .await;
+    assert!(stop.is_ok());
+
+    let db_pool = get_db_connection_pool(&config.database_url).await.unwrap();
+    let stop_events = sqlx::query("SELECT id FROM events WHERE run_id = ($1) AND event_type = 'stop'")
+        .bind(project_ids.run_id)
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stop_events.len(), 1);
+
+    for (table, row_id) in [
+        ("projects", project_ids.project_id),
+        ("workflows", project_ids.workflow_id),
+        ("pipelines", project_ids.pipeline_id),
+        ("jobs", project_ids.job_id),
+        ("runs", project_ids.run_id),
+        ("tasks", project_ids.task_id),
+    ] {
+        let row = sqlx::query(&format!("SELECT stop_date FROM {} WHERE id = ($1)", table))
+            .bind(row_id)
+            .fetch_one(&db_pool)
+            .await
+            .unwrap();
+        let stop_date: Option<DateTime<Local>> = row.get("stop_date");
+        assert!(stop_date.is_some());
+    }
+}
+
+#[tokio::test]
+async fn it_inserts_a_custom_event_with_its_label() {
+    common::setup();
+    let now = Timestamp::new(UnixFlag::Unset);
+
+    let mut boagent_server = Server::new_async().await;
+    let url = boagent_server.url();
+    let mock_boagent_path =
+        canonicalize("../mocks/query_boagent_response_before_process_embedded_impacts.json")
+            .unwrap();
+    env::set_var("BOAGENT_URL", url);
+    let _mock_boagent_query = boagent_server
+        .mock("GET", "/query")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("start_time".to_string(), now.to_string()),
+            Matcher::UrlEncoded("verbose".to_string(), "true".to_string()),
+            Matcher::UrlEncoded("location".to_string(), "FRA".to_string()),
+            Matcher::UrlEncoded("measure_power".to_string(), "true".to_string()),
+            Matcher::UrlEncoded("lifetime".to_string(), "5".to_string()),
+        ]))
+        .with_status(200)
+        .with_body_from_file(mock_boagent_path)
+        .create_async()
+        .await;
+    let mock_process_impacts_path = canonicalize("../mocks/process6042.json").unwrap();
+    let _mock_boagent_process_embedded_impacts = boagent_server
+        .mock("GET", "/process_embedded_impacts")
+        .match_query(Matcher::UrlEncoded(
+            "start_time".to_string(),
+            now.to_string(),
+        ))
+        .with_status(200)
+        .with_body_from_file(mock_process_impacts_path)
+        .create_async()
+        .await;
+
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
+    let project_ids = insert_metadata(now, UnixFlag::Unset, &config)
+        .await
+        .unwrap();
+
+    let custom_event = sample_and_insert_custom_event(
+        project_ids,
+        &mut SamplingWindow::new(now),
+        UnixFlag::Unset,
+        &mut Attribution::AllProcesses,
+        "build done",
+        &config,
+    )
+    .await;
+    assert!(custom_event.is_ok());
+
+    let db_pool = get_db_connection_pool(&config.database_url).await.unwrap();
+    let custom_events = sqlx::query("SELECT user_label FROM events WHERE run_id = ($1) AND event_type = 'custom'")
+        .bind(project_ids.run_id)
+        .fetch_all(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(custom_events.len(), 1);
+    assert_eq!(custom_events[0].get::<String, &str>("user_label"), "build done");
+}
+
+#[tokio::test]
+async fn it_resumes_a_paused_session_from_the_time_of_resuming() {
+    common::setup();
+    let start = Timestamp::new(UnixFlag::Unset);
+    let project_root_path = std::env::current_dir().unwrap().join("..");
+    let config = Config::check_configuration(&project_root_path)
+        .expect("Configuration fields should be parsable.");
+    let mut session = Session {
+        session_id: "resume-test".to_string(),
+        ids: Ids {
+            project_id: Uuid::nil(),
+            workflow_id: Uuid::nil(),
+            pipeline_id: Uuid::nil(),
+            job_id: Uuid::nil(),
+            run_id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            device_id: Uuid::nil(),
+            process_id: Uuid::nil(),
+        },
+        window: SamplingWindow::new(start),
+        attribution: Attribution::AllProcesses,
+        unix_flag: UnixFlag::Unset,
+        paused: true,
+    };
+
+    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    let response = session.handle(ControlRequest::Resume, &config).await;
+
+    assert_eq!(response, ControlResponse::Done);
+    assert!(!session.status().paused);
+    assert!(session.window.previous_end.seconds_since(start) >= 1.0);
+    assert!(matches!(
+        session.handle(ControlRequest::Stop, &config).await,
+        ControlResponse::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn it_sends_events_to_the_api_and_spools_them_when_it_fails() {
+    let mut api_server = Server::new_async().await;
+    let spool_dir = std::env::temp_dir().join(format!("carenage_api_spool_{}", Uuid::new_v4()));
+    let config = Config {
+        boagent_url: "http://localhost:8000".to_string(),
+        database_url: String::new(),
+        location: "FRA".to_string(),
+        lifetime: 5,
+        device_name: "unknown".to_string(),
+        project_name: "hubblo/carenage".to_string(),
+        run_label: None,
+        ci_platform: CiPlatform::Local,
+        spool_dir: spool_dir.clone(),
+        transport: Transport::Api {
+            api_url: api_server.url(),
+            api_token: "secret".to_string(),
+        },
+        metrics_addr: None,
+        otlp_endpoint: None,
+    };
+    let ids = Ids {
+        project_id: Uuid::nil(),
+        workflow_id: Uuid::nil(),
+        pipeline_id: Uuid::nil(),
+        job_id: Uuid::nil(),
+        run_id: Uuid::nil(),
+        task_id: Uuid::nil(),
+        process_id: Uuid::nil(),
+        device_id: Uuid::nil(),
+    };
+    let start_event = EventBuilder::new(ids, EventType::Start).build();
+
+    let mock_ingestion = api_server
+        .mock("POST", "/ingest/events")
+        .match_header("authorization", "Bearer secret")
+        .with_status(201)
+        .with_body(format!("{{\"id\": \"{}\"}}", start_event.id))
+        .create_async()
+        .await;
+    insert_event(&start_event, &config).await.unwrap();
+    mock_ingestion.assert_async().await;
+    assert!(!spool_dir.exists());
+
+    mock_ingestion.remove_async().await;
+    let _mock_failing_ingestion = api_server
+        .mock("POST", "/ingest/events")
+        .with_status(503)
+        .create_async()
+        .await;
+    insert_event(&start_event, &config).await.unwrap();
+    let spooled_records = std::fs::read_dir(&spool_dir).unwrap().count();
+    std::fs::remove_dir_all(&spool_dir).unwrap();
+    assert_eq!(spooled_records, 1);
+}
+
+#[tokio::test]
+async fn it_serves_the_latest_metrics_of_each_process_in_openmetrics_format() {
+    let mut window = SamplingWindow::new(Timestamp::new(UnixFlag::Unset));
+    window.latest_metrics.insert(
+        4242,
+        LatestMetrics {
+            exe: "/usr/bin/cargo".to_string(),
+            metrics: Metrics {
+                process_power_measured_w: 3.5,
+                cpu_usage_percentage: 12.0,
+                ..Default::default()
+            },
+        },
+    );
+    let pipeline_id = Uuid::new_v4();
+    let session = Session {
+        session_id: "exposition-test".to_string(),
+        ids: Ids {
+            project_id: Uuid::nil(),
+            workflow_id: Uuid::nil(),
+            pipeline_id,
+            job_id: Uuid::nil(),
+            run_id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            device_id: Uuid::nil(),
+            process_id: Uuid::nil(),
+        },
+        window,
+        attribution: Attribution::AllProcesses,
+        unix_flag: UnixFlag::Unset,
+        paused: false,
+    };
+    let metrics_listener = Some(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+    let metrics_addr = metrics_listener.as_ref().unwrap().local_addr().unwrap();
+
+    let scraper = tokio::spawn(async move {
+        let mut stream = tokio::net::TcpStream::connect(metrics_addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    });
+    let (stream, _) = accept_scrape(&metrics_listener).await.unwrap();
+    serve_scrape(stream, &session.exposition("hubblo/carenage"))
+        .await
+        .unwrap();
+    let response = scraper.await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains(OPENMETRICS_CONTENT_TYPE));
+    assert!(response.contains("# TYPE carenage_process_power_measured_w gauge\n"));
+    assert!(response.contains(&format!(
+        "carenage_process_power_measured_w{{project=\"hubblo/carenage\",pipeline=\"{}\",job=\"{}\",pid=\"4242\",exe=\"/usr/bin/cargo\"}} 3.5\n",
+        pipeline_id,
+        Uuid::nil()
+    )));
+    assert!(response.contains("carenage_cpu_usage_percentage{"));
+    assert!(response.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn it_gives_up_on_a_scraper_that_does_not_read_the_response() {
+    let metrics_listener = Some(tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap());
+    let metrics_addr = metrics_listener.as_ref().unwrap().local_addr().unwrap();
+
+    let mut stream = tokio::net::TcpStream::connect(metrics_addr).await.unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let (scrape_stream, _) = accept_scrape(&metrics_listener).await.unwrap();
+    let exposition = "carenage_cpu_usage_percentage 1\n".repeat(1 << 20);
+
+    let serving = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        serve_scrape(scrape_stream, &exposition),
+    )
+    .await;
+
+    assert!(serving.expect("Serving should time out by itself.").is_err());
+    drop(stream);
+}
+
+#[cfg(feature = "otlp")]
+#[tokio::test]
+async fn it_exports_samples_as_otlp_metrics_to_a_collector() {
+    use carenaged::otlp::{resource_attributes, OtlpExporter};
+    use database::ci::{CiMetadata, VcsContext};
+    use database::spool::{RecordSink, Sample, SpoolRecord};
+    use database::tables::Process;
+
+    let mut collector = Server::new_async().await;
+    let config = Config {
+        boagent_url: "http://localhost:8000".to_string(),
+        database_url: String::new(),
+        location: "FRA".to_string(),
+        lifetime: 5,
+        device_name: "runner-1".to_string(),
+        project_name: "hubblo/carenage".to_string(),
+        run_label: None,
+        ci_platform: CiPlatform::Local,
+        spool_dir: std::env::temp_dir(),
+        transport: Transport::Database,
+        metrics_addr: None,
+        otlp_endpoint: Some(collector.url()),
+    };
+    let ci_metadata = CiMetadata {
+        project_path: "hubblo/carenage".to_string(),
+        workflow_name: "workflow_hubblo/carenage".to_string(),
+        workflow_started_at: None,
+        pipeline_id: 1234,
+        pipeline_name: "main".to_string(),
+        job_name: "build".to_string(),
+        job_started_at: None,
+        task_name: "build".to_string(),
+        vcs: VcsContext {
+            commit_sha: Some("0123abcd".to_string()),
+            ..Default::default()
+        },
+    };
+    let ids = Ids {
+        project_id: Uuid::nil(),
+        workflow_id: Uuid::nil(),
+        pipeline_id: Uuid::nil(),
+        job_id: Uuid::nil(),
+        run_id: Uuid::nil(),
+        task_id: Uuid::nil(),
+        process_id: Uuid::nil(),
+        device_id: Uuid::nil(),
+    };
+    let event = EventBuilder::new(ids, EventType::Regular).build();
+    let records = [
+        SpoolRecord::Event {
+            event: event.clone(),
+        },
+        SpoolRecord::Sample(Box::new(Sample {
+            event,
+            process: Process {
+                pid: 4242,
+                exe: "/usr/bin/cargo".to_string(),
+                cmdline: "cargo build".to_string(),
+                state: "Run".to_string(),
+            },
+            metrics: Metrics {
+                process_power_measured_w: 3.5,
+                ..Default::default()
+            },
+        })),
+    ];
+
+    let mock_collector = collector
+        .mock("POST", "/v1/metrics")
+        .match_header("content-type", "application/json")
+        .match_body(Matcher::AllOf(vec![
+            Matcher::Regex(
+                r#"\{"key":"service.name","value":\{"stringValue":"carenage"\}\}"#.to_string(),
+            ),
+            Matcher::Regex(
+                r#"\{"key":"cicd.pipeline.run.id","value":\{"stringValue":"1234"\}\}"#.to_string(),
+            ),
+            Matcher::Regex(
+                r#"\{"key":"vcs.ref.head.revision","value":\{"stringValue":"0123abcd"\}\}"#
+                    .to_string(),
+            ),
+            Matcher::Regex(r#""name":"carenage.process_power_measured_w""#.to_string()),
+            Matcher::Regex(r#""asDouble":3.5"#.to_string()),
+            Matcher::Regex(r#"\{"key":"process.pid","value":\{"intValue":"4242"\}\}"#.to_string()),
+        ]))
+        .with_status(200)
+        .with_body("{}")
+        .create_async()
+        .await;
+    let exporter = OtlpExporter::new(
+        config.otlp_endpoint.as_deref().unwrap(),
+        resource_attributes(&ci_metadata, &config),
+    )
+    .unwrap();
+    exporter.send(&records).await.unwrap();
+    mock_collector.assert_async().await;
+
+    assert!(exporter.export_request(&records[..1]).is_none());
+}
+
+#[tokio::test]
+async fn it_gives_up_on_a_control_client_that_sends_no_request() {
+    let socket_path = std::env::temp_dir().join(format!("carenage_{}.sock", Uuid::new_v4()));
+    let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+    let _silent_client = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let started_at = std::time::Instant::now();
+    let request = read_request(&mut stream).await;
+    std::fs::remove_file(&socket_path).unwrap();
+
+    assert!(request.is_err());
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(5));
 }
@@ -32,14 +32,22 @@ BEGIN
 	  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
 	  name VARCHAR(255),
 	  start_date TIMESTAMPTZ,
-	  stop_date TIMESTAMPTZ
+	  stop_date TIMESTAMPTZ,
+	  commit_sha VARCHAR(64),
+	  ref_name VARCHAR(255),
+	  merge_request_iid INTEGER,
+	  tag VARCHAR(255),
+	  commit_timestamp TIMESTAMPTZ,
+	  pipeline_url TEXT
 	);
 
 	CREATE TABLE runs (
 	  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
 	  name VARCHAR(255),
 	  start_date TIMESTAMPTZ,
-	  stop_date TIMESTAMPTZ
+	  stop_date TIMESTAMPTZ,
+	  functional_unit VARCHAR(255),
+	  functional_unit_count FLOAT8
 	);
 
 	CREATE TABLE jobs (
@@ -58,7 +58,13 @@ CREATE TABLE pipelines (
     id uuid DEFAULT gen_random_uuid() NOT NULL,
     name character varying(255),
     start_date timestamp with time zone,
-    stop_date timestamp with time zone
+    stop_date timestamp with time zone,
+    commit_sha character varying(64),
+    ref_name character varying(255),
+    merge_request_iid integer,
+    tag character varying(255),
+    commit_timestamp timestamp with time zone,
+    pipeline_url text
 );
 CREATE TABLE processes (
     id uuid DEFAULT gen_random_uuid() NOT NULL,
@@ -80,7 +86,9 @@ CREATE TABLE runs (
     id uuid DEFAULT gen_random_uuid() NOT NULL,
     name character varying(255),
     start_date timestamp with time zone,
-    stop_date timestamp with time zone
+    stop_date timestamp with time zone,
+    functional_unit character varying(255),
+    functional_unit_count double precision
 );
 CREATE TABLE tasks (
     id uuid DEFAULT gen_random_uuid() NOT NULL,
@@ -94,6 +102,32 @@ CREATE TABLE workflows (
     start_date timestamp with time zone,
     stop_date timestamp with time zone
 );
+CREATE TABLE budget_verdicts (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    run_id uuid,
+    energy_budget_wh float8,
+    emissions_budget_gco2eq float8,
+    energy_wh float8,
+    emissions_gco2eq float8,
+    exceeded boolean,
+    evaluated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TYPE token_permission AS ENUM (
+    'read',
+    'write'
+);
+CREATE TABLE api_tokens (
+    id uuid DEFAULT gen_random_uuid() NOT NULL,
+    name character varying(255),
+    token_hash character(64) NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
+    revoked_at timestamp with time zone
+);
+CREATE TABLE api_token_scopes (
+    token_id uuid NOT NULL,
+    project_id uuid NOT NULL,
+    permission token_permission
+);
 --
 -- PostgreSQL database dump
 --
@@ -154,10 +188,10 @@ INSERT INTO jobs VALUES
 --
 
 INSERT INTO pipelines VALUES
-	('80d53828-dcb8-4f45-aa4c-bc666e3ee54c', 'pipeline', '2024-11-05 11:14:52.079353+00', NULL),
-	('93a50da7-d390-43ed-92cf-192bc5e41eb2', 'pipeline', '2024-11-05 11:15:36.651423+00', NULL),
-	('648f8be8-0646-453e-8e48-110338b5e398', 'pipeline', '2024-11-05 11:18:40.346398+00', NULL),
-	('9d807f09-e006-4808-9fa2-70f67432d37b', 'pipeline', '2024-11-05 11:19:22.783871+00', NULL);
+	('80d53828-dcb8-4f45-aa4c-bc666e3ee54c', 'pipeline', '2024-11-05 11:14:52.079353+00', NULL, NULL, NULL, NULL, NULL, NULL, NULL),
+	('93a50da7-d390-43ed-92cf-192bc5e41eb2', 'pipeline', '2024-11-05 11:15:36.651423+00', NULL, NULL, NULL, NULL, NULL, NULL, NULL),
+	('648f8be8-0646-453e-8e48-110338b5e398', 'pipeline', '2024-11-05 11:18:40.346398+00', NULL, NULL, NULL, NULL, NULL, NULL, NULL),
+	('9d807f09-e006-4808-9fa2-70f67432d37b', 'pipeline', '2024-11-05 11:19:22.783871+00', NULL, 'a1b2c3d4e5f60718293a4b5c6d7e8f9012345678', 'main', 42, NULL, '2024-11-05 11:10:03+00', 'https://gitlab.com/hubblo/carenage/-/pipelines/1521');
 
 
 --
@@ -196,10 +230,10 @@ INSERT INTO projects VALUES
 --
 
 INSERT INTO runs VALUES
-	('60f84f9d-1104-48d9-ab59-3f635811de3d', 'run_build_env', '2024-11-05 11:14:52.079353+00', NULL),
-	('006db111-dbe7-4c01-888e-96f0231ac0ac', 'run_build_env', '2024-11-05 11:15:36.651423+00', NULL),
-	('5643685e-6e92-4987-b0c6-c3708aa62c60', 'run_build_env', '2024-11-05 11:18:40.346398+00', NULL),
-	('e51076c8-5c47-4a47-a146-04625e77a6ae', 'run_build_env', '2024-11-05 11:19:22.783871+00', NULL);
+	('60f84f9d-1104-48d9-ab59-3f635811de3d', 'run_build_env', '2024-11-05 11:14:52.079353+00', NULL, NULL, NULL),
+	('006db111-dbe7-4c01-888e-96f0231ac0ac', 'run_build_env', '2024-11-05 11:15:36.651423+00', NULL, NULL, NULL),
+	('5643685e-6e92-4987-b0c6-c3708aa62c60', 'run_build_env', '2024-11-05 11:18:40.346398+00', NULL, NULL, NULL),
+	('e51076c8-5c47-4a47-a146-04625e77a6ae', 'run_build_env', '2024-11-05 11:19:22.783871+00', NULL, 'test', 40);
 
 
 --
@@ -230,6 +264,8 @@ INSERT INTO workflows VALUES
 
 INSERT INTO events VALUES
 	('314d2a82-2493-416d-afa1-1b20b510ce3e', '2024-11-05 11:19:23.131908+00', 'df10c3cc-2033-4347-8394-1979d7ad57ec', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'start', NULL),
+	('b3a1c2d4-5e6f-4a7b-8c9d-0e1f2a3b4c5d', '2024-11-05 11:19:30.000000+00', 'df10c3cc-2033-4347-8394-1979d7ad57ec', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'custom', 'build done'),
+	('c4b2d3e5-6f70-4b8c-9dae-1f2a3b4c5d6e', '2024-11-05 11:19:40.000000+00', 'df10c3cc-2033-4347-8394-1979d7ad57ec', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'custom', 'tests done'),
 	('6f891e09-d246-427d-bca1-1570154cf711', '2024-11-05 11:19:28.232558+00', 'd50ddad6-7e27-4bc1-8085-de7d88c98900', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'regular', NULL),
 	('85c0b697-3a72-4952-9f3e-43e3214dfe8f', '2024-11-05 11:19:28.303712+00', '51aa483e-5903-442c-8b1a-e6aee05f8684', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'regular', NULL),
 	('603d5a77-db69-4279-bb59-5e14ecd76372', '2024-11-05 11:19:28.361217+00', '59ccb44b-9c3e-4722-841d-b75204c9493c', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', 'e51076c8-5c47-4a47-a146-04625e77a6ae', '9d807f09-e006-4808-9fa2-70f67432d37b', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'regular', NULL),
@@ -9447,6 +9483,14 @@ INSERT INTO metrics VALUES
 	('c8d46b70-ca5c-44b6-bac8-03463e8cbcd0', 'total_primary_energy_consumed_mj', 5444);
 
 
+--
+-- Data for Name: budget_verdicts; Type: TABLE DATA; Schema: public; Owner: carenage
+--
+
+INSERT INTO budget_verdicts VALUES
+	('c2f1e8a0-5b7d-4a43-9d1e-2f6b8c0a9e11', 'e51076c8-5c47-4a47-a146-04625e77a6ae', 50, 10, 12.5, 11.2, true, '2024-11-05 11:20:22.783871+00');
+
+
 --
 -- PostgreSQL database dump complete
 --
@@ -0,0 +1,12 @@
+INSERT INTO runs VALUES
+	('3c1f5a2e-8d4b-4f6a-9e7c-2b8d0a4f6e13', 'run_sci', '2024-11-06 09:00:00.000000+00', '2024-11-06 09:00:10.000000+00', 'test', 4);
+INSERT INTO events VALUES
+	('7a2e4c6b-1d3f-4e5a-8b9c-0d1e2f3a4b5c', '2024-11-06 09:00:05.000000+00', 'df10c3cc-2033-4347-8394-1979d7ad57ec', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', '3c1f5a2e-8d4b-4f6a-9e7c-2b8d0a4f6e13', 'a5d8c1e4-2f7b-4c9a-b3e6-1d4f7a0c3e69', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'regular', NULL),
+	('8b3f5d7c-2e4a-4f6b-9cad-1e2f3a4b5c6d', '2024-11-06 09:00:10.000000+00', 'df10c3cc-2033-4347-8394-1979d7ad57ec', '83e9b273-9aa9-4996-8141-751b91aa98b2', '6579f658-9286-493e-a3ed-0d92afa09edd', '3c1f5a2e-8d4b-4f6a-9e7c-2b8d0a4f6e13', 'a5d8c1e4-2f7b-4c9a-b3e6-1d4f7a0c3e69', '03c06a5e-a139-4a9e-a770-f69821b10faf', '95dfae11-5cad-41d9-bcf9-fa6564c22dd6', '599d2042-98b9-46df-bcc1-8c03c85da332', 'regular', NULL);
+INSERT INTO metrics VALUES
+	('7a2e4c6b-1d3f-4e5a-8b9c-0d1e2f3a4b5c', 'energy_consumed_wh', 250),
+	('7a2e4c6b-1d3f-4e5a-8b9c-0d1e2f3a4b5c', 'electricity_carbon_intensity_kgc02eq_per_kwh', 0.25),
+	('7a2e4c6b-1d3f-4e5a-8b9c-0d1e2f3a4b5c', 'cpu_gwp_average_impact_kgc02eq', 0.0625),
+	('8b3f5d7c-2e4a-4f6b-9cad-1e2f3a4b5c6d', 'energy_consumed_wh', 250),
+	('8b3f5d7c-2e4a-4f6b-9cad-1e2f3a4b5c6d', 'electricity_carbon_intensity_kgc02eq_per_kwh', 0.5),
+	('8b3f5d7c-2e4a-4f6b-9cad-1e2f3a4b5c6d', 'ram_gwp_average_impact_kgc02eq', 0.0625);
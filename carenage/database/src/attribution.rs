@@ -0,0 +1,302 @@
+use crate::tables::Process;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+pub const OTHER_PROCESSES_EXE: &str = "other";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributionMode {
+    AllProcesses,
+    ProcessTree,
+}
+
+impl Display for AttributionMode {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            AttributionMode::AllProcesses => {
+                write!(f, "all")
+            }
+            AttributionMode::ProcessTree => {
+                write!(f, "tree")
+            }
+        }
+    }
+}
+
+impl AttributionMode {
+    pub fn parse_str(mode_str: &str) -> Result<AttributionMode, Box<dyn std::error::Error>> {
+        match mode_str {
+            "all" => Ok(AttributionMode::AllProcesses),
+            "tree" => Ok(AttributionMode::ProcessTree),
+            _ => Err(format!("Unknown attribution mode: {}.", mode_str).into()),
+        }
+    }
+}
+
+/* Processes started by a CI job might have exited by the time Scaphandre data for them is
+ * received through Boagent: PIDs found once in the tree are kept, so that their last
+ * measurements are still attributed to the job. A PID is kept along with the start time of its
+ * process, so that a PID reused by a process outside of the tree is dropped. The start time of
+ * the root process is only known once it is found in /proc. */
+#[derive(Clone, Debug)]
+pub struct ProcessTree {
+    pub root_pid: i32,
+    pub pids: HashMap<i32, Option<u64>>,
+}
+
+impl ProcessTree {
+    pub fn new(root_pid: i32) -> Self {
+        ProcessTree {
+            root_pid,
+            pids: HashMap::from([(root_pid, None)]),
+        }
+    }
+
+    pub fn refresh(&mut self, proc_stats: &HashMap<i32, ProcStat>) {
+        self.pids.retain(|pid, start_time| {
+            match (proc_stats.get(pid), start_time) {
+                (Some(proc_stat), Some(start_time)) => proc_stat.start_time == *start_time,
+                _ => true,
+            }
+        });
+        /* Once the PID of the root process is reused, the tree of the job is not walked anymore. */
+        if !self.pids.contains_key(&self.root_pid) {
+            return;
+        }
+
+        let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (pid, proc_stat) in proc_stats {
+            children.entry(proc_stat.parent_pid).or_default().push(*pid);
+        }
+
+        /* `carenage-cli run` spawns carenaged within the tree it measures: the daemon is left out. */
+        let daemon_pid = std::process::id() as i32;
+        let mut to_visit = vec![self.root_pid];
+        while let Some(pid) = to_visit.pop() {
+            if pid == daemon_pid && pid != self.root_pid {
+                continue;
+            }
+            let start_time = proc_stats.get(&pid).map(|proc_stat| proc_stat.start_time);
+            let recorded_start_time = self.pids.entry(pid).or_insert(start_time);
+            if start_time.is_some() {
+                *recorded_start_time = start_time;
+            }
+            if let Some(pid_children) = children.get(&pid) {
+                to_visit.extend(pid_children);
+            }
+        }
+    }
+
+    pub fn contains(&self, pid: i32) -> bool {
+        self.pids.contains_key(&pid)
+    }
+}
+
+pub enum Attribution {
+    AllProcesses,
+    ProcessTree {
+        tree: ProcessTree,
+        other_bucket: bool,
+    },
+}
+
+impl Attribution {
+    pub fn new(mode: AttributionMode, root_pid: i32, other_bucket: bool) -> Self {
+        match mode {
+            AttributionMode::AllProcesses => Attribution::AllProcesses,
+            AttributionMode::ProcessTree => {
+                info!("Attributing metrics to the process tree rooted at PID {}.", root_pid);
+                Attribution::ProcessTree {
+                    tree: ProcessTree::new(root_pid),
+                    other_bucket,
+                }
+            }
+        }
+    }
+
+    pub fn has_other_bucket(&self) -> bool {
+        match self {
+            Attribution::AllProcesses => false,
+            Attribution::ProcessTree { other_bucket, .. } => *other_bucket,
+        }
+    }
+
+    /* Returns the processes attributed to the CI job, and the ones filtered out. */
+    pub fn split_processes(&mut self, processes: Vec<Process>) -> (Vec<Process>, Vec<Process>) {
+        match self {
+            Attribution::AllProcesses => (processes, vec![]),
+            Attribution::ProcessTree { tree, .. } => {
+                tree.refresh(&read_proc_stats(Path::new("/proc")));
+                processes
+                    .into_iter()
+                    .partition(|process| tree.contains(process.pid))
+            }
+        }
+    }
+}
+
+/* Fields of /proc/<pid>/stat the process tree is built from: the parent PID (field 4) and the
+ * start time of the process, in clock ticks since boot (field 22). */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProcStat {
+    pub parent_pid: i32,
+    pub start_time: u64,
+}
+
+pub fn parse_proc_stat(stat: &str) -> Option<ProcStat> {
+    // The command name, between parentheses, might contain spaces: fields are read after it.
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+    let parent_pid = fields.nth(1)?.parse().ok()?;
+    let start_time = fields.nth(17)?.parse().ok()?;
+    Some(ProcStat {
+        parent_pid,
+        start_time,
+    })
+}
+
+pub fn read_proc_stats(proc_path: &Path) -> HashMap<i32, ProcStat> {
+    let mut proc_stats = HashMap::new();
+
+    let entries = match fs::read_dir(proc_path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Unable to read {}: {}", proc_path.display(), err);
+            return proc_stats;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|file_name| file_name.parse::<i32>().ok())
+        else {
+            continue;
+        };
+
+        // A process might exit between listing /proc and reading its stat file.
+        if let Some(proc_stat) = fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .and_then(|stat| parse_proc_stat(&stat))
+        {
+            proc_stats.insert(pid, proc_stat);
+        }
+    }
+    proc_stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tables::ProcessBuilder;
+
+    fn proc_stats(processes: &[(i32, i32, u64)]) -> HashMap<i32, ProcStat> {
+        processes
+            .iter()
+            .map(|(pid, parent_pid, start_time)| {
+                (
+                    *pid,
+                    ProcStat {
+                        parent_pid: *parent_pid,
+                        start_time: *start_time,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn tree_pids(tree: &ProcessTree) -> HashSet<i32> {
+        tree.pids.keys().copied().collect()
+    }
+
+    #[test]
+    fn it_parses_the_parent_pid_and_start_time_from_a_proc_stat_line() {
+        let stat = "4242 (cargo test (1)) S 4200 4242 4200 34816 4242 4194304 1205 0 0 0 12 3 0 0 20 0 1 0 987654 4096 1";
+        assert_eq!(
+            parse_proc_stat(stat),
+            Some(ProcStat {
+                parent_pid: 4200,
+                start_time: 987654
+            })
+        );
+        assert_eq!(parse_proc_stat("not a stat line"), None);
+    }
+
+    #[test]
+    fn it_resolves_all_descendants_of_the_root_process() {
+        let mut tree = ProcessTree::new(100);
+        tree.refresh(&proc_stats(&[
+            (1, 0, 1),
+            (100, 1, 10),
+            (200, 100, 20),
+            (201, 100, 20),
+            (300, 200, 30),
+            (400, 1, 40),
+        ]));
+
+        assert_eq!(tree_pids(&tree), HashSet::from([100, 200, 201, 300]));
+        assert!(!tree.contains(400));
+    }
+
+    #[test]
+    fn it_keeps_pids_of_exited_processes_in_the_tree() {
+        let mut tree = ProcessTree::new(100);
+        tree.refresh(&proc_stats(&[(200, 100, 20)]));
+        tree.refresh(&proc_stats(&[(201, 100, 21)]));
+
+        assert_eq!(tree_pids(&tree), HashSet::from([100, 200, 201]));
+    }
+
+    #[test]
+    fn it_drops_pids_reused_by_processes_outside_of_the_tree() {
+        let mut tree = ProcessTree::new(100);
+        tree.refresh(&proc_stats(&[(100, 1, 10), (200, 100, 20)]));
+        tree.refresh(&proc_stats(&[(100, 1, 10), (200, 1, 50)]));
+
+        assert_eq!(tree_pids(&tree), HashSet::from([100]));
+        assert!(!tree.contains(200));
+    }
+
+    #[test]
+    fn it_leaves_the_daemon_spawned_within_the_tree_out_of_it() {
+        let daemon_pid = std::process::id() as i32;
+        let mut tree = ProcessTree::new(100);
+        tree.refresh(&proc_stats(&[
+            (daemon_pid, 100, 10),
+            (200, 100, 20),
+            (300, daemon_pid, 30),
+        ]));
+
+        assert_eq!(tree_pids(&tree), HashSet::from([100, 200]));
+    }
+
+    #[test]
+    fn it_keeps_all_processes_when_attributing_metrics_to_all_processes() {
+        let processes = vec![
+            ProcessBuilder::new(100, "/usr/bin/bash", "bash", "running").build(),
+            ProcessBuilder::new(810, "/usr/bin/containerd", "containerd", "running").build(),
+        ];
+        let (attributed, others) = Attribution::AllProcesses.split_processes(processes);
+
+        assert_eq!(attributed.len(), 2);
+        assert!(others.is_empty());
+    }
+
+    #[test]
+    fn it_filters_out_processes_outside_of_the_process_tree() {
+        let own_pid = std::process::id() as i32;
+        let processes = vec![
+            ProcessBuilder::new(own_pid, "/usr/bin/cargo", "cargo test", "running").build(),
+            ProcessBuilder::new(-1, "/does/not/exist", "/does/not/exist", "zombie").build(),
+        ];
+        let mut attribution = Attribution::new(AttributionMode::ProcessTree, own_pid, true);
+        let (attributed, others) = attribution.split_processes(processes);
+
+        assert_eq!(attributed[0].pid, own_pid);
+        assert_eq!(others[0].pid, -1);
+        assert!(attribution.has_other_bucket());
+    }
+}
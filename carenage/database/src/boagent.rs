@@ -1,10 +1,12 @@
+use crate::ci::CiPlatform;
 use crate::timestamp::Timestamp;
 use dotenv::{from_path, var};
 use log::info;
 use reqwest::{Client, Response};
 use serde_json::{Error, Value};
 use std::fmt::{Display, Formatter};
-use std::path::Path;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 
 #[derive(Clone, Copy)]
 pub enum HardwareData {
@@ -25,6 +27,18 @@ impl Display for HardwareData {
     }
 }
 
+/* Kept outside of /tmp, so that samples spooled while the database was unreachable survive a
+ * reboot of the runner. */
+const DEFAULT_SPOOL_DIR: &str = "/var/tmp/carenage";
+
+/* Runners send their data either straight to the database, or through the ingestion API of
+ * carenage when an API URL is configured, so that they do not hold database credentials. */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transport {
+    Database,
+    Api { api_url: String, api_token: String },
+}
+
 pub struct Config {
     pub boagent_url: String,
     pub database_url: String,
@@ -32,6 +46,12 @@ pub struct Config {
     pub lifetime: i16,
     pub device_name: String,
     pub project_name: String,
+    pub run_label: Option<String>,
+    pub ci_platform: CiPlatform,
+    pub spool_dir: PathBuf,
+    pub transport: Transport,
+    pub metrics_addr: Option<SocketAddr>,
+    pub otlp_endpoint: Option<String>,
 }
 
 impl Config {
@@ -42,8 +62,35 @@ impl Config {
         let location = var("LOCATION").expect("LOCATION environment variable is absent. It is needed to indicate the energy mix relevant to the evaluated environmental impacts.");
         let lifetime: i16 = var("LIFETIME").expect("LIFETIME environment variable is absent. It is needed to calculate the environmental impact for the evaluated device.").parse().expect("Failed to parse lifetime value.");
         let device_name = var("DEVICE").unwrap_or("unknown".to_string());
-        let database_url =
-            var("DATABASE_URL").expect("DATABASE_URL environment variable is absent.");
+        let run_label = var("RUN_LABEL").ok();
+        let ci_platform = match var("CI_PLATFORM") {
+            Ok(platform_str) => CiPlatform::parse_str(&platform_str)?,
+            Err(_) => CiPlatform::detect(),
+        };
+        let transport = match var("CARENAGE_API_URL") {
+            Ok(api_url) => Transport::Api {
+                api_url,
+                api_token: var("CARENAGE_API_TOKEN").expect("CARENAGE_API_TOKEN environment variable is absent. It is needed to send data through the API of carenage."),
+            },
+            Err(_) => Transport::Database,
+        };
+        let database_url = match transport {
+            Transport::Database => {
+                var("DATABASE_URL").expect("DATABASE_URL environment variable is absent.")
+            }
+            Transport::Api { .. } => var("DATABASE_URL").unwrap_or_default(),
+        };
+        let spool_dir = PathBuf::from(var("SPOOL_DIR").unwrap_or(DEFAULT_SPOOL_DIR.to_string()));
+        /* carenaged only serves its latest metrics to Prometheus when given an address to. */
+        let metrics_addr = var("METRICS_ADDR")
+            .ok()
+            .map(|addr| addr.parse::<SocketAddr>())
+            .transpose()?;
+        /* Samples are also pushed to an OpenTelemetry collector when carenaged is built with the
+         * otlp feature and given the endpoint of the collector. */
+        let otlp_endpoint = var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty());
 
         info!("All needed configuration variables are available!");
         Ok(Config {
@@ -53,6 +100,12 @@ impl Config {
             lifetime,
             device_name,
             database_url,
+            run_label,
+            ci_platform,
+            spool_dir,
+            transport,
+            metrics_addr,
+            otlp_endpoint,
         })
     }
 }
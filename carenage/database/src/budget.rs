@@ -0,0 +1,288 @@
+use crate::energy::attributed_energy_condition;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgRow;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+pub const BUDGET_EXCEEDED_EXIT_CODE: i32 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub energy_wh: Option<f64>,
+    pub emissions_gco2eq: Option<f64>,
+}
+
+/* Budgets are declared in a TOML file checked into the repository of the project:
+ *
+ * [budget]
+ * energy_wh = 50
+ * emissions_gco2eq = 10
+ *
+ * [jobs.integration-tests]
+ * energy_wh = 120
+ *
+ * Limits declared for a job override the ones of the default budget. */
+#[derive(Debug, Default, Deserialize)]
+pub struct BudgetFile {
+    #[serde(default)]
+    pub budget: Limits,
+    #[serde(default)]
+    pub jobs: HashMap<String, Limits>,
+}
+
+impl BudgetFile {
+    pub fn parse_str(budget_str: &str) -> Result<BudgetFile, Box<dyn std::error::Error>> {
+        Ok(toml::from_str(budget_str)?)
+    }
+
+    pub fn read(budget_path: &Path) -> Result<Option<BudgetFile>, Box<dyn std::error::Error>> {
+        if !budget_path.exists() {
+            return Ok(None);
+        }
+        let budget_str = fs::read_to_string(budget_path)?;
+        Ok(Some(BudgetFile::parse_str(&budget_str)?))
+    }
+
+    pub fn limits_for_job(&self, job_name: &str) -> Limits {
+        match self.jobs.get(job_name) {
+            Some(job_limits) => Limits {
+                energy_wh: job_limits.energy_wh.or(self.budget.energy_wh),
+                emissions_gco2eq: job_limits.emissions_gco2eq.or(self.budget.emissions_gco2eq),
+            },
+            None => self.budget,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Clone, Copy, Debug, PartialEq)]
+pub struct RunTotals {
+    pub energy_wh: f64,
+    pub emissions_gco2eq: f64,
+}
+
+/* A limit of the budget compared to the total of the run, as reported to CI tools. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct BudgetCheck {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub value: f64,
+    pub limit: f64,
+    pub exceeded: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Verdict {
+    pub limits: Limits,
+    pub totals: RunTotals,
+    pub exceeded: bool,
+}
+
+impl Verdict {
+    pub fn evaluate(limits: Limits, totals: RunTotals) -> Self {
+        let energy_exceeded = limits
+            .energy_wh
+            .is_some_and(|energy_wh| totals.energy_wh > energy_wh);
+        let emissions_exceeded = limits
+            .emissions_gco2eq
+            .is_some_and(|emissions_gco2eq| totals.emissions_gco2eq > emissions_gco2eq);
+
+        Verdict {
+            limits,
+            totals,
+            exceeded: energy_exceeded || emissions_exceeded,
+        }
+    }
+
+    /* Only limits declared in the budget are checked. */
+    pub fn checks(&self) -> Vec<BudgetCheck> {
+        [
+            ("energy", "Wh", self.totals.energy_wh, self.limits.energy_wh),
+            ("emissions", "gCO2eq", self.totals.emissions_gco2eq, self.limits.emissions_gco2eq),
+        ]
+        .into_iter()
+        .filter_map(|(name, unit, value, limit)| {
+            limit.map(|limit| BudgetCheck {
+                name,
+                unit,
+                value,
+                limit,
+                exceeded: value > limit,
+            })
+        })
+        .collect()
+    }
+}
+
+fn format_limit(value: f64, limit: Option<f64>, unit: &str) -> String {
+    match limit {
+        Some(limit) => format!("{:.3} {} (budget: {} {})", value, unit, limit, unit),
+        None => format!("{:.3} {} (no budget)", value, unit),
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let verdict = match self.exceeded {
+            true => "Budget exceeded",
+            false => "Within budget",
+        };
+        write!(
+            f,
+            "{}: energy {}, emissions {}.",
+            verdict,
+            format_limit(self.totals.energy_wh, self.limits.energy_wh, "Wh"),
+            format_limit(self.totals.emissions_gco2eq, self.limits.emissions_gco2eq, "gCO2eq")
+        )
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerdictRecord {
+    pub run_id: Uuid,
+    pub run_name: String,
+    pub energy_budget_wh: Option<f64>,
+    pub emissions_budget_gco2eq: Option<f64>,
+    pub energy_wh: f64,
+    pub emissions_gco2eq: f64,
+    pub exceeded: bool,
+    pub evaluated_at: DateTime<Local>,
+}
+
+/* Energy is the sum of the energy consumed over each sample of the processes attributed to the
+ * run. Emissions reported by Boagent are computed from the start of the run: the last value is the
+ * run total. */
+pub async fn select_run_totals(
+    database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+) -> Result<RunTotals, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let formatted_query = format!(
+        "SELECT COALESCE((SELECT SUM(metrics.value) FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.run_id = runs.id AND {}), 0) AS energy_wh, COALESCE((SELECT metrics.value FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.run_id = runs.id AND metrics.metric = 'total_operational_emission_kgc02eq' ORDER BY events.timestamp DESC LIMIT 1), 0) * 1000 AS emissions_gco2eq FROM RUNS WHERE runs.id = ($1)",
+        attributed_energy_condition()
+    );
+
+    let run_totals: RunTotals = sqlx::query_as(&formatted_query)
+    .bind(run_id)
+    .fetch_one(&mut connection)
+    .await?;
+
+    Ok(run_totals)
+}
+
+pub async fn select_job_name_from_run(
+    database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let job_name: String = sqlx::query_scalar(
+        "SELECT DISTINCT jobs.name FROM JOBS INNER JOIN EVENTS ON events.job_id = jobs.id WHERE events.run_id = ($1)",
+    )
+    .bind(run_id)
+    .fetch_one(&mut connection)
+    .await?;
+
+    Ok(job_name)
+}
+
+pub async fn insert_verdict(
+    database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+    verdict: &Verdict,
+) -> Result<PgRow, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let row = sqlx::query(
+        "INSERT INTO budget_verdicts (run_id, energy_budget_wh, emissions_budget_gco2eq, energy_wh, emissions_gco2eq, exceeded) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+    )
+    .bind(run_id)
+    .bind(verdict.limits.energy_wh)
+    .bind(verdict.limits.emissions_gco2eq)
+    .bind(verdict.totals.energy_wh)
+    .bind(verdict.totals.emissions_gco2eq)
+    .bind(verdict.exceeded)
+    .fetch_one(&mut connection)
+    .await?;
+
+    Ok(row)
+}
+
+pub async fn select_verdicts_from_project(
+    database_connection: PoolConnection<Postgres>,
+    project_id: Uuid,
+) -> Result<Vec<VerdictRecord>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let verdicts: Vec<VerdictRecord> = sqlx::query_as(
+        "SELECT budget_verdicts.run_id, runs.name AS run_name, budget_verdicts.energy_budget_wh, budget_verdicts.emissions_budget_gco2eq, budget_verdicts.energy_wh, budget_verdicts.emissions_gco2eq, budget_verdicts.exceeded, budget_verdicts.evaluated_at FROM BUDGET_VERDICTS INNER JOIN RUNS ON budget_verdicts.run_id = runs.id WHERE budget_verdicts.run_id IN (SELECT DISTINCT events.run_id FROM EVENTS WHERE events.project_id = ($1)) ORDER BY budget_verdicts.evaluated_at",
+    )
+    .bind(project_id)
+    .fetch_all(&mut connection)
+    .await?;
+
+    Ok(verdicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_uses_job_limits_over_the_default_budget() {
+        let budget_file = BudgetFile::parse_str(
+            "[budget]
+energy_wh = 50
+emissions_gco2eq = 10
+
+[jobs.integration-tests]
+energy_wh = 120
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            budget_file.limits_for_job("integration-tests"),
+            Limits {
+                energy_wh: Some(120.0),
+                emissions_gco2eq: Some(10.0)
+            }
+        );
+        assert_eq!(budget_file.limits_for_job("build"), budget_file.budget);
+    }
+
+    #[test]
+    fn it_exceeds_the_budget_when_one_of_the_limits_is_exceeded() {
+        let limits = Limits {
+            energy_wh: Some(50.0),
+            emissions_gco2eq: None,
+        };
+        let within_budget = Verdict::evaluate(
+            limits,
+            RunTotals {
+                energy_wh: 49.0,
+                emissions_gco2eq: 1000.0,
+            },
+        );
+        let over_budget = Verdict::evaluate(
+            limits,
+            RunTotals {
+                energy_wh: 51.0,
+                emissions_gco2eq: 0.0,
+            },
+        );
+
+        assert!(!within_budget.exceeded);
+        assert!(over_budget.exceeded);
+        assert_eq!(
+            over_budget.to_string(),
+            "Budget exceeded: energy 51.000 Wh (budget: 50 Wh), emissions 0.000 gCO2eq (no budget)."
+        );
+    }
+}
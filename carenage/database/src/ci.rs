@@ -1,20 +1,65 @@
 use std::env;
+use std::fmt::{Display, Formatter};
 use log::info;
 
 use crate::timestamp::{Timestamp, UnixFlag};
 
-pub struct GitlabVariables {
+/* Metadata of a CI job, mapped from the concepts of each CI platform onto the dimensions of
+ * Carenage: project, workflow, pipeline, job and task. Start dates that a platform does not
+ * expose are left empty, and replaced with the start timestamp of Carenage. */
+pub struct CiMetadata {
     pub project_path: String,
+    pub workflow_name: String,
+    pub workflow_started_at: Option<Timestamp>,
     pub pipeline_id: u64,
-    pub pipeline_created_at: Timestamp,
     pub pipeline_name: String,
     pub job_name: String,
-    pub job_stage: String,
-    pub job_started_at: Timestamp,
+    pub job_started_at: Option<Timestamp>,
+    pub task_name: String,
+    pub vcs: VcsContext,
 }
 
-impl GitlabVariables {
-    pub fn parse_env_variables() -> Result<GitlabVariables, Box<dyn std::error::Error>> {
+/* Version control context of the pipeline, all fields being optional: a merge request IID is only
+ * set for merge request pipelines, a tag only for tag pipelines, and a local run might have none. */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VcsContext {
+    pub commit_sha: Option<String>,
+    pub ref_name: Option<String>,
+    pub merge_request_iid: Option<i32>,
+    pub tag: Option<String>,
+    pub commit_timestamp: Option<Timestamp>,
+    pub pipeline_url: Option<String>,
+}
+
+fn optional_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+fn optional_timestamp_var(key: &str) -> Option<Timestamp> {
+    optional_var(key)
+        .and_then(|timestamp_str| timestamp_str.parse().ok())
+        .map(|datetime| Timestamp::ISO8601(Some(datetime)))
+}
+
+pub trait CiProvider {
+    fn is_detected(&self) -> bool;
+    fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>>;
+    /* Steps of a CI job may be run by shells of their own: the session of carenage is named after
+     * the job, when the platform identifies it. */
+    fn session_id(&self) -> Option<String>;
+}
+
+pub struct Gitlab;
+pub struct GithubActions;
+pub struct Jenkins;
+pub struct Local;
+
+impl CiProvider for Gitlab {
+    fn is_detected(&self) -> bool {
+        env::var("GITLAB_CI").is_ok()
+    }
+
+    fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>> {
         let project_path = env::var("CI_PROJECT_PATH")?.to_string();
         let pipeline_id = env::var("CI_PIPELINE_ID")?.to_string().parse::<u64>()?;
         let pipeline_created_at = Timestamp::parse_str(env::var("CI_PIPELINE_CREATED_AT")?.to_string(), UnixFlag::Unset);
@@ -25,14 +70,215 @@ impl GitlabVariables {
 
         info!("All needed Gitlab variables are available!");
 
-        Ok(GitlabVariables {
+        Ok(CiMetadata {
+            workflow_name: format!("workflow_{}", project_path),
             project_path,
+            workflow_started_at: Some(pipeline_created_at),
             pipeline_id,
-            pipeline_created_at,
             pipeline_name,
             job_name,
-            job_stage,
-            job_started_at,
+            job_started_at: Some(job_started_at),
+            task_name: job_stage,
+            vcs: VcsContext {
+                commit_sha: optional_var("CI_COMMIT_SHA"),
+                ref_name: optional_var("CI_COMMIT_REF_NAME"),
+                merge_request_iid: optional_var("CI_MERGE_REQUEST_IID").and_then(|iid| iid.parse().ok()),
+                tag: optional_var("CI_COMMIT_TAG"),
+                commit_timestamp: optional_timestamp_var("CI_COMMIT_TIMESTAMP"),
+                pipeline_url: optional_var("CI_PIPELINE_URL"),
+            },
+        })
+    }
+
+    fn session_id(&self) -> Option<String> {
+        optional_var("CI_JOB_ID").map(|job_id| format!("gitlab_{}", job_id))
+    }
+}
+
+impl CiProvider for GithubActions {
+    fn is_detected(&self) -> bool {
+        env::var("GITHUB_ACTIONS").is_ok()
+    }
+
+    /* A GitHub workflow run is the pipeline, the step currently executed by the job is the task. */
+    fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>> {
+        let project_path = env::var("GITHUB_REPOSITORY")?.to_string();
+        let workflow_name = env::var("GITHUB_WORKFLOW")?.to_string();
+        let pipeline_id = env::var("GITHUB_RUN_ID")?.to_string().parse::<u64>()?;
+        let run_number = env::var("GITHUB_RUN_NUMBER")?.to_string();
+        let job_name = env::var("GITHUB_JOB")?.to_string();
+        let task_name = env::var("GITHUB_ACTION").unwrap_or(job_name.clone());
+
+        /* Pull request workflows are run on refs/pull/<number>/merge. */
+        let pull_request_number = optional_var("GITHUB_REF")
+            .filter(|git_ref| git_ref.starts_with("refs/pull/"))
+            .and_then(|git_ref| git_ref.split('/').nth(2)?.parse().ok());
+        let tag = optional_var("GITHUB_REF_TYPE")
+            .filter(|ref_type| ref_type == "tag")
+            .and_then(|_| optional_var("GITHUB_REF_NAME"));
+        let pipeline_url = optional_var("GITHUB_SERVER_URL").map(|server_url| {
+            format!("{}/{}/actions/runs/{}", server_url, project_path, pipeline_id)
+        });
+
+        info!("All needed GitHub Actions variables are available!");
+
+        Ok(CiMetadata {
+            pipeline_name: format!("{} #{}", workflow_name, run_number),
+            project_path,
+            workflow_name,
+            workflow_started_at: None,
+            pipeline_id,
+            job_name,
+            job_started_at: None,
+            task_name,
+            vcs: VcsContext {
+                commit_sha: optional_var("GITHUB_SHA"),
+                ref_name: optional_var("GITHUB_HEAD_REF").or(optional_var("GITHUB_REF_NAME")),
+                merge_request_iid: pull_request_number,
+                tag,
+                commit_timestamp: None,
+                pipeline_url,
+            },
         })
     }
+
+    /* A job is identified within the attempt of a workflow run by its key. */
+    fn session_id(&self) -> Option<String> {
+        let run_id = optional_var("GITHUB_RUN_ID")?;
+        let run_attempt = optional_var("GITHUB_RUN_ATTEMPT").unwrap_or("1".to_string());
+        let job = optional_var("GITHUB_JOB")?;
+        Some(format!("github_{}_{}_{}", run_id, run_attempt, job))
+    }
+}
+
+impl CiProvider for Jenkins {
+    fn is_detected(&self) -> bool {
+        env::var("JENKINS_URL").is_ok()
+    }
+
+    /* A Jenkins job is the project, one of its builds is the pipeline. STAGE_NAME is only set
+     * inside the stages of a declarative pipeline. */
+    fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>> {
+        let project_path = env::var("JOB_NAME")?.to_string();
+        let pipeline_id = env::var("BUILD_NUMBER")?.to_string().parse::<u64>()?;
+        let pipeline_name = env::var("BUILD_TAG")?.to_string();
+        let job_name = env::var("JOB_BASE_NAME").unwrap_or(project_path.clone());
+        let task_name = env::var("STAGE_NAME").unwrap_or(job_name.clone());
+
+        info!("All needed Jenkins variables are available!");
+
+        Ok(CiMetadata {
+            workflow_name: format!("workflow_{}", project_path),
+            project_path,
+            workflow_started_at: None,
+            pipeline_id,
+            pipeline_name,
+            job_name,
+            job_started_at: None,
+            task_name,
+            vcs: VcsContext {
+                commit_sha: optional_var("GIT_COMMIT"),
+                ref_name: optional_var("CHANGE_BRANCH").or(optional_var("GIT_BRANCH")),
+                merge_request_iid: optional_var("CHANGE_ID").and_then(|id| id.parse().ok()),
+                tag: optional_var("TAG_NAME"),
+                commit_timestamp: None,
+                pipeline_url: optional_var("BUILD_URL"),
+            },
+        })
+    }
+
+    fn session_id(&self) -> Option<String> {
+        optional_var("BUILD_TAG")
+    }
+}
+
+impl CiProvider for Local {
+    fn is_detected(&self) -> bool {
+        true
+    }
+
+    fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>> {
+        let project_path = env::var("PROJECT_NAME")?.to_string();
+
+        info!("No CI platform is used, metadata is set for a local run.");
+
+        Ok(CiMetadata {
+            workflow_name: format!("workflow_{}", project_path),
+            project_path,
+            workflow_started_at: None,
+            pipeline_id: 0,
+            pipeline_name: "local".to_string(),
+            job_name: "local".to_string(),
+            job_started_at: None,
+            task_name: "local".to_string(),
+            vcs: VcsContext::default(),
+        })
+    }
+
+    fn session_id(&self) -> Option<String> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CiPlatform {
+    Gitlab,
+    GithubActions,
+    Jenkins,
+    Local,
+}
+
+impl Display for CiPlatform {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CiPlatform::Gitlab => {
+                write!(f, "gitlab")
+            }
+            CiPlatform::GithubActions => {
+                write!(f, "github")
+            }
+            CiPlatform::Jenkins => {
+                write!(f, "jenkins")
+            }
+            CiPlatform::Local => {
+                write!(f, "local")
+            }
+        }
+    }
+}
+
+impl CiPlatform {
+    pub fn parse_str(platform_str: &str) -> Result<CiPlatform, Box<dyn std::error::Error>> {
+        match platform_str {
+            "gitlab" => Ok(CiPlatform::Gitlab),
+            "github" => Ok(CiPlatform::GithubActions),
+            "jenkins" => Ok(CiPlatform::Jenkins),
+            "local" => Ok(CiPlatform::Local),
+            _ => Err(format!("Unknown CI platform: {}.", platform_str).into()),
+        }
+    }
+
+    pub fn provider(&self) -> &dyn CiProvider {
+        match self {
+            CiPlatform::Gitlab => &Gitlab,
+            CiPlatform::GithubActions => &GithubActions,
+            CiPlatform::Jenkins => &Jenkins,
+            CiPlatform::Local => &Local,
+        }
+    }
+
+    /* Platforms are checked in order, Local being used when no CI platform is detected. */
+    pub fn detect() -> CiPlatform {
+        let platform = [CiPlatform::Gitlab, CiPlatform::GithubActions, CiPlatform::Jenkins]
+            .into_iter()
+            .find(|platform| platform.provider().is_detected())
+            .unwrap_or(CiPlatform::Local);
+
+        info!("Detected CI platform: {}.", platform);
+        platform
+    }
+
+    pub fn parse_env_variables(&self) -> Result<CiMetadata, Box<dyn std::error::Error>> {
+        self.provider().parse_env_variables()
+    }
 }
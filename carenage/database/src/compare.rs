@@ -0,0 +1,287 @@
+use crate::summary::{select_summary_from_dimension, Summary};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/* Impacts of the host over each time window: summed over the samples of a process sampled at each
+ * tick, they are the impacts of a run whatever the count of processes sampled alongside it. */
+pub const DEFAULT_COMPARED_METRICS: [&str; 2] = [
+    "interval_primary_energy_consumed_mj",
+    "interval_operational_emission_kgc02eq",
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub metric: String,
+    pub base: f64,
+    pub head: f64,
+    pub delta: f64,
+    pub relative_delta: Option<f64>,
+}
+
+impl MetricComparison {
+    pub fn new(metric: &str, base: f64, head: f64) -> Self {
+        let delta = head - base;
+        /* A relative delta is meaningless without a base value to compare to. */
+        let relative_delta = if base == 0.0 {
+            None
+        } else {
+            Some(delta / base.abs())
+        };
+        MetricComparison {
+            metric: metric.to_owned(),
+            base,
+            head,
+            delta,
+            relative_delta,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessComparison {
+    pub exe: String,
+    pub metrics: Vec<MetricComparison>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    pub base_pipeline_id: Uuid,
+    pub head_pipeline_id: Uuid,
+    pub overall: Vec<MetricComparison>,
+    pub processes: Vec<ProcessComparison>,
+}
+
+/* Thresholds are upper bounds on the increase of a metric from base to head: an absolute one,
+ * in the unit of the metric, and a relative one, as a fraction of the base value. */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Threshold {
+    pub max_absolute_increase: Option<f64>,
+    pub max_relative_increase: Option<f64>,
+}
+
+impl Threshold {
+    pub fn is_exceeded_by(&self, comparison: &MetricComparison) -> bool {
+        let absolute_exceeded = self
+            .max_absolute_increase
+            .is_some_and(|max_increase| comparison.delta > max_increase);
+        let relative_exceeded = self.max_relative_increase.is_some_and(|max_increase| {
+            match comparison.relative_delta {
+                Some(relative_delta) => relative_delta > max_increase,
+                None => comparison.delta > 0.0,
+            }
+        });
+        absolute_exceeded || relative_exceeded
+    }
+}
+
+type Totals = BTreeMap<String, f64>;
+
+/* Totals are the ones of the summary of a pipeline: host metrics are counted once per run rather
+ * than once per sampled process, and cumulative metrics are totalled with their last value. */
+fn overall_totals(summary: &Summary) -> Totals {
+    summary
+        .overall
+        .iter()
+        .map(|(metric, metric_summary)| (metric.clone(), metric_summary.total))
+        .collect()
+}
+
+fn totals_per_executable(summary: &Summary) -> BTreeMap<&String, Totals> {
+    let mut totals_per_executable: BTreeMap<&String, Totals> = BTreeMap::new();
+    for process in &summary.processes {
+        let totals = totals_per_executable.entry(&process.process_exe).or_default();
+        for (metric, metric_summary) in &process.metrics {
+            *totals.entry(metric.clone()).or_default() += metric_summary.total;
+        }
+    }
+    totals_per_executable
+}
+
+fn compare_totals(base: &Totals, head: &Totals) -> Vec<MetricComparison> {
+    let metrics: BTreeSet<&String> = base.keys().chain(head.keys()).collect();
+    metrics
+        .into_iter()
+        .map(|metric| {
+            MetricComparison::new(
+                metric,
+                base.get(metric).copied().unwrap_or_default(),
+                head.get(metric).copied().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+impl Comparison {
+    /* PIDs differ from one pipeline to another: processes are matched on their executable. */
+    pub fn build(
+        base_pipeline_id: Uuid,
+        base_summary: &Summary,
+        head_pipeline_id: Uuid,
+        head_summary: &Summary,
+    ) -> Self {
+        let overall = compare_totals(&overall_totals(base_summary), &overall_totals(head_summary));
+
+        let base_totals = totals_per_executable(base_summary);
+        let head_totals = totals_per_executable(head_summary);
+        let executables: BTreeSet<&String> =
+            base_totals.keys().chain(head_totals.keys()).copied().collect();
+        let processes = executables
+            .into_iter()
+            .map(|exe| ProcessComparison {
+                exe: exe.clone(),
+                metrics: compare_totals(
+                    base_totals.get(exe).unwrap_or(&Totals::new()),
+                    head_totals.get(exe).unwrap_or(&Totals::new()),
+                ),
+            })
+            .collect();
+
+        Comparison {
+            base_pipeline_id,
+            head_pipeline_id,
+            overall,
+            processes,
+        }
+    }
+
+    pub fn regressions(&self, metrics: &[String], threshold: Threshold) -> Vec<&MetricComparison> {
+        self.overall
+            .iter()
+            .filter(|comparison| metrics.contains(&comparison.metric))
+            .filter(|comparison| threshold.is_exceeded_by(comparison))
+            .collect()
+    }
+}
+
+/* A pipeline without any metrics, or an unknown one, can not be compared: None is returned. */
+pub async fn compare_pipelines(
+    db_pool: &PgPool,
+    base_pipeline_id: Uuid,
+    head_pipeline_id: Uuid,
+) -> Result<Option<Comparison>, sqlx::Error> {
+    let base_summary =
+        select_summary_from_dimension(db_pool.acquire().await?, "pipeline", base_pipeline_id)
+            .await?;
+    let head_summary =
+        select_summary_from_dimension(db_pool.acquire().await?, "pipeline", head_pipeline_id)
+            .await?;
+
+    if base_summary.overall.is_empty() || head_summary.overall.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Comparison::build(
+        base_pipeline_id,
+        &base_summary,
+        head_pipeline_id,
+        &head_summary,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::{MetricSummary, ProcessSummary};
+
+    fn metric_summary(total: f64) -> MetricSummary {
+        MetricSummary {
+            unit: None,
+            duration_s: 0.0,
+            sample_count: 1,
+            total,
+            average: total,
+            max: total,
+        }
+    }
+
+    fn summary(overall: &[(&str, f64)], processes: &[(&str, &str, f64)]) -> Summary {
+        Summary {
+            overall: overall
+                .iter()
+                .map(|(metric, total)| (metric.to_string(), metric_summary(*total)))
+                .collect(),
+            processes: processes
+                .iter()
+                .enumerate()
+                .map(|(pid, (exe, metric, total))| ProcessSummary {
+                    process_pid: pid as i32,
+                    process_exe: exe.to_string(),
+                    process_cmdline: exe.to_string(),
+                    metrics: BTreeMap::from([(metric.to_string(), metric_summary(*total))]),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn it_computes_totals_and_deltas_per_metric_overall_and_per_process() {
+        let base = summary(
+            &[("energy_consumed_wh", 8.0)],
+            &[
+                ("cargo", "energy_consumed_wh", 2.0),
+                ("cargo", "energy_consumed_wh", 2.0),
+                ("rustc", "energy_consumed_wh", 4.0),
+            ],
+        );
+        let head = summary(
+            &[("energy_consumed_wh", 10.0)],
+            &[
+                ("cargo", "energy_consumed_wh", 5.0),
+                ("rustc", "energy_consumed_wh", 4.0),
+                ("node", "energy_consumed_wh", 1.0),
+            ],
+        );
+        let comparison = Comparison::build(Uuid::nil(), &base, Uuid::max(), &head);
+
+        assert_eq!(
+            comparison.overall,
+            vec![MetricComparison::new("energy_consumed_wh", 8.0, 10.0)]
+        );
+        assert_eq!(comparison.overall[0].relative_delta, Some(0.25));
+        let exes: Vec<&str> = comparison
+            .processes
+            .iter()
+            .map(|process| process.exe.as_str())
+            .collect();
+        assert_eq!(exes, vec!["cargo", "node", "rustc"]);
+        assert_eq!(comparison.processes[0].metrics[0].delta, 1.0);
+        assert_eq!(comparison.processes[1].metrics[0].relative_delta, None);
+    }
+
+    #[test]
+    fn it_reports_regressions_exceeding_a_relative_or_absolute_threshold() {
+        let base = summary(
+            &[
+                ("interval_primary_energy_consumed_mj", 10.0),
+                ("interval_operational_emission_kgc02eq", 1.0),
+            ],
+            &[],
+        );
+        let head = summary(
+            &[
+                ("interval_primary_energy_consumed_mj", 10.5),
+                ("interval_operational_emission_kgc02eq", 1.5),
+            ],
+            &[],
+        );
+        let comparison = Comparison::build(Uuid::nil(), &base, Uuid::max(), &head);
+        let metrics: Vec<String> = DEFAULT_COMPARED_METRICS.map(String::from).to_vec();
+
+        let relative_threshold = Threshold {
+            max_relative_increase: Some(0.10),
+            ..Default::default()
+        };
+        let regressions = comparison.regressions(&metrics, relative_threshold);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].metric, "interval_operational_emission_kgc02eq");
+
+        let absolute_threshold = Threshold {
+            max_absolute_increase: Some(0.4),
+            ..Default::default()
+        };
+        assert_eq!(comparison.regressions(&metrics, absolute_threshold).len(), 2);
+        assert!(comparison.regressions(&metrics, Threshold::default()).is_empty());
+    }
+}
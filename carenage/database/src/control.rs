@@ -0,0 +1,188 @@
+use crate::energy::Energy;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub const SESSION_ENV_VAR: &str = "CARENAGE_SESSION";
+/* carenaged creates its socket once started: clients wait for it before giving up. */
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/* A stop request is answered once the last sample and the stop event are inserted. */
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/* Each carenaged process is scoped to a session, so that concurrent jobs on a runner each talk
+ * to their own daemon through their own socket. */
+pub fn socket_path(session_id: &str) -> PathBuf {
+    PathBuf::from(format!("/tmp/carenage_{}.sock", session_id))
+}
+
+/* Requests and responses are exchanged as a single line of JSON each, on a new connection to the
+ * socket of the session for every request. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Status,
+    Flush,
+    Event { label: String },
+    Pause,
+    Resume,
+    Stop,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackedProcess {
+    pub pid: i32,
+    pub exe: String,
+}
+
+/* Running totals of the session, from the samples taken since carenaged was started. Emissions
+ * are the cumulative operational emissions reported by Boagent. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub session_id: String,
+    pub project_id: Uuid,
+    pub pipeline_id: Uuid,
+    pub run_id: Uuid,
+    pub paused: bool,
+    pub sample_count: u64,
+    pub last_boagent_error: Option<String>,
+    pub tracked_processes: Vec<TrackedProcess>,
+    pub energy_wh: f64,
+    pub emissions_gco2eq: f64,
+}
+
+impl Display for SessionStatus {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let state = match self.paused {
+            true => "paused",
+            false => "sampling",
+        };
+        writeln!(f, "Session {} ({}).", self.session_id, state)?;
+        writeln!(f, "Project: {}", self.project_id)?;
+        writeln!(f, "Pipeline: {}", self.pipeline_id)?;
+        writeln!(f, "Run: {}", self.run_id)?;
+        writeln!(f, "Samples: {}", self.sample_count)?;
+        writeln!(
+            f,
+            "Last Boagent error: {}",
+            self.last_boagent_error.as_deref().unwrap_or("none")
+        )?;
+        writeln!(f, "Energy: {}", Energy::from_wh(self.energy_wh))?;
+        writeln!(f, "Emissions: {:.3} gCO2eq", self.emissions_gco2eq)?;
+        write!(f, "Tracked processes: {}", self.tracked_processes.len())?;
+        for process in &self.tracked_processes {
+            write!(f, "\n  {} {}", process.pid, process.exe)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum ControlResponse {
+    Status(SessionStatus),
+    Done,
+    Error { message: String },
+}
+
+fn connect(session_id: &str) -> Result<UnixStream, Box<dyn std::error::Error>> {
+    let path = socket_path(session_id);
+    let started_at = Instant::now();
+    loop {
+        match UnixStream::connect(&path) {
+            Ok(stream) => return Ok(stream),
+            Err(err) if started_at.elapsed() >= CONNECT_TIMEOUT => {
+                return Err(format!(
+                    "No carenaged running for session {} ({}): {}.",
+                    session_id,
+                    path.display(),
+                    err
+                )
+                .into())
+            }
+            Err(_) => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+pub fn send_request(
+    session_id: &str,
+    request: &ControlRequest,
+) -> Result<ControlResponse, Box<dyn std::error::Error>> {
+    let mut stream = connect(session_id)?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+
+    let mut request_line = serde_json::to_string(request)?;
+    request_line.push('\n');
+    stream.write_all(request_line.as_bytes())?;
+
+    let mut response_line = String::new();
+    BufReader::new(stream).read_line(&mut response_line)?;
+    if response_line.is_empty() {
+        return Err("carenaged closed the connection without responding.".into());
+    }
+
+    match serde_json::from_str(&response_line)? {
+        ControlResponse::Error { message } => Err(message.into()),
+        response => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_serializes_requests_and_responses_as_tagged_json() {
+        let request = ControlRequest::Event {
+            label: "build done".to_string(),
+        };
+        let request_json = serde_json::to_string(&request).unwrap();
+        assert_eq!(request_json, r#"{"request":"event","label":"build done"}"#);
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"request":"stop"}"#).unwrap(),
+            ControlRequest::Stop
+        );
+
+        let response = ControlResponse::Status(status());
+        let response_json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ControlResponse>(&response_json).unwrap(),
+            response
+        );
+    }
+
+    fn status() -> SessionStatus {
+        SessionStatus {
+            session_id: "1234".to_string(),
+            project_id: Uuid::nil(),
+            pipeline_id: Uuid::nil(),
+            run_id: Uuid::nil(),
+            paused: false,
+            sample_count: 12,
+            last_boagent_error: None,
+            tracked_processes: vec![TrackedProcess {
+                pid: 6042,
+                exe: "cargo".to_string(),
+            }],
+            energy_wh: 1500.0,
+            emissions_gco2eq: 42.0,
+        }
+    }
+
+    #[test]
+    fn it_prints_the_running_totals_and_tracked_processes_of_a_session() {
+        let printed_status = status().to_string();
+
+        assert!(printed_status.starts_with("Session 1234 (sampling)."));
+        assert!(printed_status.contains("Samples: 12\n"));
+        assert!(printed_status.contains("Last Boagent error: none\n"));
+        assert!(printed_status.contains("Energy: 1.500 kWh\n"));
+        assert!(printed_status.contains("Emissions: 42.000 gCO2eq\n"));
+        assert!(printed_status.ends_with("Tracked processes: 1\n  6042 cargo"));
+    }
+}
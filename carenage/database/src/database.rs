@@ -4,6 +4,7 @@ use crate::tables::{
 };
 use chrono::{DateTime, Local};
 use log::info;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Error, Value};
 use sqlx::pool::PoolConnection;
 use sqlx::postgres::PgRow;
@@ -11,7 +12,7 @@ use sqlx::types::uuid::Uuid;
 use sqlx::Row;
 use sqlx::{PgPool, Postgres};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Ids {
     pub project_id: Uuid,
     pub workflow_id: Uuid,
@@ -33,15 +34,31 @@ pub struct Record {
     pub value: f64,
 }
 
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VcsRecord {
+    pub commit_sha: Option<String>,
+    pub ref_name: Option<String>,
+    pub merge_request_iid: Option<i32>,
+    pub tag: Option<String>,
+    pub commit_timestamp: Option<DateTime<Local>>,
+    pub pipeline_url: Option<String>,
+}
+
 pub async fn get_db_connection_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
     let connection_pool = PgPool::connect(database_url);
 
     connection_pool.await
 }
 
-pub fn to_datetime_local(timestamp_str: &str) -> chrono::DateTime<Local> {
+pub fn parse_datetime_local(timestamp_str: &str) -> Option<chrono::DateTime<Local>> {
     DateTime::parse_from_str(timestamp_str, "%Y-%m-%d %H:%M:%S%.9f %:z")
-        .expect("It should be a parsable string to be converted to an ISO8601 timestamp with local timezone.").into()
+        .ok()
+        .map(|datetime| datetime.into())
+}
+
+pub fn to_datetime_local(timestamp_str: &str) -> chrono::DateTime<Local> {
+    parse_datetime_local(timestamp_str)
+        .expect("It should be a parsable string to be converted to an ISO8601 timestamp with local timezone.")
 }
 
 pub fn format_hardware_data(
@@ -185,6 +202,12 @@ pub fn collect_processes(
     Ok(processes)
 }
 
+/* Rows are given an ID by the database, unless their metadata holds one: carenaged generates the
+ * IDs of the metadata it spools. */
+fn metadata_id(data: &Value) -> Option<Uuid> {
+    data["id"].as_str().and_then(|id| Uuid::parse_str(id).ok())
+}
+
 pub async fn insert_dimension_table_metadata(
     database_connection: PoolConnection<Postgres>,
     table: &str,
@@ -201,18 +224,51 @@ pub async fn insert_dimension_table_metadata(
     let mut connection = database_connection.detach();
 
     let insert_query = format!(
-        "INSERT INTO {} (name, start_date) VALUES ($1, $2) RETURNING *",
+        "INSERT INTO {} (id, name, start_date) VALUES (COALESCE($3, gen_random_uuid()), $1, $2) RETURNING *",
         table
     );
 
     let row = sqlx::query(&insert_query)
         .bind(name)
         .bind(start_timestamptz)
+        .bind(metadata_id(&data))
         .fetch_one(&mut connection)
         .await?;
     Ok(row)
 }
 
+pub async fn insert_pipeline_metadata(
+    database_connection: PoolConnection<Postgres>,
+    data: Value,
+) -> Result<PgRow, sqlx::Error> {
+    let name = data["name"].as_str();
+    let start_date = data
+        .get("start_date")
+        .expect("Unable to read timestamp.")
+        .as_str()
+        .expect("Unable to read string.");
+    let start_timestamptz = to_datetime_local(start_date);
+    let commit_timestamptz = data["commit_timestamp"].as_str().map(to_datetime_local);
+
+    let mut connection = database_connection.detach();
+
+    let row = sqlx::query(
+        "INSERT INTO pipelines (id, name, start_date, commit_sha, ref_name, merge_request_iid, tag, commit_timestamp, pipeline_url) VALUES (COALESCE($9, gen_random_uuid()), $1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+    )
+    .bind(name)
+    .bind(start_timestamptz)
+    .bind(data["commit_sha"].as_str())
+    .bind(data["ref_name"].as_str())
+    .bind(data["merge_request_iid"].as_i64().map(|iid| iid as i32))
+    .bind(data["tag"].as_str())
+    .bind(commit_timestamptz)
+    .bind(data["pipeline_url"].as_str())
+    .bind(metadata_id(&data))
+    .fetch_one(&mut connection)
+    .await?;
+    Ok(row)
+}
+
 pub async fn insert_device_metadata(
     database_connection: PoolConnection<Postgres>,
     device_data: Value,
@@ -226,12 +282,12 @@ pub async fn insert_device_metadata(
 
     let mut connection = database_connection.detach();
 
-    let formatted_query =
-        "INSERT INTO devices (name, lifetime, location) VALUES ($1, $2, $3) RETURNING *";
+    let formatted_query = "INSERT INTO devices (id, name, lifetime, location) VALUES (COALESCE($4, gen_random_uuid()), $1, $2, $3) RETURNING *";
     let device_row = sqlx::query(formatted_query)
         .bind(device_name)
         .bind(device_lifetime)
         .bind(device_location)
+        .bind(metadata_id(&device_data))
         .fetch_one(&mut connection)
         .await?;
 
@@ -350,26 +406,193 @@ pub async fn check_process_existence_for_id(
     Ok(b)
 }
 
+/* Function aggregating the values of a metric sampled within a same time bucket. */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    #[default]
+    Avg,
+    Max,
+    Min,
+    Sum,
+}
+
+impl Aggregation {
+    pub fn parse_str(aggregation_str: &str) -> Result<Aggregation, Box<dyn std::error::Error>> {
+        match aggregation_str {
+            "avg" => Ok(Aggregation::Avg),
+            "max" => Ok(Aggregation::Max),
+            "min" => Ok(Aggregation::Min),
+            "sum" => Ok(Aggregation::Sum),
+            _ => Err(format!("Unknown aggregation: {}.", aggregation_str).into()),
+        }
+    }
+
+    fn sql_function(&self) -> &'static str {
+        match self {
+            Aggregation::Avg => "AVG",
+            Aggregation::Max => "MAX",
+            Aggregation::Min => "MIN",
+            Aggregation::Sum => "SUM",
+        }
+    }
+}
+
+/* Width of a time bucket in seconds, parsed from durations such as "30s", "1m" or "1h". */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bucket {
+    pub seconds: i64,
+    pub aggregation: Aggregation,
+}
+
+impl Bucket {
+    pub fn parse_str(
+        bucket_str: &str,
+        aggregation: Aggregation,
+    ) -> Result<Bucket, Box<dyn std::error::Error>> {
+        let unit_index = bucket_str
+            .find(|character: char| !character.is_ascii_digit())
+            .ok_or_else(|| format!("Missing unit in bucket: {}.", bucket_str))?;
+        let (count, unit) = bucket_str.split_at(unit_index);
+        let unit_seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            _ => return Err(format!("Unknown unit in bucket: {}.", bucket_str).into()),
+        };
+        let seconds = count
+            .parse::<i64>()?
+            .checked_mul(unit_seconds)
+            .ok_or_else(|| format!("Bucket too large: {}.", bucket_str))?;
+        if seconds <= 0 {
+            return Err(format!("Empty bucket: {}.", bucket_str).into());
+        }
+
+        Ok(Bucket {
+            seconds,
+            aggregation,
+        })
+    }
+}
+
+/* Restricts the metrics selected for a dimension: empty fields select everything. Bounds of the
+ * time range are inclusive, and `limit` caps the number of records, the earliest ones being kept
+ * whatever their process. With a bucket, the values of
+ * each metric are aggregated per bucket, timestamped with the start of the bucket. */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsFilter {
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    pub metrics: Vec<String>,
+    pub exe: Option<String>,
+    pub pid: Option<i32>,
+    pub limit: Option<u32>,
+    pub bucket: Option<Bucket>,
+}
+
 pub async fn select_metrics_from_dimension(
     database_connection: PoolConnection<Postgres>,
     dimension: &str,
     dimension_id: Uuid,
+) -> Result<Vec<Record>, sqlx::Error> {
+    select_filtered_metrics_from_dimension(
+        database_connection,
+        dimension,
+        dimension_id,
+        &MetricsFilter::default(),
+    )
+    .await
+}
+
+pub async fn select_filtered_metrics_from_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+    filter: &MetricsFilter,
 ) -> Result<Vec<Record>, sqlx::Error> {
     let mut connection = database_connection.detach();
 
+    let (timestamp_column, value_column, grouping) = match filter.bucket {
+        Some(bucket) => (
+            "date_bin(make_interval(secs => $8), events.timestamp, 'epoch'::timestamptz)",
+            format!("{}(metrics.value)", bucket.aggregation.sql_function()),
+            "GROUP BY 1, processes.pid, processes.exe, processes.cmdline, processes.id, metrics.metric",
+        ),
+        None => ("events.timestamp", "metrics.value".to_string(), ""),
+    };
+
     let formatted_query = format!(
-        "SELECT DISTINCT events.timestamp, processes.pid, processes.exe, processes.cmdline, processes.id, metrics.metric, metrics.value FROM PROCESSES INNER JOIN EVENTS ON events.process_id = processes.id INNER JOIN METRICS ON metrics.event_id = events.id WHERE events.{}_id=($1) ORDER BY processes.id, events.timestamp, metrics.metric",
-        dimension
+        "SELECT DISTINCT {timestamp_column} AS timestamp, processes.pid, processes.exe, processes.cmdline, processes.id, metrics.metric, {value_column} AS value FROM PROCESSES INNER JOIN EVENTS ON events.process_id = processes.id INNER JOIN METRICS ON metrics.event_id = events.id WHERE events.{dimension}_id=($1)
+        AND ($2::timestamptz IS NULL OR events.timestamp >= $2)
+        AND ($3::timestamptz IS NULL OR events.timestamp <= $3)
+        AND (cardinality($4::text[]) = 0 OR metrics.metric = ANY($4))
+        AND ($5::text IS NULL OR processes.exe = $5)
+        AND ($6::integer IS NULL OR processes.pid = $6)
+        {grouping}
+        ORDER BY timestamp, processes.id, metrics.metric
+        LIMIT $7"
     );
 
     let records: Vec<Record> = sqlx::query_as(&formatted_query)
         .bind(dimension_id)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(&filter.metrics)
+        .bind(&filter.exe)
+        .bind(filter.pid)
+        .bind(filter.limit.map(i64::from))
+        .bind(filter.bucket.map(|bucket| bucket.seconds as f64))
         .fetch_all(&mut connection)
         .await?;
 
     Ok(records)
 }
 
+/* Metrics of the samples taken after the first custom event labeled `from_label`, up to the
+ * first custom event labeled `to_label` that follows it. None is returned if a label is missing. */
+pub async fn select_metrics_between_labels(
+    database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+    from_label: &str,
+    to_label: &str,
+) -> Result<Option<Vec<Record>>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let from_timestamp: Option<DateTime<Local>> = sqlx::query_scalar(
+        "SELECT MIN(events.timestamp) FROM EVENTS WHERE events.run_id = ($1) AND events.event_type = 'custom' AND events.user_label = ($2)",
+    )
+    .bind(run_id)
+    .bind(from_label)
+    .fetch_one(&mut connection)
+    .await?;
+    let Some(from_timestamp) = from_timestamp else {
+        return Ok(None);
+    };
+
+    let to_timestamp: Option<DateTime<Local>> = sqlx::query_scalar(
+        "SELECT MIN(events.timestamp) FROM EVENTS WHERE events.run_id = ($1) AND events.event_type = 'custom' AND events.user_label = ($2) AND events.timestamp > ($3)",
+    )
+    .bind(run_id)
+    .bind(to_label)
+    .bind(from_timestamp)
+    .fetch_one(&mut connection)
+    .await?;
+    let Some(to_timestamp) = to_timestamp else {
+        return Ok(None);
+    };
+
+    let records: Vec<Record> = sqlx::query_as(
+        "SELECT DISTINCT events.timestamp, processes.pid, processes.exe, processes.cmdline, processes.id, metrics.metric, metrics.value FROM PROCESSES INNER JOIN EVENTS ON events.process_id = processes.id INNER JOIN METRICS ON metrics.event_id = events.id WHERE events.run_id = ($1) AND events.timestamp > ($2) AND events.timestamp < ($3) ORDER BY processes.id, events.timestamp, metrics.metric",
+    )
+    .bind(run_id)
+    .bind(from_timestamp)
+    .bind(to_timestamp)
+    .fetch_all(&mut connection)
+    .await?;
+
+    Ok(Some(records))
+}
+
 pub async fn select_project_name_from_dimension(
     database_connection: PoolConnection<Postgres>,
     dimension: &str,
@@ -387,6 +610,24 @@ pub async fn select_project_name_from_dimension(
     Ok(project_row)
 }
 
+/* Projects and workflows span several pipelines: the VCS context of the latest one is returned. */
+pub async fn select_vcs_from_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+) -> Result<Option<VcsRecord>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let formatted_query = format!("SELECT DISTINCT pipelines.commit_sha, pipelines.ref_name, pipelines.merge_request_iid, pipelines.tag, pipelines.commit_timestamp, pipelines.pipeline_url, pipelines.start_date FROM PIPELINES INNER JOIN EVENTS ON events.pipeline_id = pipelines.id WHERE events.{}_id=($1) ORDER BY pipelines.start_date DESC LIMIT 1", dimension);
+
+    let vcs_record: Option<VcsRecord> = sqlx::query_as(&formatted_query)
+        .bind(dimension_id)
+        .fetch_optional(&mut connection)
+        .await?;
+
+    Ok(vcs_record)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -422,4 +663,17 @@ LIFETIME=5
         let converted_string = to_datetime_local(dt_local_timestamp.to_string().as_str());
         assert_eq!(dt_local_timestamp, converted_string);
     }
+
+    #[test]
+    fn it_parses_buckets_in_seconds_minutes_and_hours() {
+        assert_eq!(Bucket::parse_str("30s", Aggregation::Avg).unwrap().seconds, 30);
+        assert_eq!(Bucket::parse_str("1m", Aggregation::Max).unwrap().seconds, 60);
+        assert_eq!(Bucket::parse_str("2h", Aggregation::Sum).unwrap().seconds, 7200);
+        assert!(Bucket::parse_str("0s", Aggregation::Avg).is_err());
+        assert!(Bucket::parse_str("30", Aggregation::Avg).is_err());
+        assert!(Bucket::parse_str("m", Aggregation::Avg).is_err());
+        assert!(Bucket::parse_str("1d", Aggregation::Avg).is_err());
+        assert!(Bucket::parse_str("-5s", Aggregation::Avg).is_err());
+        assert!(Bucket::parse_str("9223372036854775807h", Aggregation::Avg).is_err());
+    }
 }
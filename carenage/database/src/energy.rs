@@ -0,0 +1,120 @@
+use crate::attribution::OTHER_PROCESSES_EXE;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+use std::fmt::{Display, Formatter};
+
+/* Scaphandre reports the power consumed by processes in microwatts. */
+pub const MICROWATTS_PER_WATT: f64 = 1_000_000.0;
+const SECONDS_PER_HOUR: f64 = 3600.0;
+const WATT_HOURS_PER_KILOWATT_HOUR: f64 = 1000.0;
+
+/* Energy is the one of the processes attributed to the CI job: the bucket of processes outside of
+ * it, sampled with `--other`, is left out. The condition applies to rows of metrics joined to their
+ * events. */
+pub fn attributed_energy_condition() -> String {
+    format!(
+        "metric = 'energy_consumed_wh' AND process_id NOT IN (SELECT id FROM PROCESSES WHERE pid = 0 AND exe = '{}')",
+        OTHER_PROCESSES_EXE
+    )
+}
+
+pub fn interval_energy_wh(power_w: f64, interval_duration_s: f64) -> f64 {
+    power_w * interval_duration_s / SECONDS_PER_HOUR
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EnergyUnit {
+    #[serde(rename = "Wh")]
+    WattHour,
+    #[serde(rename = "kWh")]
+    KilowattHour,
+}
+
+impl Display for EnergyUnit {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            EnergyUnit::WattHour => {
+                write!(f, "Wh")
+            }
+            EnergyUnit::KilowattHour => {
+                write!(f, "kWh")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Energy {
+    pub value: f64,
+    pub unit: EnergyUnit,
+}
+
+impl Energy {
+    /* Energy is stored in Wh, and reported in kWh from a thousand Wh on. */
+    pub fn from_wh(energy_wh: f64) -> Self {
+        if energy_wh.abs() >= WATT_HOURS_PER_KILOWATT_HOUR {
+            Energy {
+                value: energy_wh / WATT_HOURS_PER_KILOWATT_HOUR,
+                unit: EnergyUnit::KilowattHour,
+            }
+        } else {
+            Energy {
+                value: energy_wh,
+                unit: EnergyUnit::WattHour,
+            }
+        }
+    }
+}
+
+impl Display for Energy {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{:.3} {}", self.value, self.unit)
+    }
+}
+
+/* Power is integrated over the dimension by summing the energy of each sample. */
+pub async fn select_energy_from_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+) -> Result<Energy, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let formatted_query = format!(
+        "SELECT COALESCE(SUM(metrics.value), 0) FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.{}_id=($1) AND {}",
+        dimension,
+        attributed_energy_condition()
+    );
+
+    let energy_wh: f64 = sqlx::query_scalar(&formatted_query)
+        .bind(dimension_id)
+        .fetch_one(&mut connection)
+        .await?;
+
+    Ok(Energy::from_wh(energy_wh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_integrates_power_over_an_interval_in_watt_hours() {
+        assert_eq!(interval_energy_wh(36.0, 10.0), 0.1);
+        assert_eq!(interval_energy_wh(0.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn it_reports_energy_in_kilowatt_hours_from_a_thousand_watt_hours() {
+        assert_eq!(
+            Energy::from_wh(999.0),
+            Energy {
+                value: 999.0,
+                unit: EnergyUnit::WattHour
+            }
+        );
+        assert_eq!(Energy::from_wh(2500.0).to_string(), "2.500 kWh");
+    }
+}
@@ -1,13 +1,17 @@
-use sqlx::postgres::Postgres;
-use sqlx::Row;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgArguments, Postgres};
+use sqlx::query::Query;
+use sqlx::{PgConnection, Row};
 use sqlx::{pool::PoolConnection, postgres::PgRow};
 use std::fmt::{Display, Formatter};
 use uuid::Uuid;
 
 use crate::database::Ids;
 
-#[derive(sqlx::Type, Default, Clone, Copy, Debug)]
+#[derive(sqlx::Type, Default, Clone, Copy, Debug, Serialize, Deserialize)]
 #[sqlx(type_name = "event_type", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum EventType {
     #[default]
     Regular,
@@ -35,8 +39,12 @@ impl Display for EventType {
     }
 }
 
-#[derive(Debug)]
+/* Events are identified and timestamped when built rather than when inserted: a spooled event
+ * keeps the time it was sampled at, and replaying it twice inserts it once. */
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
+    pub id: Uuid,
+    pub timestamp: DateTime<Local>,
     pub project_id: Uuid,
     pub workflow_id: Uuid,
     pub pipeline_id: Uuid,
@@ -46,13 +54,19 @@ pub struct Event {
     pub process_id: Uuid,
     pub device_id: Uuid,
     pub event_type: EventType,
+    pub user_label: Option<String>,
 }
 
+const INSERT_EVENT_QUERY: &str = "INSERT INTO events (id, timestamp, project_id, workflow_id, pipeline_id, job_id, run_id, task_id, process_id, device_id, event_type, user_label) 
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)";
+
 pub struct EventBuilder(Event);
 
 impl EventBuilder {
     pub fn new(ids: Ids, event_type: EventType) -> Self {
         EventBuilder(Event {
+            id: Uuid::new_v4(),
+            timestamp: Local::now(),
             project_id: ids.project_id,
             workflow_id: ids.workflow_id,
             pipeline_id: ids.pipeline_id,
@@ -62,23 +76,64 @@ impl EventBuilder {
             process_id: ids.process_id,
             device_id: ids.device_id,
             event_type,
+            user_label: None,
         })
     }
+    /* Samples taken at the same tick share its timestamp, for host metrics to be counted once per
+     * tick. */
+    pub fn timestamp(mut self, timestamp: DateTime<Local>) -> Self {
+        self.0.timestamp = timestamp;
+        self
+    }
+    pub fn user_label(mut self, user_label: &str) -> Self {
+        self.0.user_label = Some(user_label.to_owned());
+        self
+    }
     pub fn build(self) -> Event {
         self.0
     }
 }
 
 impl Event {
+    pub fn ids(&self) -> Ids {
+        Ids {
+            project_id: self.project_id,
+            workflow_id: self.workflow_id,
+            pipeline_id: self.pipeline_id,
+            job_id: self.job_id,
+            run_id: self.run_id,
+            task_id: self.task_id,
+            device_id: self.device_id,
+            process_id: self.process_id,
+        }
+    }
+
     pub async fn insert(
         &self,
         db_connection: PoolConnection<Postgres>,
     ) -> Result<PgRow, Box<dyn std::error::Error>> {
-        let formatted_query = "INSERT INTO events (project_id, workflow_id, pipeline_id, job_id, run_id, task_id, process_id, device_id, event_type) 
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) 
-    RETURNING id";
+        let formatted_query = format!("{} RETURNING id", INSERT_EVENT_QUERY);
+
+        let event_row = self
+            .bind_to(sqlx::query(&formatted_query))
+            .fetch_one(&mut db_connection.detach())
+            .await?;
+        Ok(event_row)
+    }
+    /* Returns whether the event was inserted, false if it already was. */
+    pub async fn insert_if_absent(&self, connection: &mut PgConnection) -> Result<bool, sqlx::Error> {
+        let formatted_query = format!("{} ON CONFLICT (id) DO NOTHING", INSERT_EVENT_QUERY);
 
-        let event_row = sqlx::query(formatted_query)
+        let insertion = self
+            .bind_to(sqlx::query(&formatted_query))
+            .execute(connection)
+            .await?;
+        Ok(insertion.rows_affected() == 1)
+    }
+    fn bind_to<'q>(&'q self, query: Query<'q, Postgres, PgArguments>) -> Query<'q, Postgres, PgArguments> {
+        query
+            .bind(self.id)
+            .bind(self.timestamp)
             .bind(self.project_id)
             .bind(self.workflow_id)
             .bind(self.pipeline_id)
@@ -88,9 +143,7 @@ impl Event {
             .bind(self.process_id)
             .bind(self.device_id)
             .bind(self.event_type)
-            .fetch_one(&mut db_connection.detach())
-            .await?;
-        Ok(event_row)
+            .bind(&self.user_label)
     }
     pub fn get_id(event_row: PgRow) -> Uuid {
         let event_id: Uuid = event_row.get("id");
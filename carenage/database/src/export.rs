@@ -0,0 +1,200 @@
+use crate::database::Record;
+use crate::metrics::metric_unit;
+use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::{DateTime, Local, SecondsFormat};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ExportFormat::Csv => {
+                write!(f, "csv")
+            }
+            ExportFormat::Parquet => {
+                write!(f, "parquet")
+            }
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn parse_str(format_str: &str) -> Result<ExportFormat, Box<dyn std::error::Error>> {
+        match format_str {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("Unknown export format: {}.", format_str).into()),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<ExportFormat> {
+        [ExportFormat::Csv, ExportFormat::Parquet]
+            .into_iter()
+            .find(|format| format.content_type() == content_type)
+    }
+}
+
+/* Records are exported in long format, one metric value of a process per row. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportRow {
+    pub timestamp: DateTime<Local>,
+    pub pid: i32,
+    pub exe: String,
+    pub cmdline: String,
+    pub metric: String,
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+impl From<&Record> for ExportRow {
+    fn from(record: &Record) -> Self {
+        ExportRow {
+            timestamp: record.timestamp,
+            pid: record.pid,
+            exe: record.exe.clone(),
+            cmdline: record.cmdline.clone(),
+            metric: record.metric.clone(),
+            value: record.value,
+            unit: metric_unit(&record.metric).map(String::from),
+        }
+    }
+}
+
+pub fn write_csv<W: Write>(records: &[Record], writer: W) -> Result<(), Box<dyn std::error::Error>> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(["timestamp", "pid", "exe", "cmdline", "metric", "value", "unit"])?;
+    for row in records.iter().map(ExportRow::from) {
+        csv_writer.write_record([
+            row.timestamp.to_rfc3339_opts(SecondsFormat::Micros, false),
+            row.pid.to_string(),
+            row.exe,
+            row.cmdline,
+            row.metric,
+            row.value.to_string(),
+            row.unit.unwrap_or_default(),
+        ])?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/* Timestamps are stored in UTC, with a microsecond precision like in the database. */
+pub fn write_parquet<W: Write + Send>(
+    records: &[Record],
+    writer: W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("pid", DataType::Int32, false),
+        Field::new("exe", DataType::Utf8, false),
+        Field::new("cmdline", DataType::Utf8, false),
+        Field::new("metric", DataType::Utf8, false),
+        Field::new("value", DataType::Float64, false),
+        Field::new("unit", DataType::Utf8, true),
+    ]));
+    let rows: Vec<ExportRow> = records.iter().map(ExportRow::from).collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            TimestampMicrosecondArray::from_iter_values(
+                rows.iter().map(|row| row.timestamp.timestamp_micros()),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(Int32Array::from_iter_values(rows.iter().map(|row| row.pid))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|row| &row.exe))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|row| &row.cmdline))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|row| &row.metric))),
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.value))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|row| row.unit.as_deref()))),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let mut parquet_writer = ArrowWriter::try_new(writer, schema, None)?;
+    parquet_writer.write(&batch)?;
+    parquet_writer.close()?;
+    Ok(())
+}
+
+pub fn export_records(
+    records: &[Record],
+    format: ExportFormat,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut exported = vec![];
+    match format {
+        ExportFormat::Csv => write_csv(records, &mut exported)?,
+        ExportFormat::Parquet => write_parquet(records, &mut exported)?,
+    }
+    Ok(exported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                timestamp: Local::now(),
+                pid: 6042,
+                exe: "/usr/bin/cargo".to_string(),
+                cmdline: "cargo build, --release".to_string(),
+                metric: "cpu_usage_percentage".to_string(),
+                value: 12.5,
+            },
+            Record {
+                timestamp: Local::now(),
+                pid: 6042,
+                exe: "/usr/bin/cargo".to_string(),
+                cmdline: "cargo build, --release".to_string(),
+                metric: "regular".to_string(),
+                value: 1.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn it_exports_records_as_csv_rows_in_long_format() {
+        let exported = String::from_utf8(export_records(&records(), ExportFormat::Csv).unwrap()).unwrap();
+        let lines: Vec<&str> = exported.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,pid,exe,cmdline,metric,value,unit");
+        assert!(lines[1].ends_with(",6042,/usr/bin/cargo,\"cargo build, --release\",cpu_usage_percentage,12.5,%"));
+        assert!(lines[2].ends_with(",regular,1,"));
+    }
+
+    #[test]
+    fn it_exports_records_as_a_parquet_file() {
+        let export_path = std::env::temp_dir().join(format!("carenage-{}.parquet", uuid::Uuid::new_v4()));
+        write_parquet(&records(), std::fs::File::create(&export_path).unwrap()).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&export_path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+        assert_eq!(metadata.file_metadata().schema_descr().num_columns(), 7);
+        std::fs::remove_file(export_path).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use crate::database::Ids;
+use crate::event::Event;
+#[cfg(feature = "export")]
+use crate::export::ExportFormat;
+use crate::spool::{RecordSink, Sample, SessionMetadata, SpoolRecord, SESSION_DIMENSIONS};
+use crate::tables::{CarenageRow, Process};
+use log::info;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ingested {
+    pub id: Uuid,
+}
+
+/* A session is registered once the metadata of its dimensions and its device are: the process of
+ * carenage is inserted, with the given ID if any, and the IDs events are attached to are returned. */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRegistration {
+    pub project_id: Uuid,
+    pub workflow_id: Uuid,
+    pub pipeline_id: Uuid,
+    pub job_id: Uuid,
+    pub run_id: Uuid,
+    pub task_id: Uuid,
+    pub device_id: Uuid,
+    pub process: Process,
+    #[serde(default)]
+    pub process_id: Option<Uuid>,
+}
+
+pub struct IngestionClient {
+    api_url: String,
+    api_token: String,
+    client: Client,
+}
+
+impl IngestionClient {
+    pub fn new(api_url: &str, api_token: &str) -> Self {
+        IngestionClient {
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            api_token: api_token.to_owned(),
+            client: Client::new(),
+        }
+    }
+
+    async fn post<T: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<R, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .post(format!("{}/ingest/{}", self.api_url, path))
+            .bearer_auth(&self.api_token)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn post_dimension(
+        &self,
+        row: &CarenageRow,
+        metadata: &Value,
+    ) -> Result<Uuid, Box<dyn std::error::Error>> {
+        let path = format!("dimensions/{}", row.table_name());
+        let ingested: Ingested = self.post(&path, metadata).await?;
+        info!("Sent {} metadata to the API.", row.table_name());
+        Ok(ingested.id)
+    }
+
+    pub async fn post_device(&self, inventory: &Value) -> Result<Uuid, Box<dyn std::error::Error>> {
+        let ingested: Ingested = self.post("devices", inventory).await?;
+        info!("Sent device inventory to the API.");
+        Ok(ingested.id)
+    }
+
+    pub async fn post_session(
+        &self,
+        registration: &SessionRegistration,
+    ) -> Result<Ids, Box<dyn std::error::Error>> {
+        self.post("sessions", registration).await
+    }
+
+    pub async fn post_event(&self, event: &Event) -> Result<Uuid, Box<dyn std::error::Error>> {
+        let ingested: Ingested = self.post("events", event).await?;
+        Ok(ingested.id)
+    }
+
+    pub async fn post_metrics(
+        &self,
+        samples: &[&Sample],
+    ) -> Result<Vec<Uuid>, Box<dyn std::error::Error>> {
+        let ingested: Vec<Ingested> = self.post("metrics", samples).await?;
+        info!("Sent a batch of {} samples to the API.", ingested.len());
+        Ok(ingested.into_iter().map(|ingested| ingested.id).collect())
+    }
+
+    /* Metrics of a run are exported by the API, as when requested with "?format=". */
+    #[cfg(feature = "export")]
+    pub async fn get_run_export(
+        &self,
+        run_id: Uuid,
+        format: ExportFormat,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .get(format!("{}/runs/{}", self.api_url, run_id))
+            .query(&[("format", format.to_string())])
+            .bearer_auth(&self.api_token)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn post_batch(&self, batch: &mut Vec<&Sample>) -> Result<(), Box<dyn std::error::Error>> {
+        if !batch.is_empty() {
+            self.post_metrics(batch).await?;
+            batch.clear();
+        }
+        Ok(())
+    }
+}
+
+/* Samples taken in a row are sent as a single batch, events in between are sent on their own to
+ * keep the order of the records. */
+impl RecordSink for IngestionClient {
+    async fn send(&self, records: &[SpoolRecord]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut batch: Vec<&Sample> = vec![];
+        for record in records {
+            match record {
+                SpoolRecord::Sample(sample) => batch.push(sample),
+                SpoolRecord::Metadata(metadata) => {
+                    self.post_batch(&mut batch).await?;
+                    self.register(metadata).await?;
+                }
+                SpoolRecord::Event { event } | SpoolRecord::Stop { event } => {
+                    self.post_batch(&mut batch).await?;
+                    self.post_event(event).await?;
+                }
+            }
+        }
+        self.post_batch(&mut batch).await?;
+        Ok(())
+    }
+
+    /* Metadata is sent as serialized for the database, with the IDs generated by carenaged: the API
+     * returns the rows already registered by a previous attempt rather than inserting them again.
+     * Dimensions other than the project are sent along with the ID of the project. */
+    async fn register(
+        &self,
+        metadata: &SessionMetadata,
+    ) -> Result<Ids, Box<dyn std::error::Error>> {
+        let ids = metadata.ids;
+        let spooled_dimension_ids = [
+            ids.project_id,
+            ids.workflow_id,
+            ids.pipeline_id,
+            ids.job_id,
+            ids.run_id,
+            ids.task_id,
+        ];
+        let mut dimension_ids: Vec<Uuid> = vec![];
+        for ((row, dimension), id) in SESSION_DIMENSIONS
+            .iter()
+            .zip(&metadata.dimensions)
+            .zip(spooled_dimension_ids)
+        {
+            let mut dimension = dimension.clone();
+            dimension["id"] = json!(id);
+            if let Some(project_id) = dimension_ids.first() {
+                dimension["project_id"] = json!(project_id);
+            }
+            dimension_ids.push(self.post_dimension(row, &dimension).await?);
+        }
+        let mut device = metadata.device.clone();
+        device["id"] = json!(ids.device_id);
+        let device_id = self.post_device(&device).await?;
+
+        let registration = SessionRegistration {
+            project_id: dimension_ids[0],
+            workflow_id: dimension_ids[1],
+            pipeline_id: dimension_ids[2],
+            job_id: dimension_ids[3],
+            run_id: dimension_ids[4],
+            task_id: dimension_ids[5],
+            device_id,
+            process: metadata.process.clone(),
+            process_id: Some(ids.process_id),
+        };
+        self.post_session(&registration).await
+    }
+}
@@ -5,3 +5,17 @@ pub mod ci;
 pub mod event;
 pub mod tables;
 pub mod metrics;
+pub mod attribution;
+pub mod compare;
+pub mod budget;
+pub mod energy;
+pub mod sci;
+pub mod control;
+pub mod spool;
+pub mod ingest;
+pub mod tokens;
+pub mod listing;
+pub mod summary;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod report;
@@ -0,0 +1,229 @@
+use crate::energy::attributed_energy_condition;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+
+pub const DEFAULT_PAGE_LIMIT: u32 = 20;
+pub const MAX_PAGE_LIMIT: u32 = 100;
+
+/* Tables of the dimensions that can be listed, with the column of events referring to them. */
+const DIMENSIONS: [(&str, &str); 6] = [
+    ("projects", "project"),
+    ("workflows", "workflow"),
+    ("pipelines", "pipeline"),
+    ("jobs", "job"),
+    ("runs", "run"),
+    ("tasks", "task"),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    StartDate,
+    Name,
+    EnergyWh,
+}
+
+impl SortKey {
+    /* Values missing for a dimension are sorted as the lowest ones, so that keys are never NULL
+     * and can be compared with the cursor. */
+    fn expression(&self) -> (&'static str, &'static str) {
+        match self {
+            SortKey::StartDate => ("COALESCE(start_date, 'epoch'::timestamptz)", "timestamptz"),
+            SortKey::Name => ("COALESCE(name, '')", "text"),
+            SortKey::EnergyWh => ("energy_wh", "float8"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct ListParams {
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default)]
+    pub order: SortOrder,
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+/* A cursor points to the last item of a page: the next page starts right after its sort key. It
+ * is hex encoded, to be passed as is in a query string. */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub id: Uuid,
+    pub sort_value: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{}|{}", self.id, self.sort_value)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(cursor_str: &str) -> Result<Cursor, Box<dyn std::error::Error>> {
+        if !cursor_str.is_ascii() || !cursor_str.len().is_multiple_of(2) {
+            return Err("Invalid cursor.".into());
+        }
+        let bytes = (0..cursor_str.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&cursor_str[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let decoded = String::from_utf8(bytes)?;
+        let (id, sort_value) = decoded.split_once('|').ok_or("Invalid cursor.")?;
+
+        Ok(Cursor {
+            id: Uuid::parse_str(id)?,
+            sort_value: sort_value.to_owned(),
+        })
+    }
+}
+
+/* Emissions reported by Boagent are computed from the start of each run: the emissions of a
+ * dimension are the sum of the last value of each of its runs. */
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DimensionSummary {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub start_date: Option<DateTime<Local>>,
+    pub stop_date: Option<DateTime<Local>>,
+    pub energy_wh: f64,
+    pub emissions_gco2eq: f64,
+    pub run_count: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct SummaryRow {
+    #[sqlx(flatten)]
+    summary: DimensionSummary,
+    sort_value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/* Dimensions are listed either by their IDs, or as the children of another dimension: the ones
+ * events of the parent refer to. */
+#[derive(Clone, Debug, PartialEq)]
+pub enum DimensionFilter {
+    Ids(Vec<Uuid>),
+    Parent { dimension: String, id: Uuid },
+}
+
+fn dimension_column(table: &str) -> Result<&'static str, Box<dyn std::error::Error>> {
+    DIMENSIONS
+        .iter()
+        .find(|(dimension_table, _)| *dimension_table == table)
+        .map(|(_, column)| *column)
+        .ok_or_else(|| format!("Unknown dimension: {}.", table).into())
+}
+
+pub async fn select_dimension_summaries(
+    database_connection: PoolConnection<Postgres>,
+    table: &str,
+    filter: &DimensionFilter,
+    params: &ListParams,
+) -> Result<Page<DimensionSummary>, Box<dyn std::error::Error>> {
+    let column = dimension_column(table)?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let (sort_expression, sort_type) = params.sort.expression();
+    let (direction, comparison) = match params.order {
+        SortOrder::Asc => ("ASC", ">"),
+        SortOrder::Desc => ("DESC", "<"),
+    };
+    let filter_condition = match filter {
+        DimensionFilter::Ids(_) => format!("{table}.id = ANY($1)"),
+        DimensionFilter::Parent { dimension, .. } => {
+            if !DIMENSIONS.iter().any(|(_, parent_column)| parent_column == dimension) {
+                return Err(format!("Unknown dimension: {}.", dimension).into());
+            }
+            format!("{table}.id IN (SELECT DISTINCT events.{column}_id FROM EVENTS WHERE events.{dimension}_id = ($1))")
+        }
+    };
+    let attributed_energy = attributed_energy_condition();
+
+    let formatted_query = format!(
+        "SELECT *, {sort_expression}::text AS sort_value FROM (
+          SELECT {table}.id, {table}.name, {table}.start_date, {table}.stop_date,
+            COALESCE((SELECT SUM(metrics.value) FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.{column}_id = {table}.id AND {attributed_energy}), 0) AS energy_wh,
+            COALESCE((SELECT SUM(run_emissions.value) FROM (SELECT DISTINCT ON (events.run_id) metrics.value FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.{column}_id = {table}.id AND metrics.metric = 'total_operational_emission_kgc02eq' ORDER BY events.run_id, events.timestamp DESC) AS run_emissions), 0) * 1000 AS emissions_gco2eq,
+            (SELECT COUNT(DISTINCT events.run_id) FROM EVENTS WHERE events.{column}_id = {table}.id) AS run_count
+          FROM {table} WHERE {filter_condition}
+        ) AS summaries
+        WHERE ($2::text IS NULL OR ({sort_expression}, id) {comparison} ($2::{sort_type}, $3))
+        ORDER BY {sort_expression} {direction}, id {direction}
+        LIMIT $4"
+    );
+
+    let query = sqlx::query_as::<_, SummaryRow>(&formatted_query);
+    let query = match filter {
+        DimensionFilter::Ids(ids) => query.bind(ids.clone()),
+        DimensionFilter::Parent { id, .. } => query.bind(*id),
+    };
+
+    let mut connection = database_connection.detach();
+    let mut rows = query
+        .bind(cursor.as_ref().map(|cursor| cursor.sort_value.clone()))
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(i64::from(limit) + 1)
+        .fetch_all(&mut connection)
+        .await?;
+
+    /* One more item than the limit is fetched, to know whether there is a next page. */
+    let next_cursor = match rows.len() > limit as usize {
+        true => {
+            rows.truncate(limit as usize);
+            rows.last().map(|row| {
+                Cursor {
+                    id: row.summary.id,
+                    sort_value: row.sort_value.clone(),
+                }
+                .encode()
+            })
+        }
+        false => None,
+    };
+
+    Ok(Page {
+        items: rows.into_iter().map(|row| row.summary).collect(),
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_an_encoded_cursor_and_rejects_invalid_ones() {
+        let cursor = Cursor {
+            id: Uuid::new_v4(),
+            sort_value: "2024-11-05 11:19:22.783871+00".to_string(),
+        };
+
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(Cursor::decode("abc").is_err());
+        assert!(Cursor::decode("zz").is_err());
+        assert!(Cursor::decode("aéb").is_err());
+        assert!(Cursor::decode(&Cursor::encode(&cursor)[..20]).is_err());
+    }
+}
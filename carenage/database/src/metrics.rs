@@ -1,10 +1,11 @@
+use crate::energy::{interval_energy_wh, MICROWATTS_PER_WATT};
 use log::info;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use serde_with::with_prefix;
 use sqlx::pool::PoolConnection;
 use sqlx::types::Uuid;
-use sqlx::Postgres;
+use sqlx::{PgConnection, Postgres};
 
 pub trait Metric {
     fn build(&self, process_embedded_impacts: &Value) -> ProcessEmbeddedImpactValues;
@@ -82,6 +83,106 @@ impl Metric for ProcessEmbeddedImpacts {
     }
 }
 
+fn last_consumers(boagent_response: &Value) -> &Vec<Value> {
+    boagent_response["raw_data"]["power_data"]["raw_data"]
+        .as_array()
+        .expect("Data from Scaphandre should be parsable.")
+        .last()
+        .expect("Last timestamp from Scaphandre should be parsable.")
+        .get("consumers")
+        .expect("Data on processes should be present from Scaphandre.")
+        .as_array()
+        .expect("Data on processes should be parsable.")
+}
+
+fn resource_usage(resources: &Map<String, Value>, resource: &str) -> f64 {
+    resources
+        .get(resource)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .parse::<f64>()
+        .unwrap()
+}
+
+fn process_power(process: &Value) -> f64 {
+    process["consumption"].as_f64().unwrap_or_default() / MICROWATTS_PER_WATT
+}
+
+/* Operational impacts computed by Boagent over the queried time window. Summed over the windows
+ * of a run, they are the cumulative impacts of the run up to the last window. */
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OperationalTotals {
+    pub emission_kgc02eq: f64,
+    pub abiotic_resources_depletion_kgsbeq: f64,
+    pub primary_energy_consumed_mj: f64,
+}
+
+impl OperationalTotals {
+    pub fn from_boagent_response(boagent_response: &Value) -> Self {
+        OperationalTotals {
+            emission_kgc02eq: boagent_response["total_operational_emissions"]["value"]["value"]
+                .as_f64()
+                .unwrap(),
+            abiotic_resources_depletion_kgsbeq: boagent_response
+                ["total_operational_abiotic_resources_depletion"]["value"]["value"]
+                .as_f64()
+                .unwrap(),
+            primary_energy_consumed_mj: boagent_response
+                ["total_operational_primary_energy_consumed"]["value"]["value"]
+                .as_f64()
+                .unwrap(),
+        }
+    }
+
+    pub fn add(&mut self, interval_totals: OperationalTotals) {
+        self.emission_kgc02eq += interval_totals.emission_kgc02eq;
+        self.abiotic_resources_depletion_kgsbeq += interval_totals.abiotic_resources_depletion_kgsbeq;
+        self.primary_energy_consumed_mj += interval_totals.primary_energy_consumed_mj;
+    }
+}
+
+/* Metrics computed for the whole host at each sample, stored alike for every process sampled. */
+pub const HOST_METRICS: [&str; 12] = [
+    "total_operational_emission_kgc02eq",
+    "total_operational_abiotic_resources_depletion_kgsbeq",
+    "total_primary_energy_consumed_mj",
+    "interval_operational_emission_kgc02eq",
+    "interval_operational_abiotic_resources_depletion_kgsbeq",
+    "interval_primary_energy_consumed_mj",
+    "average_power_measured_w",
+    "electricity_carbon_intensity_kgc02eq_per_kwh",
+    "interval_duration_s",
+    "embedded_emissions_kgc02eq",
+    "embedded_abiotic_resources_depletion_kgsbeq",
+    "embedded_primary_energy_mj",
+];
+
+/* Units are the suffixes of the names of the metrics. */
+const METRIC_UNITS: [(&str, &str); 9] = [
+    ("_kgc02eq_per_kwh", "kgCO2eq/kWh"),
+    ("_kgc02eq", "kgCO2eq"),
+    ("_kgsbeq", "kgSbeq"),
+    ("_mj", "MJ"),
+    ("_wh", "Wh"),
+    ("_w", "W"),
+    ("_bytes", "B"),
+    ("_percentage", "%"),
+    ("_s", "s"),
+];
+
+pub fn metric_unit(metric: &str) -> Option<&'static str> {
+    METRIC_UNITS
+        .iter()
+        .find(|(suffix, _)| metric.ends_with(suffix))
+        .map(|(_, unit)| *unit)
+}
+
+/* Totals accumulated since the start of a run, as opposed to values of a single sample. */
+pub fn is_cumulative_metric(metric: &str) -> bool {
+    metric.starts_with("total_")
+}
+
 with_prefix!(prefix_cpu "cpu_");
 with_prefix!(prefix_ram "ram_");
 with_prefix!(prefix_ssd "ssd_");
@@ -105,7 +206,14 @@ pub struct Metrics {
     pub total_operational_emission_kgc02eq: f64,
     pub total_operational_abiotic_resources_depletion_kgsbeq: f64,
     pub total_primary_energy_consumed_mj: f64,
+    pub interval_operational_emission_kgc02eq: f64,
+    pub interval_operational_abiotic_resources_depletion_kgsbeq: f64,
+    pub interval_primary_energy_consumed_mj: f64,
     pub average_power_measured_w: f64,
+    pub electricity_carbon_intensity_kgc02eq_per_kwh: f64,
+    pub process_power_measured_w: f64,
+    pub interval_duration_s: f64,
+    pub energy_consumed_wh: f64,
     pub embedded_emissions_kgc02eq: f64,
     pub embedded_abiotic_resources_depletion_kgsbeq: f64,
     pub embedded_primary_energy_mj: f64,
@@ -115,15 +223,7 @@ impl Metrics {
     pub fn build(process_data: &Value, boagent_response: &Value) -> Self {
         let pid = process_data.get("pid").expect("PID should be present.");
 
-        let queried_process: Vec<&Value> = boagent_response["raw_data"]["power_data"]["raw_data"]
-            .as_array()
-            .expect("Data from Scaphandre should be parsable.")
-            .last()
-            .expect("Last timestamp from Scaphandre should be parsable.")
-            .get("consumers")
-            .expect("Data on processes should be present from Scaphandre.")
-            .as_array()
-            .expect("Data on processes should be parsable.")
+        let queried_process: Vec<&Value> = last_consumers(boagent_response)
             .iter()
             .filter(|&process| process["pid"] == *pid)
             .collect();
@@ -160,53 +260,59 @@ impl Metrics {
             ),
             process_ssd_embedded_impacts,
             process_hdd_embedded_impacts,
-            cpu_usage_percentage: resources
-                .get("cpu_usage")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            memory_usage_bytes: resources
-                .get("memory_usage")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            memory_virtual_usage_bytes: resources
-                .get("memory_virtual_usage")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            disk_usage_write_bytes: resources
-                .get("disk_usage_write")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            disk_usage_read_bytes: resources
-                .get("disk_usage_read")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .parse::<f64>()
-                .unwrap(),
-            total_operational_emission_kgc02eq: boagent_response["total_operational_emissions"]
-                ["value"]["value"]
-                .as_f64()
-                .unwrap(),
-            total_operational_abiotic_resources_depletion_kgsbeq: boagent_response
-                ["total_operational_abiotic_resources_depletion"]["value"]["value"]
-                .as_f64()
-                .unwrap(),
-            total_primary_energy_consumed_mj: boagent_response
-                ["total_operational_primary_energy_consumed"]["value"]["value"]
-                .as_f64()
-                .unwrap(),
+            cpu_usage_percentage: resource_usage(resources, "cpu_usage"),
+            memory_usage_bytes: resource_usage(resources, "memory_usage"),
+            memory_virtual_usage_bytes: resource_usage(resources, "memory_virtual_usage"),
+            disk_usage_write_bytes: resource_usage(resources, "disk_usage_write"),
+            disk_usage_read_bytes: resource_usage(resources, "disk_usage_read"),
+            process_power_measured_w: process_power(queried_process[0]),
+            ..Metrics::build_host_metrics(boagent_response)
+        }
+    }
+
+    /* Processes filtered out of the attribution to a CI job are aggregated into a single set of
+     * metrics: resources usages and powers are summed, with no embedded impacts. */
+    pub fn build_aggregate(pids: &[i32], boagent_response: &Value) -> Self {
+        let aggregated_processes = last_consumers(boagent_response)
+            .iter()
+            .filter(|&process| {
+                process["pid"]
+                    .as_i64()
+                    .is_some_and(|pid| pids.contains(&(pid as i32)))
+            });
+
+        let mut metrics = Metrics::build_host_metrics(boagent_response);
+        for process in aggregated_processes {
+            let resources = process["resources_usage"]
+                .as_object()
+                .expect("Data on ressources usage by process should be present.");
+            metrics.process_power_measured_w += process_power(process);
+            metrics.cpu_usage_percentage += resource_usage(resources, "cpu_usage");
+            metrics.memory_usage_bytes += resource_usage(resources, "memory_usage");
+            metrics.memory_virtual_usage_bytes += resource_usage(resources, "memory_virtual_usage");
+            metrics.disk_usage_write_bytes += resource_usage(resources, "disk_usage_write");
+            metrics.disk_usage_read_bytes += resource_usage(resources, "disk_usage_read");
+        }
+        metrics
+    }
+
+    /* The energy of a sample is the power of the process integrated over the interval since the
+     * previous sample. */
+    pub fn with_interval(mut self, interval_duration_s: f64) -> Self {
+        self.interval_duration_s = interval_duration_s;
+        self.energy_consumed_wh = interval_energy_wh(self.process_power_measured_w, interval_duration_s);
+        self
+    }
+
+    /* Without cumulative totals tracked across windows, the totals are the ones of the queried
+     * window. */
+    fn build_host_metrics(boagent_response: &Value) -> Self {
+        let interval_totals = OperationalTotals::from_boagent_response(boagent_response);
+        Metrics {
+            interval_operational_emission_kgc02eq: interval_totals.emission_kgc02eq,
+            interval_operational_abiotic_resources_depletion_kgsbeq: interval_totals
+                .abiotic_resources_depletion_kgsbeq,
+            interval_primary_energy_consumed_mj: interval_totals.primary_energy_consumed_mj,
             embedded_emissions_kgc02eq: boagent_response["embedded_emissions"]["value"]
                 .as_f64()
                 .unwrap(),
@@ -220,15 +326,37 @@ impl Metrics {
             average_power_measured_w: boagent_response["average_power_measured"]["value"]
                 .as_f64()
                 .unwrap(),
+            electricity_carbon_intensity_kgc02eq_per_kwh: boagent_response
+                ["electricity_carbon_intensity"]["value"]
+                .as_f64()
+                .unwrap(),
+            ..Default::default()
         }
+        .with_cumulative_totals(interval_totals)
     }
+
+    pub fn with_cumulative_totals(mut self, cumulative_totals: OperationalTotals) -> Self {
+        self.total_operational_emission_kgc02eq = cumulative_totals.emission_kgc02eq;
+        self.total_operational_abiotic_resources_depletion_kgsbeq =
+            cumulative_totals.abiotic_resources_depletion_kgsbeq;
+        self.total_primary_energy_consumed_mj = cumulative_totals.primary_energy_consumed_mj;
+        self
+    }
+
     pub async fn insert(
         &self,
         event_id: Uuid,
         db_connection: PoolConnection<Postgres>,
     ) -> Result<(), sqlx::Error> {
-        let mut connection = db_connection.detach();
+        self.insert_with_connection(event_id, &mut db_connection.detach())
+            .await
+    }
 
+    pub async fn insert_with_connection(
+        &self,
+        event_id: Uuid,
+        connection: &mut PgConnection,
+    ) -> Result<(), sqlx::Error> {
         let metrics_value = serde_json::to_value(self).expect("Metrics should be deserializable.");
         let iterable_metrics = metrics_value
             .as_object()
@@ -250,7 +378,7 @@ impl Metrics {
             .bind(event_id)
             .bind(metric_fields)
             .bind(metric_values)
-            .execute(&mut connection)
+            .execute(connection)
             .await?;
 
         info!("Inserted metrics.");
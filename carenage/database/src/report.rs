@@ -0,0 +1,159 @@
+use crate::budget::{select_run_totals, Verdict};
+use sqlx::pool::PoolConnection;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+
+/* Totals of a run written to the report artifacts of a CI job once it is stopped. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunReport {
+    pub energy_wh: f64,
+    pub emissions_gco2eq: f64,
+    pub primary_energy_mj: f64,
+    pub peak_power_w: f64,
+}
+
+/* Energy and emissions are the totals budgets are checked against. Like emissions, primary energy
+ * reported by Boagent is accumulated since its start, and peak power is the highest power measured
+ * on the host. */
+pub async fn select_run_report(
+    mut database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+) -> Result<RunReport, sqlx::Error> {
+    let (primary_energy_mj, peak_power_w): (f64, f64) = sqlx::query_as(
+        "SELECT COALESCE((SELECT metrics.value FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.run_id = runs.id AND metrics.metric = 'total_primary_energy_consumed_mj' ORDER BY events.timestamp DESC LIMIT 1), 0) AS primary_energy_mj, COALESCE((SELECT MAX(metrics.value) FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.run_id = runs.id AND metrics.metric = 'average_power_measured_w'), 0) AS peak_power_w FROM RUNS WHERE runs.id = ($1)",
+    )
+    .bind(run_id)
+    .fetch_one(&mut *database_connection)
+    .await?;
+    let run_totals = select_run_totals(database_connection, run_id).await?;
+
+    Ok(RunReport {
+        energy_wh: run_totals.energy_wh,
+        emissions_gco2eq: run_totals.emissions_gco2eq,
+        primary_energy_mj,
+        peak_power_w,
+    })
+}
+
+/* GitLab shows the metrics of a `metrics.txt` report artifact in merge requests, compared to the
+ * ones of the target branch: one metric per line, its name followed by its value. */
+pub fn render_metrics_report(run_report: &RunReport) -> String {
+    [
+        ("carenage_energy_wh", run_report.energy_wh),
+        ("carenage_emissions_gco2eq", run_report.emissions_gco2eq),
+        ("carenage_primary_energy_mj", run_report.primary_energy_mj),
+        ("carenage_peak_power_w", run_report.peak_power_w),
+    ]
+    .iter()
+    .map(|(metric, value)| format!("{} {}\n", metric, value))
+    .collect()
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/* Each check of the budget is a test case of a JUnit report, failed when its limit is exceeded,
+ * so that GitLab lists them in the tests of the pipeline and of merge requests. */
+pub fn render_junit_report(verdict: &Verdict, job_name: &str) -> String {
+    let checks = verdict.checks();
+    let failures = checks.iter().filter(|check| check.exceeded).count();
+    let classname = escape_xml(&format!("carenage.budget.{}", job_name));
+
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    report.push_str(&format!(
+        "<testsuites name=\"carenage\" tests=\"{}\" failures=\"{}\">\n",
+        checks.len(),
+        failures
+    ));
+    report.push_str(&format!(
+        "  <testsuite name=\"carenage budget\" tests=\"{}\" failures=\"{}\">\n",
+        checks.len(),
+        failures
+    ));
+    for check in checks {
+        let outcome = format!(
+            "{} {:.3} {} {} the budget of {} {}",
+            check.name,
+            check.value,
+            check.unit,
+            match check.exceeded {
+                true => "exceeds",
+                false => "is within",
+            },
+            check.limit,
+            check.unit
+        );
+        report.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\">\n",
+            classname, check.name
+        ));
+        match check.exceeded {
+            true => report.push_str(&format!(
+                "      <failure message=\"{}\" type=\"budget\">{}</failure>\n",
+                escape_xml(&outcome),
+                escape_xml(&outcome)
+            )),
+            false => report.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape_xml(&outcome)
+            )),
+        }
+        report.push_str("    </testcase>\n");
+    }
+    report.push_str("  </testsuite>\n</testsuites>\n");
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::{Limits, RunTotals};
+
+    #[test]
+    fn it_renders_the_totals_of_a_run_as_a_gitlab_metrics_report() {
+        let run_report = RunReport {
+            energy_wh: 12.5,
+            emissions_gco2eq: 3.25,
+            primary_energy_mj: 0.5,
+            peak_power_w: 42.0,
+        };
+
+        assert_eq!(
+            render_metrics_report(&run_report),
+            "carenage_energy_wh 12.5\ncarenage_emissions_gco2eq 3.25\ncarenage_primary_energy_mj 0.5\ncarenage_peak_power_w 42\n"
+        );
+    }
+
+    #[test]
+    fn it_renders_a_junit_test_case_per_budget_check() {
+        let verdict = Verdict::evaluate(
+            Limits {
+                energy_wh: Some(50.0),
+                emissions_gco2eq: Some(10.0),
+            },
+            RunTotals {
+                energy_wh: 51.0,
+                emissions_gco2eq: 2.0,
+            },
+        );
+
+        let report = render_junit_report(&verdict, "integration-tests");
+
+        assert!(report.contains("<testsuites name=\"carenage\" tests=\"2\" failures=\"1\">"));
+        assert!(report.contains(
+            "<testcase classname=\"carenage.budget.integration-tests\" name=\"energy\">\n      <failure message=\"energy 51.000 Wh exceeds the budget of 50 Wh\" type=\"budget\">"
+        ));
+        assert!(report.contains(
+            "<testcase classname=\"carenage.budget.integration-tests\" name=\"emissions\">\n      <system-out>emissions 2.000 gCO2eq is within the budget of 10 gCO2eq</system-out>"
+        ));
+
+        let unbudgeted = Verdict::evaluate(Limits::default(), verdict.totals);
+        assert!(render_junit_report(&unbudgeted, "build").contains("tests=\"0\" failures=\"0\""));
+    }
+}
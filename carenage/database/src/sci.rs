@@ -0,0 +1,158 @@
+use crate::energy::attributed_energy_condition;
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgRow;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+use std::fmt::{Display, Formatter};
+
+/* Runs for which no functional unit has been declared are counted as a single run. */
+pub const DEFAULT_FUNCTIONAL_UNIT: &str = "run";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunctionalUnit {
+    pub name: String,
+    pub count: f64,
+}
+
+impl FunctionalUnit {
+    pub fn new(name: &str, count: f64) -> Result<FunctionalUnit, Box<dyn std::error::Error>> {
+        if count <= 0.0 || !count.is_finite() {
+            return Err(format!("Count of functional units should be positive, got {}.", count).into());
+        }
+        Ok(FunctionalUnit {
+            name: name.to_owned(),
+            count,
+        })
+    }
+}
+
+/* Software Carbon Intensity, as specified by the Green Software Foundation: SCI = (E * I + M) per
+ * R, with E the energy consumed in kWh, I the carbon intensity of the electricity in gCO2eq/kWh,
+ * M the embodied emissions of the hardware for the time it was used, and R the functional unit. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sci {
+    pub energy_kwh: f64,
+    pub carbon_intensity_gco2eq_per_kwh: f64,
+    pub embodied_emissions_gco2eq: f64,
+    pub functional_unit: FunctionalUnit,
+    pub score_gco2eq: f64,
+}
+
+impl Sci {
+    pub fn compute(
+        energy_kwh: f64,
+        carbon_intensity_gco2eq_per_kwh: f64,
+        embodied_emissions_gco2eq: f64,
+        functional_unit: FunctionalUnit,
+    ) -> Self {
+        let score_gco2eq = (energy_kwh * carbon_intensity_gco2eq_per_kwh
+            + embodied_emissions_gco2eq)
+            / functional_unit.count;
+        Sci {
+            energy_kwh,
+            carbon_intensity_gco2eq_per_kwh,
+            embodied_emissions_gco2eq,
+            functional_unit,
+            score_gco2eq,
+        }
+    }
+}
+
+impl Display for Sci {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "SCI: {:.3} gCO2eq per {}, over {} functional units.",
+            self.score_gco2eq, self.functional_unit.name, self.functional_unit.count
+        )
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SciInputs {
+    energy_kwh: f64,
+    carbon_intensity_gco2eq_per_kwh: f64,
+    embodied_emissions_gco2eq: f64,
+    functional_units: Vec<String>,
+    functional_unit_count: f64,
+}
+
+pub async fn insert_functional_unit(
+    database_connection: PoolConnection<Postgres>,
+    run_id: Uuid,
+    functional_unit: &FunctionalUnit,
+) -> Result<PgRow, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let row = sqlx::query(
+        "UPDATE runs SET functional_unit = ($1), functional_unit_count = ($2) WHERE id = ($3) RETURNING *",
+    )
+    .bind(&functional_unit.name)
+    .bind(functional_unit.count)
+    .bind(run_id)
+    .fetch_one(&mut connection)
+    .await?;
+
+    Ok(row)
+}
+
+/* Energy and embodied emissions are summed over the samples of the dimension, the carbon
+ * intensity of the electricity is averaged. Functional units are summed over the runs of the
+ * dimension: runs declaring different functional units can not be aggregated, None is returned. */
+pub async fn select_sci_from_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+) -> Result<Option<Sci>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let attributed_energy = attributed_energy_condition();
+    let formatted_query = format!(
+        "WITH dimension_metrics AS (SELECT metrics.metric, metrics.value, events.process_id FROM METRICS INNER JOIN EVENTS ON metrics.event_id = events.id WHERE events.{dimension}_id=($1)), dimension_runs AS (SELECT runs.functional_unit, runs.functional_unit_count FROM RUNS WHERE runs.id IN (SELECT DISTINCT events.run_id FROM EVENTS WHERE events.{dimension}_id=($1)))
+        SELECT
+          COALESCE((SELECT SUM(value) FROM dimension_metrics WHERE {attributed_energy}), 0) / 1000 AS energy_kwh,
+          COALESCE((SELECT AVG(value) FROM dimension_metrics WHERE metric = 'electricity_carbon_intensity_kgc02eq_per_kwh'), 0) * 1000 AS carbon_intensity_gco2eq_per_kwh,
+          COALESCE((SELECT SUM(value) FROM dimension_metrics WHERE metric IN ('cpu_gwp_average_impact_kgc02eq', 'ram_gwp_average_impact_kgc02eq', 'ssd_gwp_average_impact_kgc02eq', 'hdd_gwp_average_impact_kgc02eq')), 0) * 1000 AS embodied_emissions_gco2eq,
+          ARRAY(SELECT DISTINCT COALESCE(functional_unit, '{DEFAULT_FUNCTIONAL_UNIT}') FROM dimension_runs) AS functional_units,
+          COALESCE((SELECT SUM(COALESCE(functional_unit_count, 1)) FROM dimension_runs), 0) AS functional_unit_count"
+    );
+
+    let inputs: SciInputs = sqlx::query_as(&formatted_query)
+        .bind(dimension_id)
+        .fetch_one(&mut connection)
+        .await?;
+
+    let functional_unit = match inputs.functional_units.as_slice() {
+        [name] => FunctionalUnit::new(name, inputs.functional_unit_count).ok(),
+        _ => None,
+    };
+
+    Ok(functional_unit.map(|functional_unit| {
+        Sci::compute(
+            inputs.energy_kwh,
+            inputs.carbon_intensity_gco2eq_per_kwh,
+            inputs.embodied_emissions_gco2eq,
+            functional_unit,
+        )
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_the_sci_score_per_functional_unit() {
+        let sci = Sci::compute(0.5, 380.0, 10.0, FunctionalUnit::new("test", 100.0).unwrap());
+
+        assert_eq!(sci.score_gco2eq, 2.0);
+        assert_eq!(sci.to_string(), "SCI: 2.000 gCO2eq per test, over 100 functional units.");
+    }
+
+    #[test]
+    fn it_rejects_a_non_positive_count_of_functional_units() {
+        assert!(FunctionalUnit::new("test", 0.0).is_err());
+        assert!(FunctionalUnit::new("1000 requests", -1.0).is_err());
+    }
+}
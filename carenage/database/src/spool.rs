@@ -0,0 +1,477 @@
+use crate::database::{
+    check_process_existence_for_id, get_process_id, get_project_id, insert_device_metadata,
+    insert_dimension_table_metadata, insert_pipeline_metadata, update_stop_date, Ids,
+};
+use crate::event::Event;
+use crate::metrics::Metrics;
+use crate::tables::{CarenageRow, Process};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use reqwest::StatusCode;
+use sqlx::error::ErrorKind;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::process;
+use uuid::Uuid;
+
+const SPOOL_EXTENSION: &str = "jsonl";
+const UPLOADING_EXTENSION: &str = "uploading";
+const REJECTED_EXTENSION: &str = "rejected";
+const REGISTRATIONS_FILE: &str = "registrations.json";
+
+/* A sample of a process: its metrics over the time window ending at the time of the event. The
+ * process is resolved to its ID when inserted. */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sample {
+    pub event: Event,
+    pub process: Process,
+    pub metrics: Metrics,
+}
+
+/* Metadata of a session that could not be registered when carenaged started: IDs are generated by
+ * carenaged, and the rows inserted with them once uploaded. Dimensions are serialized in the order
+ * of `SESSION_DIMENSIONS`. */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub ids: Ids,
+    pub dimensions: Vec<Value>,
+    pub device: Value,
+    pub process: Process,
+}
+
+pub const SESSION_DIMENSIONS: [CarenageRow; 6] = [
+    CarenageRow::Project,
+    CarenageRow::Workflow,
+    CarenageRow::Pipeline,
+    CarenageRow::Job,
+    CarenageRow::Run,
+    CarenageRow::Task,
+];
+
+/* What carenaged inserts after registering the metadata of the run, as a line of JSON when
+ * spooled. Dimensions of the run are closed at the time of the stop event. Spooled metadata comes
+ * first, as the records following it refer to its IDs. */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum SpoolRecord {
+    Metadata(Box<SessionMetadata>),
+    Event { event: Event },
+    Sample(Box<Sample>),
+    Stop { event: Event },
+}
+
+/* Where records are sent: the database, or the ingestion API of carenage. Registering spooled
+ * metadata returns the IDs the session ends up with, which may differ from the generated ones. */
+#[allow(async_fn_in_trait)]
+pub trait RecordSink {
+    async fn send(&self, records: &[SpoolRecord]) -> Result<(), Box<dyn std::error::Error>>;
+    async fn register(
+        &self,
+        metadata: &SessionMetadata,
+    ) -> Result<Ids, Box<dyn std::error::Error>>;
+}
+
+impl RecordSink for PgPool {
+    async fn send(&self, records: &[SpoolRecord]) -> Result<(), Box<dyn std::error::Error>> {
+        for record in records {
+            record.insert(self).await?;
+        }
+        Ok(())
+    }
+
+    async fn register(
+        &self,
+        metadata: &SessionMetadata,
+    ) -> Result<Ids, Box<dyn std::error::Error>> {
+        metadata.insert(self).await
+    }
+}
+
+pub fn is_unique_violation(err: &(dyn std::error::Error + 'static)) -> bool {
+    err.downcast_ref::<sqlx::Error>()
+        .and_then(|err| err.as_database_error())
+        .is_some_and(|err| matches!(err.kind(), ErrorKind::UniqueViolation))
+}
+
+/* A record the sink refuses, because it breaks a constraint of the database, holds invalid data, or
+ * is answered with a client error by the API, is set aside. Any other error, such as a sink that
+ * can not be reached or refuses the token, stops the upload: the spool is replayed by the next one. */
+fn is_rejection(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<sqlx::Error>() {
+        return err
+            .as_database_error()
+            .and_then(|err| err.code())
+            .is_some_and(|code| code.starts_with("22") || code.starts_with("23"));
+    }
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return err.status().is_some_and(|status| {
+            status.is_client_error()
+                && ![
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::REQUEST_TIMEOUT,
+                    StatusCode::TOO_MANY_REQUESTS,
+                ]
+                .contains(&status)
+        });
+    }
+    false
+}
+
+impl SessionMetadata {
+    /* Rows already inserted by a previous upload are left as they are. Projects being unique by
+     * name, a project registered meanwhile keeps its ID, returned in place of the generated one. */
+    pub async fn insert(&self, db_pool: &PgPool) -> Result<Ids, Box<dyn std::error::Error>> {
+        let mut ids = self.ids;
+        let dimension_ids = [
+            ids.project_id,
+            ids.workflow_id,
+            ids.pipeline_id,
+            ids.job_id,
+            ids.run_id,
+            ids.task_id,
+        ];
+        for ((row, metadata), id) in SESSION_DIMENSIONS
+            .iter()
+            .zip(&self.dimensions)
+            .zip(dimension_ids)
+        {
+            let mut metadata = metadata.clone();
+            metadata["id"] = json!(id);
+            let connection = db_pool.acquire().await?;
+            let insert_attempt = match row {
+                CarenageRow::Pipeline => insert_pipeline_metadata(connection, metadata.clone()).await,
+                _ => {
+                    insert_dimension_table_metadata(connection, row.table_name(), metadata.clone())
+                        .await
+                }
+            };
+            match insert_attempt {
+                Err(err) if is_unique_violation(&err) => {
+                    if let (CarenageRow::Project, Some(name)) = (row, metadata["name"].as_str()) {
+                        ids.project_id =
+                            get_project_id(db_pool.acquire().await?, &name.to_string()).await?;
+                    }
+                }
+                insert_attempt => {
+                    insert_attempt?;
+                }
+            }
+        }
+
+        let mut device = self.device.clone();
+        device["id"] = json!(ids.device_id);
+        match insert_device_metadata(db_pool.acquire().await?, device).await {
+            Err(err) if is_unique_violation(&err) => {}
+            insert_attempt => {
+                insert_attempt?;
+            }
+        }
+
+        match self
+            .process
+            .insert_with_id(Some(ids.process_id), db_pool.acquire().await?)
+            .await
+        {
+            Err(err) if is_unique_violation(&*err) => {}
+            insert_attempt => {
+                insert_attempt?;
+            }
+        }
+        info!("Inserted spooled metadata of run {}.", ids.run_id);
+        Ok(ids)
+    }
+}
+
+async fn get_or_insert_process_id(
+    db_pool: &PgPool,
+    process: &Process,
+    run_id: Uuid,
+) -> Result<Uuid, Box<dyn std::error::Error>> {
+    let process_metadata_already_registered =
+        check_process_existence_for_id(db_pool.acquire().await?, process, "run", run_id).await?;
+
+    let process_id = match process_metadata_already_registered {
+        true => get_process_id(db_pool.acquire().await?, process, "run", run_id).await?,
+        false => {
+            let process_row = Process::insert(process, db_pool.acquire().await?).await?;
+            Process::get_id(process_row)
+        }
+    };
+    Ok(process_id)
+}
+
+impl SpoolRecord {
+    pub fn event(&self) -> Option<&Event> {
+        match self {
+            SpoolRecord::Metadata(_) => None,
+            SpoolRecord::Event { event } | SpoolRecord::Stop { event } => Some(event),
+            SpoolRecord::Sample(sample) => Some(&sample.event),
+        }
+    }
+
+    /* Records spooled along with metadata refer to the IDs generated by carenaged, replaced by the
+     * ones the session was registered with. */
+    pub fn reattach(&mut self, spooled_ids: &Ids, registered_ids: &Ids) {
+        let event = match self {
+            SpoolRecord::Metadata(_) => return,
+            SpoolRecord::Event { event } | SpoolRecord::Stop { event } => event,
+            SpoolRecord::Sample(sample) => &mut sample.event,
+        };
+        for (id, spooled_id, registered_id) in [
+            (&mut event.project_id, spooled_ids.project_id, registered_ids.project_id),
+            (&mut event.workflow_id, spooled_ids.workflow_id, registered_ids.workflow_id),
+            (&mut event.pipeline_id, spooled_ids.pipeline_id, registered_ids.pipeline_id),
+            (&mut event.job_id, spooled_ids.job_id, registered_ids.job_id),
+            (&mut event.run_id, spooled_ids.run_id, registered_ids.run_id),
+            (&mut event.task_id, spooled_ids.task_id, registered_ids.task_id),
+            (&mut event.device_id, spooled_ids.device_id, registered_ids.device_id),
+            (&mut event.process_id, spooled_ids.process_id, registered_ids.process_id),
+        ] {
+            if *id == spooled_id {
+                *id = registered_id;
+            }
+        }
+    }
+
+    /* Inserting a record that was already inserted has no effect, so that a spool can be replayed
+     * until it is fully uploaded. The event of a sample and its metrics are inserted together. */
+    pub async fn insert(&self, db_pool: &PgPool) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            SpoolRecord::Metadata(metadata) => {
+                metadata.insert(db_pool).await?;
+            }
+            SpoolRecord::Event { event } => {
+                event.insert_if_absent(&mut *db_pool.acquire().await?).await?;
+            }
+            SpoolRecord::Sample(sample) => {
+                let mut event = sample.event.clone();
+                event.process_id =
+                    get_or_insert_process_id(db_pool, &sample.process, event.run_id).await?;
+
+                let mut transaction = db_pool.begin().await?;
+                if event.insert_if_absent(&mut transaction).await? {
+                    sample
+                        .metrics
+                        .insert_with_connection(event.id, &mut transaction)
+                        .await?;
+                }
+                transaction.commit().await?;
+            }
+            SpoolRecord::Stop { event } => {
+                event.insert_if_absent(&mut *db_pool.acquire().await?).await?;
+
+                let dimension_rows = [
+                    (CarenageRow::Project, event.project_id),
+                    (CarenageRow::Workflow, event.workflow_id),
+                    (CarenageRow::Pipeline, event.pipeline_id),
+                    (CarenageRow::Job, event.job_id),
+                    (CarenageRow::Run, event.run_id),
+                    (CarenageRow::Task, event.task_id),
+                ];
+                for (row, row_id) in dimension_rows {
+                    update_stop_date(
+                        db_pool.acquire().await?,
+                        row.table_name(),
+                        row_id,
+                        &event.timestamp.to_string(),
+                    )
+                    .await?;
+                    info!("Updated stop date for {} metadata.", row.table_name());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Spool {
+    pub path: PathBuf,
+}
+
+impl Spool {
+    /* Each carenaged process appends to a spool of its own, named after its PID. */
+    pub fn for_process(spool_dir: &Path) -> Self {
+        Spool {
+            path: spool_dir.join(format!("carenaged_{}.{}", process::id(), SPOOL_EXTENSION)),
+        }
+    }
+
+    /* The spool is opened for each record rather than kept open: once renamed by an upload,
+     * records appended afterwards go to a new spool. */
+    pub fn append(&self, record: &SpoolRecord) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(spool_dir) = self.path.parent() {
+            fs::create_dir_all(spool_dir)?;
+        }
+        append_line(&self.path, &serde_json::to_string(record)?)
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(format!("{}\n", line).as_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct Registration {
+    spooled_ids: Ids,
+    registered_ids: Ids,
+}
+
+/* IDs spooled sessions were registered with, by the run ID generated by carenaged. They are kept
+ * in the spool directory: records of a session are spooled again, after a rotation, in a spool
+ * without its metadata. */
+pub struct Registrations {
+    path: PathBuf,
+    sessions: HashMap<Uuid, Registration>,
+}
+
+impl Registrations {
+    pub fn read(spool_dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = spool_dir.join(REGISTRATIONS_FILE);
+        let sessions = match path.exists() {
+            true => serde_json::from_str(&fs::read_to_string(&path)?)?,
+            false => HashMap::new(),
+        };
+        Ok(Registrations { path, sessions })
+    }
+
+    fn insert(&mut self, spooled_ids: Ids, registered_ids: Ids) -> Result<(), Box<dyn std::error::Error>> {
+        self.sessions.insert(
+            spooled_ids.run_id,
+            Registration {
+                spooled_ids,
+                registered_ids,
+            },
+        );
+        fs::write(&self.path, serde_json::to_string(&self.sessions)?)?;
+        Ok(())
+    }
+
+    fn reattach(&self, record: &mut SpoolRecord) {
+        let registration = record
+            .event()
+            .and_then(|event| self.sessions.get(&event.run_id))
+            .copied();
+        if let Some(registration) = registration {
+            record.reattach(&registration.spooled_ids, &registration.registered_ids);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UploadSummary {
+    pub files: usize,
+    pub records: usize,
+    pub invalid_lines: usize,
+    pub rejected_records: usize,
+}
+
+impl AddAssign for UploadSummary {
+    fn add_assign(&mut self, other: UploadSummary) {
+        self.files += other.files;
+        self.records += other.records;
+        self.invalid_lines += other.invalid_lines;
+        self.rejected_records += other.rejected_records;
+    }
+}
+
+/* A line left incomplete by carenaged being killed while writing it can not be parsed: it is
+ * counted, and skipped. A record rejected by the sink is counted, and moved to a file of rejected
+ * records next to the spool, for the rest of it to be uploaded. Spooled metadata is registered
+ * before the records following it are sent. */
+pub async fn replay_spool_file(
+    sink: &impl RecordSink,
+    spool_path: &Path,
+    registrations: &mut Registrations,
+) -> Result<UploadSummary, Box<dyn std::error::Error>> {
+    let mut summary = UploadSummary {
+        files: 1,
+        ..Default::default()
+    };
+    let rejected_path = spool_path.with_extension(REJECTED_EXTENSION);
+
+    for line in fs::read_to_string(spool_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sending = match serde_json::from_str::<SpoolRecord>(line) {
+            Ok(SpoolRecord::Metadata(metadata)) => match sink.register(&metadata).await {
+                Ok(registered_ids) => registrations.insert(metadata.ids, registered_ids),
+                Err(err) => Err(err),
+            },
+            Ok(mut record) => {
+                registrations.reattach(&mut record);
+                sink.send(&[record]).await
+            }
+            Err(err) => {
+                warn!("Skipping invalid line of {}: {}", spool_path.display(), err);
+                summary.invalid_lines += 1;
+                continue;
+            }
+        };
+        match sending {
+            Ok(()) => summary.records += 1,
+            Err(err) if is_rejection(&*err) => {
+                warn!(
+                    "Moving record of {} rejected by carenage to {}: {}",
+                    spool_path.display(),
+                    rejected_path.display(),
+                    err
+                );
+                append_line(&rejected_path, line)?;
+                summary.rejected_records += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(summary)
+}
+
+/* Spools are renamed before being replayed, so that carenaged appends to a new one meanwhile, and
+ * removed once replayed. A spool left renamed by a failed upload is replayed first by the next
+ * one, before the spool carenaged went on appending to replaces it. */
+pub async fn upload_spool(
+    sink: &impl RecordSink,
+    spool_dir: &Path,
+) -> Result<UploadSummary, Box<dyn std::error::Error>> {
+    let mut summary = UploadSummary::default();
+    if !spool_dir.exists() {
+        return Ok(summary);
+    }
+    let mut registrations = Registrations::read(spool_dir)?;
+
+    let mut spool_paths: Vec<PathBuf> = fs::read_dir(spool_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    spool_paths.sort_by_key(|spool_path| {
+        let uploading = spool_path.extension().and_then(|extension| extension.to_str())
+            == Some(UPLOADING_EXTENSION);
+        (!uploading, spool_path.clone())
+    });
+
+    for spool_path in spool_paths {
+        let uploading_path = match spool_path.extension().and_then(|extension| extension.to_str()) {
+            Some(SPOOL_EXTENSION) => {
+                let uploading_path = spool_path.with_extension(UPLOADING_EXTENSION);
+                if uploading_path.exists() || fs::rename(&spool_path, &uploading_path).is_err() {
+                    continue;
+                }
+                uploading_path
+            }
+            Some(UPLOADING_EXTENSION) => spool_path,
+            _ => continue,
+        };
+
+        summary += replay_spool_file(sink, &uploading_path, &mut registrations).await?;
+        fs::remove_file(&uploading_path)?;
+        info!("Uploaded spool {}.", uploading_path.display());
+    }
+    Ok(summary)
+}
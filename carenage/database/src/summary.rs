@@ -0,0 +1,224 @@
+use crate::metrics::{is_cumulative_metric, metric_unit, HOST_METRICS};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sqlx::pool::PoolConnection;
+use sqlx::types::Uuid;
+use sqlx::Postgres;
+use std::collections::BTreeMap;
+
+/* Statistics of a metric over the samples of a dimension. The total of a metric accumulated since
+ * the start of each run is the sum of its last value in each run, other metrics are summed over
+ * their samples. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub unit: Option<String>,
+    pub duration_s: f64,
+    pub sample_count: i64,
+    pub total: f64,
+    pub average: f64,
+    pub max: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProcessSummary {
+    pub process_pid: i32,
+    pub process_exe: String,
+    pub process_cmdline: String,
+    pub metrics: BTreeMap<String, MetricSummary>,
+}
+
+/* Metrics are keyed by the names of the fields of `Metrics`. */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub overall: BTreeMap<String, MetricSummary>,
+    pub processes: Vec<ProcessSummary>,
+}
+
+/* Aggregates of the samples of a metric in a run. */
+#[derive(sqlx::FromRow, Clone, Debug)]
+struct RunAggregate {
+    metric: String,
+    first_timestamp: DateTime<Local>,
+    last_timestamp: DateTime<Local>,
+    sample_count: i64,
+    sum: f64,
+    max: f64,
+    last_value: f64,
+}
+
+/* Aggregates of the samples of a metric, for a process in a run. */
+#[derive(sqlx::FromRow, Clone, Debug)]
+struct ProcessAggregate {
+    process_id: Uuid,
+    pid: i32,
+    exe: String,
+    cmdline: String,
+    #[sqlx(flatten)]
+    run_aggregate: RunAggregate,
+}
+
+fn summarize(metric: &str, run_aggregates: &[&RunAggregate]) -> MetricSummary {
+    let sample_count: i64 = run_aggregates.iter().map(|aggregate| aggregate.sample_count).sum();
+    let sum: f64 = run_aggregates.iter().map(|aggregate| aggregate.sum).sum();
+    let first_timestamp = run_aggregates
+        .iter()
+        .map(|aggregate| aggregate.first_timestamp)
+        .min();
+    let last_timestamp = run_aggregates
+        .iter()
+        .map(|aggregate| aggregate.last_timestamp)
+        .max();
+    let duration_s = match (first_timestamp, last_timestamp) {
+        (Some(first), Some(last)) => (last - first).num_milliseconds() as f64 / 1000.0,
+        _ => 0.0,
+    };
+    let total = match is_cumulative_metric(metric) {
+        true => run_aggregates.iter().map(|aggregate| aggregate.last_value).sum(),
+        false => sum,
+    };
+
+    MetricSummary {
+        unit: metric_unit(metric).map(String::from),
+        duration_s,
+        sample_count,
+        total,
+        average: match sample_count {
+            0 => 0.0,
+            _ => sum / sample_count as f64,
+        },
+        max: run_aggregates
+            .iter()
+            .map(|aggregate| aggregate.max)
+            .fold(f64::MIN, f64::max),
+    }
+}
+
+/* Host metrics are stored alike for every process sampled at a tick, the samples of which share
+ * their timestamp: overall, host metrics are counted once per tick. */
+pub async fn select_summary_from_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+) -> Result<Summary, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let process_query = format!(
+        "SELECT processes.id AS process_id, processes.pid, processes.exe, processes.cmdline, metrics.metric,
+          MIN(events.timestamp) AS first_timestamp, MAX(events.timestamp) AS last_timestamp, COUNT(*) AS sample_count,
+          SUM(metrics.value) AS sum, MAX(metrics.value) AS max, (ARRAY_AGG(metrics.value ORDER BY events.timestamp DESC))[1] AS last_value
+        FROM PROCESSES INNER JOIN EVENTS ON events.process_id = processes.id INNER JOIN METRICS ON metrics.event_id = events.id
+        WHERE events.{}_id=($1)
+        GROUP BY processes.id, processes.pid, processes.exe, processes.cmdline, events.run_id, metrics.metric
+        ORDER BY processes.id, metrics.metric",
+        dimension
+    );
+    let overall_query = format!(
+        "SELECT samples.metric,
+          MIN(samples.timestamp) AS first_timestamp, MAX(samples.timestamp) AS last_timestamp, COUNT(*) AS sample_count,
+          SUM(samples.value) AS sum, MAX(samples.value) AS max, (ARRAY_AGG(samples.value ORDER BY samples.timestamp DESC))[1] AS last_value
+        FROM (
+          SELECT DISTINCT ON (events.run_id, events.timestamp, metrics.metric, CASE WHEN metrics.metric = ANY($2) THEN NULL ELSE events.process_id END)
+            events.run_id, events.timestamp, metrics.metric, metrics.value
+          FROM EVENTS INNER JOIN METRICS ON metrics.event_id = events.id
+          WHERE events.{}_id=($1)
+        ) AS samples
+        GROUP BY samples.run_id, samples.metric",
+        dimension
+    );
+
+    let process_aggregates: Vec<ProcessAggregate> = sqlx::query_as(&process_query)
+        .bind(dimension_id)
+        .fetch_all(&mut connection)
+        .await?;
+    let overall_aggregates: Vec<RunAggregate> = sqlx::query_as(&overall_query)
+        .bind(dimension_id)
+        .bind(&HOST_METRICS[..])
+        .fetch_all(&mut connection)
+        .await?;
+
+    let mut overall_aggregates_per_metric: BTreeMap<&str, Vec<&RunAggregate>> = BTreeMap::new();
+    for aggregate in &overall_aggregates {
+        overall_aggregates_per_metric
+            .entry(&aggregate.metric)
+            .or_default()
+            .push(aggregate);
+    }
+    let overall = overall_aggregates_per_metric
+        .iter()
+        .map(|(metric, aggregates)| (metric.to_string(), summarize(metric, aggregates)))
+        .collect();
+
+    let mut aggregates_per_process: BTreeMap<Uuid, Vec<&ProcessAggregate>> = BTreeMap::new();
+    for aggregate in &process_aggregates {
+        aggregates_per_process
+            .entry(aggregate.process_id)
+            .or_default()
+            .push(aggregate);
+    }
+    let processes = aggregates_per_process
+        .values()
+        .map(|aggregates| {
+            let mut aggregates_per_metric: BTreeMap<&str, Vec<&RunAggregate>> = BTreeMap::new();
+            for aggregate in aggregates {
+                aggregates_per_metric
+                    .entry(&aggregate.run_aggregate.metric)
+                    .or_default()
+                    .push(&aggregate.run_aggregate);
+            }
+            ProcessSummary {
+                process_pid: aggregates[0].pid,
+                process_exe: aggregates[0].exe.clone(),
+                process_cmdline: aggregates[0].cmdline.clone(),
+                metrics: aggregates_per_metric
+                    .iter()
+                    .map(|(metric, aggregates)| (metric.to_string(), summarize(metric, aggregates)))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(Summary { overall, processes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn run_aggregate(metric: &str, values: &[f64]) -> RunAggregate {
+        let first_timestamp = Local::now();
+        RunAggregate {
+            metric: metric.to_string(),
+            first_timestamp,
+            last_timestamp: first_timestamp + Duration::seconds(10 * (values.len() as i64 - 1)),
+            sample_count: values.len() as i64,
+            sum: values.iter().sum(),
+            max: values.iter().cloned().fold(f64::MIN, f64::max),
+            last_value: *values.last().unwrap(),
+        }
+    }
+
+    #[test]
+    fn it_totals_cumulative_metrics_with_the_last_value_of_each_run() {
+        let first_run = run_aggregate("total_operational_emission_kgc02eq", &[1.0, 2.0, 3.0]);
+        let second_run = run_aggregate("total_operational_emission_kgc02eq", &[4.0, 5.0]);
+
+        let summary = summarize(
+            "total_operational_emission_kgc02eq",
+            &[&first_run, &second_run],
+        );
+
+        assert_eq!(summary.total, 8.0);
+        assert_eq!(summary.sample_count, 5);
+        assert_eq!(summary.average, 3.0);
+        assert_eq!(summary.max, 5.0);
+        assert_eq!(summary.duration_s, 20.0);
+        assert_eq!(summary.unit.as_deref(), Some("kgCO2eq"));
+
+        let energy = run_aggregate("energy_consumed_wh", &[1.0, 2.0]);
+        let summary = summarize("energy_consumed_wh", &[&energy]);
+
+        assert_eq!(summary.total, 3.0);
+        assert_eq!(summary.unit.as_deref(), Some("Wh"));
+    }
+}
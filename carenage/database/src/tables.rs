@@ -1,8 +1,7 @@
 use crate::boagent::Config;
-use crate::ci::GitlabVariables;
 use crate::database::{
     format_hardware_data, get_db_connection_pool, get_project_id, insert_device_metadata,
-    insert_dimension_table_metadata
+    insert_dimension_table_metadata, insert_pipeline_metadata
 };
 use crate::timestamp::Timestamp;
 use log::{error, info};
@@ -17,7 +16,7 @@ use uuid::Uuid;
 
 pub trait Metadata {
     fn set_name(&self, config: &Config) -> String;
-    fn set_start_date(&self, start_timestamp: Timestamp) -> Timestamp;
+    fn set_start_date(&self, start_timestamp: Timestamp, config: &Config) -> Timestamp;
     fn serialize(
         &self,
         start_timestamp: Timestamp,
@@ -68,28 +67,35 @@ impl CarenageRow {
 
 impl Metadata for CarenageRow {
     fn set_name(&self, config: &Config) -> String {
-        let gitlab_vars = GitlabVariables::parse_env_variables()
-            .expect("Gitlab variables should be available to parse");
+        let ci_metadata = config
+            .ci_platform
+            .parse_env_variables()
+            .expect("CI variables should be available to parse");
         let row_name: String = match self {
-            CarenageRow::Project => gitlab_vars.project_path.to_string(),
-            CarenageRow::Workflow => format!("workflow_{}", gitlab_vars.project_path),
-            CarenageRow::Pipeline => gitlab_vars.pipeline_name,
-            CarenageRow::Job => gitlab_vars.job_name,
-            CarenageRow::Run => format!("run_{}", gitlab_vars.job_name),
-            CarenageRow::Task => gitlab_vars.job_stage,
+            CarenageRow::Project => ci_metadata.project_path.to_string(),
+            CarenageRow::Workflow => ci_metadata.workflow_name,
+            CarenageRow::Pipeline => ci_metadata.pipeline_name,
+            CarenageRow::Job => ci_metadata.job_name.clone(),
+            CarenageRow::Run => config
+                .run_label
+                .clone()
+                .unwrap_or(format!("run_{}", ci_metadata.job_name)),
+            CarenageRow::Task => ci_metadata.task_name,
             CarenageRow::Device => config.device_name.clone(),
         };
         row_name
     }
 
-    fn set_start_date(&self, start_timestamp: Timestamp) -> Timestamp {
-        let gitlab_vars = GitlabVariables::parse_env_variables()
-            .expect("Gitlab variables should be available to parse");
+    fn set_start_date(&self, start_timestamp: Timestamp, config: &Config) -> Timestamp {
+        let ci_metadata = config
+            .ci_platform
+            .parse_env_variables()
+            .expect("CI variables should be available to parse");
         let start_date: Option<Timestamp> = match self {
             CarenageRow::Project => Some(start_timestamp),
-            CarenageRow::Workflow => Some(gitlab_vars.pipeline_created_at),
+            CarenageRow::Workflow => ci_metadata.workflow_started_at.or(Some(start_timestamp)),
             CarenageRow::Pipeline => Some(start_timestamp),
-            CarenageRow::Job => Some(gitlab_vars.job_started_at),
+            CarenageRow::Job => ci_metadata.job_started_at.or(Some(start_timestamp)),
             CarenageRow::Run => Some(start_timestamp),
             CarenageRow::Task => Some(start_timestamp),
             CarenageRow::Device => None,
@@ -112,9 +118,28 @@ impl Metadata for CarenageRow {
                 config.lifetime,
             )
             .expect("Formatting of device data should succeed."),
+            CarenageRow::Pipeline => {
+                let name = self.set_name(config);
+                let start_date = self.set_start_date(start_timestamp, config);
+                let vcs = config
+                    .ci_platform
+                    .parse_env_variables()
+                    .expect("CI variables should be available to parse")
+                    .vcs;
+                json!({
+                     "name": name,
+                     "start_date": start_date.to_string(),
+                     "commit_sha": vcs.commit_sha,
+                     "ref_name": vcs.ref_name,
+                     "merge_request_iid": vcs.merge_request_iid,
+                     "tag": vcs.tag,
+                     "commit_timestamp": vcs.commit_timestamp.map(|timestamp| timestamp.to_string()),
+                     "pipeline_url": vcs.pipeline_url
+                })
+            }
             _ => {
                 let name = self.set_name(config);
-                let start_date = self.set_start_date(start_timestamp);
+                let start_date = self.set_start_date(start_timestamp, config);
                 json!({
                      "name": name,
                      "start_date": start_date.to_string()
@@ -138,8 +163,14 @@ impl Metadata for CarenageRow {
                 )
                 .await,
             ),
+            CarenageRow::Pipeline => InsertAttempt::Success(
+                insert_pipeline_metadata(
+                    db_pool.acquire().await?,
+                    self.serialize(start_timestamp, None, config),
+                )
+                .await?,
+            ),
             CarenageRow::Workflow
-            | CarenageRow::Pipeline
             | CarenageRow::Job
             | CarenageRow::Run
             | CarenageRow::Task => InsertAttempt::Success(
@@ -228,15 +259,25 @@ impl Process {
     pub async fn insert(
         &self,
         db_connection: PoolConnection<Postgres>,
+    ) -> Result<PgRow, Box<dyn std::error::Error>> {
+        self.insert_with_id(None, db_connection).await
+    }
+
+    /* The process of a session spooled by carenaged is inserted with the ID it generated. */
+    pub async fn insert_with_id(
+        &self,
+        id: Option<Uuid>,
+        db_connection: PoolConnection<Postgres>,
     ) -> Result<PgRow, Box<dyn std::error::Error>> {
         let insert_query =
-            "INSERT INTO processes (pid, exe, cmdline, state) VALUES ($1, $2, $3, $4) RETURNING id";
+            "INSERT INTO processes (id, pid, exe, cmdline, state) VALUES (COALESCE($5, gen_random_uuid()), $1, $2, $3, $4) RETURNING id";
 
         let process_row = sqlx::query(insert_query)
             .bind(self.pid)
             .bind(&self.exe)
             .bind(&self.cmdline)
             .bind(&self.state)
+            .bind(id)
             .fetch_one(&mut db_connection.detach())
             .await?;
 
@@ -258,7 +299,7 @@ pub enum CharacteristicValue {
     NumericValue(Number),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Device {
     pub name: String,
     pub location: String,
@@ -279,6 +320,13 @@ pub struct ComponentCharacteristic {
     pub value: CharacteristicValue,
 }
 
+/* Hardware of the device running the CI job, as formatted from the response of Boagent. */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceInventory {
+    pub device: Device,
+    pub components: Vec<Component>,
+}
+
 pub struct DeviceBuilder(Device);
 pub struct ComponentBuilder(Component);
 pub struct ComponentCharacteristicBuilder(ComponentCharacteristic);
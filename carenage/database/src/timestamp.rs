@@ -77,6 +77,19 @@ impl Timestamp {
         }
     }
 
+    /* Timestamps of a run share the same format: a duration between formats is not defined. */
+    pub fn seconds_since(&self, earlier: Timestamp) -> f64 {
+        match (self, earlier) {
+            (Timestamp::Unix(Some(value)), Timestamp::Unix(Some(earlier_value))) => {
+                value.saturating_sub(earlier_value) as f64
+            }
+            (Timestamp::ISO8601(Some(value)), Timestamp::ISO8601(Some(earlier_value))) => {
+                (*value - earlier_value).num_milliseconds() as f64 / 1000.0
+            }
+            _ => 0.0,
+        }
+    }
+
     pub fn as_query_parameter(&self) -> String {
         match self {
             Timestamp::Unix(value) => value.unwrap_or(0).to_string(),
@@ -108,6 +121,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_computes_the_seconds_elapsed_between_two_timestamps() {
+        let now_iso8601 = Local::now();
+        let later_iso8601 = Timestamp::ISO8601(Some(now_iso8601 + chrono::Duration::milliseconds(2500)));
+        assert_eq!(later_iso8601.seconds_since(Timestamp::ISO8601(Some(now_iso8601))), 2.5);
+        assert_eq!(
+            Timestamp::Unix(Some(1724833110)).seconds_since(Timestamp::Unix(Some(1724833101))),
+            9.0
+        );
+    }
+
     #[test]
     #[should_panic]
     fn it_fails_to_parse_a_string_as_unix_timestamp() {
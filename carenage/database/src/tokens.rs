@@ -0,0 +1,296 @@
+use crate::database::{get_project_id, insert_dimension_table_metadata, Ids};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::pool::PoolConnection;
+use sqlx::types::Uuid;
+use sqlx::{PgPool, Postgres, Row};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const TOKEN_PREFIX: &str = "crn_";
+
+/* Permissions are ordered: a token allowed to write data of a project can also read it. */
+#[derive(sqlx::Type, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[sqlx(type_name = "token_permission", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Permission::Read => {
+                write!(f, "read")
+            }
+            Permission::Write => {
+                write!(f, "write")
+            }
+        }
+    }
+}
+
+impl Permission {
+    pub fn parse_str(permission_str: &str) -> Result<Permission, Box<dyn std::error::Error>> {
+        match permission_str {
+            "read" => Ok(Permission::Read),
+            "write" => Ok(Permission::Write),
+            _ => Err(format!("Unknown permission: {}.", permission_str).into()),
+        }
+    }
+}
+
+/* Projects a token gives access to, with the permission it has on each of them. */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenScopes {
+    pub token_id: Uuid,
+    pub projects: HashMap<Uuid, Permission>,
+}
+
+impl TokenScopes {
+    pub fn allows(&self, project_id: Uuid, permission: Permission) -> bool {
+        self.projects
+            .get(&project_id)
+            .is_some_and(|granted| *granted >= permission)
+    }
+
+    /* Devices are tied to a project by the events referring to them: any token allowed to write
+     * data of a project can register them. */
+    pub fn writes_any_project(&self) -> bool {
+        self.projects
+            .values()
+            .any(|granted| *granted == Permission::Write)
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Local>,
+    pub revoked_at: Option<DateTime<Local>>,
+}
+
+/* Tokens are random UUIDs, shown once when issued: only their SHA-256 hash is stored. */
+pub fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub async fn insert_token(
+    db_pool: &PgPool,
+    name: &str,
+    token_hash: &str,
+    scopes: &HashMap<Uuid, Permission>,
+) -> Result<Uuid, sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
+
+    let token_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO api_tokens (name, token_hash) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(name)
+    .bind(token_hash)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    for (project_id, permission) in scopes {
+        sqlx::query(
+            "INSERT INTO api_token_scopes (token_id, project_id, permission) VALUES ($1, $2, $3)",
+        )
+        .bind(token_id)
+        .bind(project_id)
+        .bind(permission)
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(token_id)
+}
+
+/* Revoked tokens are kept, so that the list of issued tokens stays complete. Revoking a token
+ * already revoked has no effect. */
+pub async fn revoke_token(
+    database_connection: PoolConnection<Postgres>,
+    token_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let result = sqlx::query(
+        "UPDATE api_tokens SET revoked_at = current_timestamp WHERE id = ($1) AND revoked_at IS NULL",
+    )
+    .bind(token_id)
+    .execute(&mut connection)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn select_tokens(
+    database_connection: PoolConnection<Postgres>,
+) -> Result<Vec<TokenRecord>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let tokens: Vec<TokenRecord> = sqlx::query_as(
+        "SELECT id, name, created_at, revoked_at FROM API_TOKENS ORDER BY created_at",
+    )
+    .fetch_all(&mut connection)
+    .await?;
+
+    Ok(tokens)
+}
+
+/* Unknown and revoked tokens have no scopes. */
+pub async fn select_token_scopes(
+    database_connection: PoolConnection<Postgres>,
+    token_hash: &str,
+) -> Result<Option<TokenScopes>, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let token_id: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM API_TOKENS WHERE token_hash = ($1) AND revoked_at IS NULL",
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut connection)
+    .await?;
+    let Some(token_id) = token_id else {
+        return Ok(None);
+    };
+
+    let scope_rows =
+        sqlx::query("SELECT project_id, permission FROM API_TOKEN_SCOPES WHERE token_id = ($1)")
+            .bind(token_id)
+            .fetch_all(&mut connection)
+            .await?;
+    let projects = scope_rows
+        .iter()
+        .map(|row| (row.get("project_id"), row.get("permission")))
+        .collect();
+
+    Ok(Some(TokenScopes { token_id, projects }))
+}
+
+/* Tokens can be issued before the first run of a project: the project is registered if absent. */
+pub async fn register_project(db_pool: &PgPool, project_name: &String) -> Result<Uuid, sqlx::Error> {
+    match get_project_id(db_pool.acquire().await?, project_name).await {
+        Err(sqlx::Error::RowNotFound) => {
+            let project_metadata = json!({
+                "name": project_name,
+                "start_date": Local::now().to_string(),
+            });
+            let project_row =
+                insert_dimension_table_metadata(db_pool.acquire().await?, "projects", project_metadata)
+                    .await?;
+            Ok(project_row.get("id"))
+        }
+        project_id => project_id,
+    }
+}
+
+/* Data of a dimension belongs to the project its events refer to. A dimension referred to by the
+ * events of several projects belongs to none of them, so that none of them gives access to it. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DimensionProject {
+    Unreferenced,
+    Project(Uuid),
+    Conflicting,
+}
+
+pub async fn select_project_of_dimension(
+    database_connection: PoolConnection<Postgres>,
+    dimension: &str,
+    dimension_id: Uuid,
+) -> Result<DimensionProject, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let formatted_query = format!(
+        "SELECT DISTINCT events.project_id FROM EVENTS WHERE events.{}_id=($1) LIMIT 2",
+        dimension
+    );
+
+    let project_ids: Vec<Uuid> = sqlx::query_scalar(&formatted_query)
+        .bind(dimension_id)
+        .fetch_all(&mut connection)
+        .await?;
+
+    let dimension_project = match project_ids.as_slice() {
+        [] => DimensionProject::Unreferenced,
+        [project_id] => DimensionProject::Project(*project_id),
+        _ => DimensionProject::Conflicting,
+    };
+    Ok(dimension_project)
+}
+
+/* Workflows, pipelines, jobs, runs and tasks already referred to by the events of a project can
+ * not be referred to by events of another one. */
+pub async fn refers_to_other_projects(
+    database_connection: PoolConnection<Postgres>,
+    ids: &Ids,
+) -> Result<bool, sqlx::Error> {
+    let mut connection = database_connection.detach();
+
+    let refers_to_other_projects: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM EVENTS WHERE events.project_id <> ($1) AND (events.workflow_id = ($2) OR events.pipeline_id = ($3) OR events.job_id = ($4) OR events.run_id = ($5) OR events.task_id = ($6)))",
+    )
+    .bind(ids.project_id)
+    .bind(ids.workflow_id)
+    .bind(ids.pipeline_id)
+    .bind(ids.job_id)
+    .bind(ids.run_id)
+    .bind(ids.task_id)
+    .fetch_one(&mut connection)
+    .await?;
+
+    Ok(refers_to_other_projects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_allows_reading_with_a_write_permission_but_not_the_other_way() {
+        let read_project = Uuid::new_v4();
+        let written_project = Uuid::new_v4();
+        let scopes = TokenScopes {
+            token_id: Uuid::new_v4(),
+            projects: HashMap::from([
+                (read_project, Permission::Read),
+                (written_project, Permission::Write),
+            ]),
+        };
+
+        assert!(scopes.allows(written_project, Permission::Read));
+        assert!(scopes.allows(read_project, Permission::Read));
+        assert!(!scopes.allows(read_project, Permission::Write));
+        assert!(!scopes.allows(Uuid::new_v4(), Permission::Read));
+        assert!(scopes.writes_any_project());
+    }
+
+    #[test]
+    fn it_hashes_tokens_to_hexadecimal_sha256_digests() {
+        let token = generate_token();
+
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token).len(), 64);
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use database::ci::{CiPlatform, CiProvider, GithubActions, Gitlab, Jenkins, Local};
+use std::env;
+
+#[test]
+fn it_parses_ci_platforms_given_as_strings() {
+    assert_eq!(CiPlatform::parse_str("gitlab").unwrap(), CiPlatform::Gitlab);
+    assert_eq!(CiPlatform::parse_str("github").unwrap(), CiPlatform::GithubActions);
+    assert_eq!(CiPlatform::parse_str("jenkins").unwrap(), CiPlatform::Jenkins);
+    assert_eq!(CiPlatform::parse_str("local").unwrap(), CiPlatform::Local);
+    assert!(CiPlatform::parse_str("travis").is_err());
+}
+
+#[test]
+fn it_maps_github_actions_variables_onto_carenage_dimensions() {
+    env::set_var("GITHUB_REPOSITORY", "hubblo-org/carenage");
+    env::set_var("GITHUB_WORKFLOW", "CI");
+    env::set_var("GITHUB_RUN_ID", "1658821493");
+    env::set_var("GITHUB_RUN_NUMBER", "42");
+    env::set_var("GITHUB_JOB", "test");
+    env::set_var("GITHUB_ACTION", "run_tests");
+
+    let ci_metadata = GithubActions.parse_env_variables().unwrap();
+
+    assert_eq!(ci_metadata.project_path, "hubblo-org/carenage");
+    assert_eq!(ci_metadata.workflow_name, "CI");
+    assert_eq!(ci_metadata.pipeline_id, 1658821493);
+    assert_eq!(ci_metadata.pipeline_name, "CI #42");
+    assert_eq!(ci_metadata.job_name, "test");
+    assert_eq!(ci_metadata.task_name, "run_tests");
+    assert!(ci_metadata.job_started_at.is_none());
+}
+
+#[test]
+fn it_maps_jenkins_variables_onto_carenage_dimensions() {
+    env::set_var("JOB_NAME", "carenage/main");
+    env::set_var("JOB_BASE_NAME", "main");
+    env::set_var("BUILD_NUMBER", "17");
+    env::set_var("BUILD_TAG", "jenkins-carenage-main-17");
+
+    let ci_metadata = Jenkins.parse_env_variables().unwrap();
+
+    assert_eq!(ci_metadata.project_path, "carenage/main");
+    assert_eq!(ci_metadata.workflow_name, "workflow_carenage/main");
+    assert_eq!(ci_metadata.pipeline_id, 17);
+    assert_eq!(ci_metadata.pipeline_name, "jenkins-carenage-main-17");
+    assert_eq!(ci_metadata.job_name, "main");
+    assert_eq!(ci_metadata.task_name, "main");
+}
+
+#[test]
+fn it_sets_local_metadata_from_the_project_name() {
+    env::set_var("PROJECT_NAME", "carenage_webapp");
+
+    let ci_metadata = Local.parse_env_variables().unwrap();
+
+    assert!(Local.is_detected());
+    assert_eq!(ci_metadata.project_path, "carenage_webapp");
+    assert_eq!(ci_metadata.pipeline_name, "local");
+    assert!(ci_metadata.workflow_started_at.is_none());
+}
+
+#[test]
+fn it_names_sessions_after_the_ci_job() {
+    env::set_var("CI_JOB_ID", "8123");
+    env::set_var("GITHUB_RUN_ID", "1658821493");
+    env::set_var("GITHUB_RUN_ATTEMPT", "2");
+    env::set_var("GITHUB_JOB", "test");
+    env::set_var("BUILD_TAG", "jenkins-carenage-main-17");
+
+    assert_eq!(Gitlab.session_id().unwrap(), "gitlab_8123");
+    assert_eq!(GithubActions.session_id().unwrap(), "github_1658821493_2_test");
+    assert_eq!(Jenkins.session_id().unwrap(), "jenkins-carenage-main-17");
+    assert!(Local.session_id().is_none());
+}
@@ -1,15 +1,33 @@
-use std::fs::canonicalize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{canonicalize, read_to_string};
+use std::ops::Range;
 
 use chrono::{Duration, Local};
+use database::attribution::OTHER_PROCESSES_EXE;
 use database::boagent::{deserialize_boagent_json, query_boagent, HardwareData};
+use database::budget::{
+    insert_verdict, select_job_name_from_run, select_run_totals, select_verdicts_from_project,
+    Limits, Verdict,
+};
+use database::compare::{compare_pipelines, Threshold, DEFAULT_COMPARED_METRICS};
+use database::energy::{select_energy_from_dimension, EnergyUnit};
 use database::database::{
     check_process_existence_for_id, collect_processes, format_hardware_data,
-    get_db_connection_pool, get_process_id, get_project_id, insert_device_metadata,
-    insert_dimension_table_metadata, select_metrics_from_dimension,
-    select_project_name_from_dimension, update_stop_date,
+    get_db_connection_pool, get_process_id, get_project_id, insert_device_metadata, Aggregation, Bucket,
+    MetricsFilter,
+    insert_dimension_table_metadata, insert_pipeline_metadata, select_metrics_between_labels, Ids,
+    select_filtered_metrics_from_dimension, select_metrics_from_dimension,
+    select_project_name_from_dimension, select_vcs_from_dimension, update_stop_date,
 };
 use database::event::{Event, EventType};
-use database::metrics::Metrics;
+use database::report::select_run_report;
+use database::listing::{
+    select_dimension_summaries, DimensionFilter, ListParams, SortKey, SortOrder,
+};
+use database::metrics::{Metrics, OperationalTotals};
+use database::sci::{insert_functional_unit, select_sci_from_dimension, FunctionalUnit};
+use database::summary::select_summary_from_dimension;
+use database::spool::{upload_spool, Sample, SessionMetadata, Spool, SpoolRecord, UploadSummary};
 use database::tables::{Process, ProcessBuilder};
 use database::timestamp::Timestamp;
 use dotenv::var;
@@ -73,7 +91,14 @@ async fn it_inserts_valid_data_for_several_dimension_tables_in_the_carenage_data
         assert!(insert_query.is_ok());
         let row = insert_query.unwrap();
         let project_name: String = row.get("name");
-        assert_eq!(row.len(), 4);
+        // Pipelines also store their VCS context.
+        let columns_count = match table {
+            "pipelines" => 10,
+            // Runs also store their functional unit.
+            "runs" => 6,
+            _ => 4,
+        };
+        assert_eq!(row.len(), columns_count);
         assert_eq!(project_name, dimension_table_metadata["name"]);
     }
 
@@ -427,6 +452,8 @@ async fn it_inserts_foreign_keys_into_events_table(pool: PgPool) -> sqlx::Result
     let vec_ids: Vec<uuid::Uuid> = query[0].get("project_ids");
 
     let event = Event {
+        id: uuid::Uuid::new_v4(),
+        timestamp: Local::now(),
         project_id: vec_ids[0],
         workflow_id: vec_ids[1],
         pipeline_id: vec_ids[2],
@@ -436,6 +463,7 @@ async fn it_inserts_foreign_keys_into_events_table(pool: PgPool) -> sqlx::Result
         process_id: vec_ids[6],
         device_id: vec_ids[7],
         event_type: EventType::Regular,
+        user_label: None,
     };
 
     let insert_event = Event::insert(&event, pool.acquire().await?).await;
@@ -444,6 +472,258 @@ async fn it_inserts_foreign_keys_into_events_table(pool: PgPool) -> sqlx::Result
 
     Ok(())
 }
+#[sqlx::test(fixtures("../fixtures/dimensions.sql"))]
+async fn it_uploads_spooled_records_once_when_replayed_twice(pool: PgPool) -> sqlx::Result<()> {
+    let connection = pool.acquire().await?;
+    let query = sqlx::query("SELECT * FROM project_ids()")
+        .fetch_all(&mut connection.detach())
+        .await?;
+    let vec_ids: Vec<uuid::Uuid> = query[0].get("project_ids");
+
+    let event = Event {
+        id: uuid::Uuid::new_v4(),
+        timestamp: Local::now() - Duration::minutes(1),
+        project_id: vec_ids[0],
+        workflow_id: vec_ids[1],
+        pipeline_id: vec_ids[2],
+        job_id: vec_ids[3],
+        run_id: vec_ids[4],
+        task_id: vec_ids[5],
+        process_id: vec_ids[6],
+        device_id: vec_ids[7],
+        event_type: EventType::Regular,
+        user_label: None,
+    };
+    let sample = SpoolRecord::Sample(Box::new(Sample {
+        event: event.clone(),
+        process: ProcessBuilder::new(6042, "cargo", "cargo test", "running").build(),
+        metrics: Metrics {
+            energy_consumed_wh: 0.5,
+            ..Default::default()
+        },
+    }));
+
+    let spool_dir = std::env::temp_dir().join(format!("carenage_spool_{}", event.id));
+    let spool = Spool {
+        path: spool_dir.join("carenaged_1.jsonl"),
+    };
+    spool.append(&sample).unwrap();
+    std::fs::write(spool_dir.join("carenaged_2.jsonl"), "{\"record\":\"sam").unwrap();
+
+    let first_upload = upload_spool(&pool, &spool_dir).await.unwrap();
+    assert_eq!(
+        first_upload,
+        UploadSummary {
+            files: 2,
+            records: 1,
+            invalid_lines: 1,
+            rejected_records: 0
+        }
+    );
+    assert_eq!(std::fs::read_dir(&spool_dir).unwrap().count(), 0);
+
+    spool.append(&sample).unwrap();
+    upload_spool(&pool, &spool_dir).await.unwrap();
+    std::fs::remove_dir_all(&spool_dir).unwrap();
+
+    let events = sqlx::query("SELECT timestamp FROM events WHERE id = ($1)")
+        .bind(event.id)
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].get::<chrono::DateTime<Local>, &str>("timestamp").timestamp_micros(),
+        event.timestamp.timestamp_micros()
+    );
+    let energy_metrics = sqlx::query(
+        "SELECT value FROM metrics WHERE event_id = ($1) AND metric = 'energy_consumed_wh'",
+    )
+    .bind(event.id)
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(energy_metrics.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/dimensions.sql"))]
+async fn it_moves_rejected_spooled_records_aside_and_uploads_the_others(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let connection = pool.acquire().await?;
+    let query = sqlx::query("SELECT * FROM project_ids()")
+        .fetch_all(&mut connection.detach())
+        .await?;
+    let vec_ids: Vec<uuid::Uuid> = query[0].get("project_ids");
+
+    let event = Event {
+        id: uuid::Uuid::new_v4(),
+        timestamp: Local::now() - Duration::minutes(1),
+        project_id: vec_ids[0],
+        workflow_id: vec_ids[1],
+        pipeline_id: vec_ids[2],
+        job_id: vec_ids[3],
+        run_id: vec_ids[4],
+        task_id: vec_ids[5],
+        process_id: vec_ids[6],
+        device_id: vec_ids[7],
+        event_type: EventType::Custom,
+        user_label: None,
+    };
+    let unknown_run_event = Event {
+        id: uuid::Uuid::new_v4(),
+        run_id: uuid::Uuid::new_v4(),
+        ..event.clone()
+    };
+
+    let spool_dir = std::env::temp_dir().join(format!("carenage_spool_{}", event.id));
+    let spool = Spool {
+        path: spool_dir.join("carenaged_1.jsonl"),
+    };
+    spool
+        .append(&SpoolRecord::Event {
+            event: unknown_run_event,
+        })
+        .unwrap();
+    spool
+        .append(&SpoolRecord::Event {
+            event: event.clone(),
+        })
+        .unwrap();
+
+    let upload = upload_spool(&pool, &spool_dir).await.unwrap();
+    assert_eq!(
+        upload,
+        UploadSummary {
+            files: 1,
+            records: 1,
+            invalid_lines: 0,
+            rejected_records: 1
+        }
+    );
+    let rejected_records = std::fs::read_to_string(spool_dir.join("carenaged_1.rejected")).unwrap();
+    assert_eq!(rejected_records.lines().count(), 1);
+    assert_eq!(upload_spool(&pool, &spool_dir).await.unwrap().files, 0);
+    std::fs::remove_dir_all(&spool_dir).unwrap();
+
+    let events = sqlx::query("SELECT id FROM events WHERE id = ($1)")
+        .bind(event.id)
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(events.len(), 1);
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_registers_spooled_metadata_before_the_records_referring_to_it(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let now_timestamp = Local::now();
+    let project_row = insert_dimension_table_metadata(
+        pool.acquire().await?,
+        "projects",
+        json!({ "name": "my_web_application", "start_date": now_timestamp.to_string() }),
+    )
+    .await?;
+    let registered_project_id: uuid::Uuid = project_row.get("id");
+
+    let spooled_ids = Ids {
+        project_id: uuid::Uuid::new_v4(),
+        workflow_id: uuid::Uuid::new_v4(),
+        pipeline_id: uuid::Uuid::new_v4(),
+        job_id: uuid::Uuid::new_v4(),
+        run_id: uuid::Uuid::new_v4(),
+        task_id: uuid::Uuid::new_v4(),
+        device_id: uuid::Uuid::new_v4(),
+        process_id: uuid::Uuid::new_v4(),
+    };
+    let metadata = SpoolRecord::Metadata(Box::new(SessionMetadata {
+        ids: spooled_ids,
+        dimensions: ["my_web_application", "workflow", "pipeline", "job", "run", "task"]
+            .iter()
+            .map(|name| json!({ "name": name, "start_date": now_timestamp.to_string() }))
+            .collect(),
+        device: json!({
+            "device": { "name": "dell r740", "lifetime": 5, "location": "FRA" },
+            "components": []
+        }),
+        process: ProcessBuilder::new(6041, "carenage", "carenage start", "running").build(),
+    }));
+    let start_event = Event {
+        id: uuid::Uuid::new_v4(),
+        timestamp: now_timestamp,
+        project_id: spooled_ids.project_id,
+        workflow_id: spooled_ids.workflow_id,
+        pipeline_id: spooled_ids.pipeline_id,
+        job_id: spooled_ids.job_id,
+        run_id: spooled_ids.run_id,
+        task_id: spooled_ids.task_id,
+        process_id: spooled_ids.process_id,
+        device_id: spooled_ids.device_id,
+        event_type: EventType::Start,
+        user_label: None,
+    };
+
+    let spool_dir = std::env::temp_dir().join(format!("carenage_spool_{}", start_event.id));
+    let spool = Spool {
+        path: spool_dir.join("carenaged_1.jsonl"),
+    };
+    spool.append(&metadata).unwrap();
+    spool
+        .append(&SpoolRecord::Event {
+            event: start_event.clone(),
+        })
+        .unwrap();
+    let uploaded = upload_spool(&pool, &spool_dir).await.unwrap();
+    assert_eq!(uploaded.records, 2);
+
+    spool.append(&metadata).unwrap();
+    upload_spool(&pool, &spool_dir).await.unwrap();
+
+    let stop_event = Event {
+        id: uuid::Uuid::new_v4(),
+        event_type: EventType::Stop,
+        ..start_event.clone()
+    };
+    Spool {
+        path: spool_dir.join("carenaged_2.jsonl"),
+    }
+    .append(&SpoolRecord::Stop {
+        event: stop_event.clone(),
+    })
+    .unwrap();
+    let rotated_upload = upload_spool(&pool, &spool_dir).await.unwrap();
+    assert_eq!(rotated_upload.records, 1);
+    std::fs::remove_dir_all(&spool_dir).unwrap();
+
+    let events = sqlx::query("SELECT project_id, run_id, process_id FROM events WHERE id = ($1)")
+        .bind(start_event.id)
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].get::<uuid::Uuid, &str>("project_id"), registered_project_id);
+    let stop_events = sqlx::query("SELECT project_id FROM events WHERE id = ($1)")
+        .bind(stop_event.id)
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(stop_events.len(), 1);
+    assert_eq!(stop_events[0].get::<uuid::Uuid, &str>("project_id"), registered_project_id);
+    assert_eq!(events[0].get::<uuid::Uuid, &str>("run_id"), spooled_ids.run_id);
+    assert_eq!(events[0].get::<uuid::Uuid, &str>("process_id"), spooled_ids.process_id);
+
+    let runs = sqlx::query("SELECT name FROM runs")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(runs.len(), 1);
+    let projects = sqlx::query("SELECT id FROM projects")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(projects.len(), 1);
+
+    Ok(())
+}
+
 #[sqlx::test]
 async fn it_builds_metrics_from_json_values() {
     let process_data = common::process_data();
@@ -499,6 +779,11 @@ async fn it_builds_metrics_from_json_values() {
     assert_eq!(metrics.disk_usage_read_bytes, 0 as f64);
     assert_eq!(metrics.disk_usage_write_bytes, 0 as f64);
     assert_eq!(metrics.average_power_measured_w, 14.94261724369748);
+    assert_eq!(metrics.process_power_measured_w, 147645.48 / 1_000_000.0);
+    assert_eq!(
+        metrics.interval_operational_emission_kgc02eq,
+        metrics.total_operational_emission_kgc02eq
+    );
     assert_eq!(metrics.embedded_emissions_kgc02eq, 900_f64);
     assert_eq!(metrics.embedded_abiotic_resources_depletion_kgsbeq, 0.14);
     assert_eq!(metrics.embedded_primary_energy_mj, 13000_f64);
@@ -526,6 +811,52 @@ async fn it_builds_metrics_from_json_values() {
     assert!(metrics.process_hdd_embedded_impacts.is_none());
 }
 
+#[test]
+fn it_builds_aggregated_metrics_for_the_processes_with_given_pids() {
+    let boagent_response: serde_json::Value = serde_json::from_str(
+        &read_to_string("../mocks/query_boagent_response_before_process_embedded_impacts.json")
+            .unwrap(),
+    )
+    .unwrap();
+
+    let metrics = Metrics::build_aggregate(&[6042, 4163, 1], &boagent_response);
+
+    assert_eq!(metrics.cpu_usage_percentage, 1.1115274 + 0.43711752);
+    assert_eq!(metrics.memory_usage_bytes, (212635648 + 21315584) as f64);
+    assert_eq!(metrics.memory_virtual_usage_bytes, (2866921472_u64 + 2880172032) as f64);
+    assert_eq!(metrics.average_power_measured_w, 14.94261724369748);
+    assert_eq!(
+        metrics.process_power_measured_w,
+        147645.48 / 1_000_000.0 + 58062.83 / 1_000_000.0
+    );
+    assert!(metrics.process_cpu_embedded_impacts.is_none());
+}
+
+#[test]
+fn it_keeps_interval_impacts_and_sets_cumulative_impacts_of_the_run() {
+    let boagent_response: serde_json::Value = serde_json::from_str(
+        &read_to_string("../mocks/query_boagent_response_before_process_embedded_impacts.json")
+            .unwrap(),
+    )
+    .unwrap();
+    let interval_totals = OperationalTotals::from_boagent_response(&boagent_response);
+    let mut cumulative_totals = OperationalTotals::default();
+    cumulative_totals.add(interval_totals);
+    cumulative_totals.add(interval_totals);
+
+    let metrics = Metrics::build(&common::process_data(), &boagent_response)
+        .with_cumulative_totals(cumulative_totals);
+
+    assert_eq!(
+        metrics.interval_primary_energy_consumed_mj,
+        interval_totals.primary_energy_consumed_mj
+    );
+    assert_eq!(
+        metrics.total_primary_energy_consumed_mj,
+        2.0 * interval_totals.primary_energy_consumed_mj
+    );
+}
+
 #[sqlx::test(fixtures("../fixtures/events.sql"))]
 async fn it_inserts_metrics_for_an_event_into_metrics_table(pool: PgPool) -> sqlx::Result<()> {
     let connection = pool.acquire().await?;
@@ -601,6 +932,160 @@ async fn it_selects_all_metrics_associated_with_a_given_run_id(pool: PgPool) ->
     Ok(())
 }
 
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_selects_the_metrics_of_a_run_matching_a_filter(pool: PgPool) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let all_records = select_metrics_from_dimension(pool.acquire().await?, "run", run_id).await?;
+    let first_record = &all_records[0];
+    let last_timestamp = all_records.iter().map(|record| record.timestamp).max().unwrap();
+
+    let filter = MetricsFilter {
+        from: Some(first_record.timestamp),
+        to: Some(last_timestamp),
+        metrics: vec![first_record.metric.clone()],
+        exe: Some(first_record.exe.clone()),
+        pid: Some(first_record.pid),
+        limit: None,
+        bucket: None,
+    };
+    let records =
+        select_filtered_metrics_from_dimension(pool.acquire().await?, "run", run_id, &filter)
+            .await?;
+
+    assert!(!records.is_empty());
+    assert!(records.len() < all_records.len());
+    assert!(records.iter().all(|record| record.metric == first_record.metric
+        && record.exe == first_record.exe
+        && record.pid == first_record.pid
+        && record.timestamp >= first_record.timestamp));
+
+    let filter = MetricsFilter {
+        from: Some(last_timestamp),
+        limit: Some(3),
+        ..MetricsFilter::default()
+    };
+    let records =
+        select_filtered_metrics_from_dimension(pool.acquire().await?, "run", run_id, &filter)
+            .await?;
+
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|record| record.timestamp == last_timestamp));
+
+    let first_timestamp = all_records.iter().map(|record| record.timestamp).min().unwrap();
+    let filter = MetricsFilter {
+        limit: Some(3),
+        ..MetricsFilter::default()
+    };
+    let records =
+        select_filtered_metrics_from_dimension(pool.acquire().await?, "run", run_id, &filter)
+            .await?;
+
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|record| record.timestamp == first_timestamp));
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_aggregates_the_metrics_of_a_run_by_time_bucket(pool: PgPool) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let records = select_metrics_from_dimension(pool.acquire().await?, "run", run_id).await?;
+    let metric = records[0].metric.clone();
+    let pid = records[0].pid;
+    let raw_values: Vec<f64> = records
+        .iter()
+        .filter(|record| record.metric == metric && record.pid == pid)
+        .map(|record| record.value)
+        .collect();
+
+    let filter = MetricsFilter {
+        metrics: vec![metric.clone()],
+        pid: Some(pid),
+        bucket: Some(Bucket::parse_str("1h", Aggregation::Sum).unwrap()),
+        ..MetricsFilter::default()
+    };
+    let bucketed_records =
+        select_filtered_metrics_from_dimension(pool.acquire().await?, "run", run_id, &filter)
+            .await?;
+
+    assert!(bucketed_records.len() < raw_values.len());
+    let bucketed_sum: f64 = bucketed_records.iter().map(|record| record.value).sum();
+    assert!((bucketed_sum - raw_values.iter().sum::<f64>()).abs() < 1e-6);
+
+    let filter = MetricsFilter {
+        bucket: Some(Bucket::parse_str("1h", Aggregation::Max).unwrap()),
+        ..filter
+    };
+    let bucketed_records =
+        select_filtered_metrics_from_dimension(pool.acquire().await?, "run", run_id, &filter)
+            .await?;
+    let raw_max = raw_values.iter().cloned().fold(f64::MIN, f64::max);
+    assert!(bucketed_records.iter().any(|record| record.value == raw_max));
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_summarizes_the_metrics_of_a_run_overall_and_per_process(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let records = select_metrics_from_dimension(pool.acquire().await?, "run", run_id).await?;
+
+    let summary = select_summary_from_dimension(pool.acquire().await?, "run", run_id).await?;
+
+    assert_eq!(summary.processes.len(), 15);
+    let cpu_usage = &summary.overall["cpu_usage_percentage"];
+    let cpu_usage_records: Vec<f64> = records
+        .iter()
+        .filter(|record| record.metric == "cpu_usage_percentage")
+        .map(|record| record.value)
+        .collect();
+    assert_eq!(cpu_usage.sample_count as usize, cpu_usage_records.len());
+    assert!((cpu_usage.total - cpu_usage_records.iter().sum::<f64>()).abs() < 1e-6);
+    assert_eq!(cpu_usage.unit.as_deref(), Some("%"));
+
+    let emissions = &summary.overall["total_operational_emission_kgc02eq"];
+    let last_emissions = records
+        .iter()
+        .filter(|record| record.metric == "total_operational_emission_kgc02eq")
+        .max_by_key(|record| record.timestamp)
+        .unwrap();
+    assert_eq!(emissions.total, last_emissions.value);
+    let emissions_timestamps: HashSet<_> = records
+        .iter()
+        .filter(|record| record.metric == "total_operational_emission_kgc02eq")
+        .map(|record| record.timestamp)
+        .collect();
+    assert_eq!(emissions.sample_count as usize, emissions_timestamps.len());
+    assert!(summary.processes.iter().all(|process| process
+        .metrics
+        .contains_key("cpu_usage_percentage")));
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_counts_host_metrics_once_per_tick_overall(pool: PgPool) -> sqlx::Result<()> {
+    let pipeline_id = uuid::Uuid::new_v4();
+    insert_run_samples(&pool, pipeline_id, &[(100, "cargo", 0..2), (101, "rustc", 1..3)]).await?;
+
+    let summary =
+        select_summary_from_dimension(pool.acquire().await?, "pipeline", pipeline_id).await?;
+
+    let interval_energy = &summary.overall["interval_primary_energy_consumed_mj"];
+    assert_eq!(interval_energy.sample_count, 3);
+    assert_eq!(interval_energy.total, 3.0);
+    assert_eq!(summary.overall["total_primary_energy_consumed_mj"].total, 3.0);
+    assert_eq!(summary.overall["energy_consumed_wh"].sample_count, 4);
+    assert!(summary
+        .processes
+        .iter()
+        .all(|process| process.metrics["interval_primary_energy_consumed_mj"].sample_count == 2));
+
+    Ok(())
+}
+
 #[sqlx::test(fixtures("../fixtures/metrics.sql"))]
 async fn it_selects_the_project_name_with_a_given_run_id(pool: PgPool) -> sqlx::Result<()> {
     let connection = pool.acquire().await?;
@@ -613,3 +1098,428 @@ async fn it_selects_the_project_name_with_a_given_run_id(pool: PgPool) -> sqlx::
 
     Ok(())
 }
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_selects_the_vcs_context_of_the_pipeline_with_a_given_run_id(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let connection = pool.acquire().await?;
+
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let vcs_record = select_vcs_from_dimension(connection, "run", run_id)
+        .await?
+        .expect("Run should be associated with a pipeline.");
+
+    assert_eq!(
+        vcs_record.commit_sha.as_deref(),
+        Some("a1b2c3d4e5f60718293a4b5c6d7e8f9012345678")
+    );
+    assert_eq!(vcs_record.ref_name.as_deref(), Some("main"));
+    assert_eq!(vcs_record.merge_request_iid, Some(42));
+    assert!(vcs_record.tag.is_none());
+    assert_eq!(
+        vcs_record.pipeline_url.as_deref(),
+        Some("https://gitlab.com/hubblo/carenage/-/pipelines/1521")
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "../../db/")]
+async fn it_inserts_the_vcs_context_of_a_pipeline(pool: PgPool) -> sqlx::Result<()> {
+    let commit_timestamp = Timestamp::ISO8601(Some("2024-11-05T11:10:03Z".parse().unwrap()));
+
+    let pipeline_metadata = json!({
+        "name": "Pipeline for merge request",
+        "start_date": Local::now().to_string(),
+        "commit_sha": "a1b2c3d4e5f60718293a4b5c6d7e8f9012345678",
+        "ref_name": "feature/vcs",
+        "merge_request_iid": 42,
+        "tag": null,
+        "commit_timestamp": commit_timestamp.to_string(),
+        "pipeline_url": "https://gitlab.com/hubblo/carenage/-/pipelines/1521"
+    });
+
+    let pipeline_row = insert_pipeline_metadata(pool.acquire().await?, pipeline_metadata).await?;
+
+    assert_eq!(pipeline_row.get::<&str, &str>("ref_name"), "feature/vcs");
+    assert_eq!(pipeline_row.get::<i32, &str>("merge_request_iid"), 42);
+    assert!(pipeline_row.get::<Option<&str>, &str>("tag").is_none());
+    assert_eq!(
+        Timestamp::ISO8601(Some(pipeline_row.get("commit_timestamp"))),
+        commit_timestamp
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_compares_metrics_of_two_pipelines(pool: PgPool) -> sqlx::Result<()> {
+    let pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+    let pipeline_without_metrics_id = uuid!("80d53828-dcb8-4f45-aa4c-bc666e3ee54c");
+
+    let comparison = compare_pipelines(&pool, pipeline_id, pipeline_id)
+        .await?
+        .expect("Pipeline should have metrics to compare.");
+
+    assert_eq!(comparison.overall.len(), 39);
+    assert_eq!(comparison.processes.len(), 12);
+    assert!(comparison.overall.iter().all(|metric| metric.delta == 0.0));
+
+    let comparison_without_metrics =
+        compare_pipelines(&pool, pipeline_without_metrics_id, pipeline_id).await?;
+    assert!(comparison_without_metrics.is_none());
+
+    Ok(())
+}
+
+/* Samples of a pipeline of a single run, every process sampled at each tick reporting the same
+ * host metrics, and an equal share of the energy consumed. */
+async fn insert_pipeline_samples(
+    pool: &PgPool,
+    pipeline_id: uuid::Uuid,
+    processes: &[(i32, &str)],
+    ticks: i64,
+) -> sqlx::Result<()> {
+    let sampled_processes: Vec<(i32, &str, Range<i64>)> = processes
+        .iter()
+        .map(|(pid, exe)| (*pid, *exe, 0..ticks))
+        .collect();
+    insert_run_samples(pool, pipeline_id, &sampled_processes).await?;
+    Ok(())
+}
+
+/* Samples of the processes sampled at a tick share its timestamp, as inserted by carenaged. */
+async fn insert_run_samples(
+    pool: &PgPool,
+    pipeline_id: uuid::Uuid,
+    processes: &[(i32, &str, Range<i64>)],
+) -> sqlx::Result<uuid::Uuid> {
+    let run_id = uuid::Uuid::new_v4();
+    let start = Local::now();
+    for (pid, exe, ticks) in processes {
+        let process_id = uuid::Uuid::new_v4();
+        sqlx::query("INSERT INTO processes (id, pid, exe, cmdline, state) VALUES ($1, $2, $3, $3, 'running')")
+            .bind(process_id)
+            .bind(pid)
+            .bind(exe)
+            .execute(pool)
+            .await?;
+        for tick in ticks.clone() {
+            let event_id = uuid::Uuid::new_v4();
+            sqlx::query("INSERT INTO events (id, timestamp, process_id, task_id, job_id, run_id, pipeline_id, workflow_id, project_id, device_id, event_type) VALUES ($1, $2, $3, $4, $4, $5, $6, $4, $4, $4, 'regular')")
+                .bind(event_id)
+                .bind(start + Duration::seconds(5 * tick))
+                .bind(process_id)
+                .bind(uuid::Uuid::nil())
+                .bind(run_id)
+                .bind(pipeline_id)
+                .execute(pool)
+                .await?;
+            for (metric, value) in [
+                ("interval_primary_energy_consumed_mj", 1.0),
+                ("interval_operational_emission_kgc02eq", 0.1),
+                ("total_primary_energy_consumed_mj", (tick + 1) as f64),
+                ("energy_consumed_wh", 1.0 / processes.len() as f64),
+            ] {
+                sqlx::query("INSERT INTO metrics (event_id, metric, value) VALUES ($1, $2, $3)")
+                    .bind(event_id)
+                    .bind(metric)
+                    .bind(value)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+    }
+    Ok(run_id)
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_leaves_the_energy_of_processes_outside_of_the_job_out_of_totals(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let pipeline_id = uuid::Uuid::new_v4();
+    let run_id = insert_run_samples(
+        &pool,
+        pipeline_id,
+        &[(100, "cargo", 0..2), (0, OTHER_PROCESSES_EXE, 0..2)],
+    )
+    .await?;
+    sqlx::query("INSERT INTO pipelines (id, name) VALUES ($1, 'pipeline')")
+        .bind(pipeline_id)
+        .execute(&pool)
+        .await?;
+    sqlx::query("INSERT INTO runs (id, name) VALUES ($1, 'run')")
+        .bind(run_id)
+        .execute(&pool)
+        .await?;
+
+    let energy = select_energy_from_dimension(pool.acquire().await?, "run", run_id).await?;
+    assert_eq!(energy.value, 1.0);
+    let totals = select_run_totals(pool.acquire().await?, run_id).await?;
+    assert_eq!(totals.energy_wh, 1.0);
+    let sci = select_sci_from_dimension(pool.acquire().await?, "run", run_id)
+        .await?
+        .expect("Run should have a SCI score.");
+    assert_eq!(sci.energy_kwh, 0.001);
+    let pipelines = select_dimension_summaries(
+        pool.acquire().await?,
+        "pipelines",
+        &DimensionFilter::Ids(vec![pipeline_id]),
+        &ListParams::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(pipelines.items[0].energy_wh, 1.0);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_compares_host_metrics_of_pipelines_regardless_of_their_process_count(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let base_pipeline_id = uuid::Uuid::new_v4();
+    let head_pipeline_id = uuid::Uuid::new_v4();
+    insert_pipeline_samples(&pool, base_pipeline_id, &[(100, "cargo")], 3).await?;
+    insert_pipeline_samples(
+        &pool,
+        head_pipeline_id,
+        &[(200, "cargo"), (201, "rustc"), (202, "rustc")],
+        3,
+    )
+    .await?;
+
+    let comparison = compare_pipelines(&pool, base_pipeline_id, head_pipeline_id)
+        .await?
+        .expect("Pipelines should have metrics to compare.");
+
+    let overall: HashMap<&str, (f64, f64)> = comparison
+        .overall
+        .iter()
+        .map(|metric| (metric.metric.as_str(), (metric.base, metric.head)))
+        .collect();
+    assert_eq!(overall["interval_primary_energy_consumed_mj"], (3.0, 3.0));
+    assert_eq!(overall["total_primary_energy_consumed_mj"], (3.0, 3.0));
+    let (base_emissions, head_emissions) = overall["interval_operational_emission_kgc02eq"];
+    assert!((base_emissions - 0.3).abs() < 1e-9 && (head_emissions - 0.3).abs() < 1e-9);
+    assert_eq!(overall["energy_consumed_wh"], (3.0, 3.0));
+    let regressions = comparison.regressions(
+        &DEFAULT_COMPARED_METRICS.map(String::from),
+        Threshold {
+            max_relative_increase: Some(0.0),
+            ..Default::default()
+        },
+    );
+    assert!(regressions.is_empty());
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_evaluates_and_stores_the_budget_verdict_of_a_run(pool: PgPool) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let project_id = uuid!("95dfae11-5cad-41d9-bcf9-fa6564c22dd6");
+
+    let job_name = select_job_name_from_run(pool.acquire().await?, run_id).await?;
+    assert!(!job_name.is_empty());
+
+    let totals = select_run_totals(pool.acquire().await?, run_id).await?;
+    assert!(totals.emissions_gco2eq > 0.0);
+
+    let limits = Limits {
+        energy_wh: None,
+        emissions_gco2eq: Some(0.0),
+    };
+    let verdict = Verdict::evaluate(limits, totals);
+    assert!(verdict.exceeded);
+
+    let row = insert_verdict(pool.acquire().await?, run_id, &verdict).await?;
+    assert!(row.get::<bool, &str>("exceeded"));
+
+    let verdicts = select_verdicts_from_project(pool.acquire().await?, project_id).await?;
+    assert_eq!(verdicts.len(), 2);
+    assert_eq!(verdicts[1].emissions_gco2eq, totals.emissions_gco2eq);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_selects_the_report_totals_of_a_run(pool: PgPool) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let run_report = select_run_report(pool.acquire().await?, run_id).await?;
+
+    assert_eq!(run_report.energy_wh, 0.0);
+    assert_eq!(run_report.emissions_gco2eq, 47260.0);
+    assert_eq!(run_report.primary_energy_mj, 5444.0);
+    assert_eq!(run_report.peak_power_w, 14.4066632857143);
+
+    let unknown_run = select_run_report(pool.acquire().await?, uuid!("00000000-0000-0000-0000-000000000001")).await;
+    assert!(matches!(unknown_run, Err(sqlx::Error::RowNotFound)));
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_integrates_the_energy_consumed_over_a_run(pool: PgPool) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+    let event_id = uuid!("6f891e09-d246-427d-bca1-1570154cf711");
+
+    let metrics = Metrics {
+        process_power_measured_w: 36.0,
+        ..Default::default()
+    }
+    .with_interval(10.0);
+    assert_eq!(metrics.energy_consumed_wh, 0.1);
+
+    metrics.insert(event_id, pool.acquire().await?).await?;
+    metrics.insert(event_id, pool.acquire().await?).await?;
+
+    let energy = select_energy_from_dimension(pool.acquire().await?, "run", run_id).await?;
+    assert_eq!(energy.value, 0.2);
+    assert_eq!(energy.unit, EnergyUnit::WattHour);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_computes_the_sci_score_of_a_run_per_its_functional_unit(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let sci = select_sci_from_dimension(pool.acquire().await?, "run", run_id)
+        .await?
+        .expect("Run should have a SCI score.");
+    assert_eq!(sci.functional_unit, FunctionalUnit::new("test", 40.0).unwrap());
+    assert_eq!(sci.score_gco2eq, sci.embodied_emissions_gco2eq / 40.0);
+
+    let functional_unit = FunctionalUnit::new("1000 requests", 4.0).unwrap();
+    insert_functional_unit(pool.acquire().await?, run_id, &functional_unit).await?;
+
+    let sci = select_sci_from_dimension(pool.acquire().await?, "run", run_id)
+        .await?
+        .expect("Run should have a SCI score.");
+    assert_eq!(sci.functional_unit, functional_unit);
+    assert_eq!(sci.score_gco2eq, sci.embodied_emissions_gco2eq / 4.0);
+
+    Ok(())
+}
+
+/* 500 Wh at an average of 375 gCO2eq/kWh, and 125 gCO2eq embodied, over 4 tests. */
+#[sqlx::test(fixtures("../fixtures/metrics.sql", "../fixtures/sci.sql"))]
+async fn it_computes_the_sci_score_of_a_run_with_its_operational_emissions(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let run_id = uuid!("3c1f5a2e-8d4b-4f6a-9e7c-2b8d0a4f6e13");
+
+    let sci = select_sci_from_dimension(pool.acquire().await?, "run", run_id)
+        .await?
+        .expect("Run should have a SCI score.");
+    assert_eq!(sci.energy_kwh, 0.5);
+    assert_eq!(sci.carbon_intensity_gco2eq_per_kwh, 375.0);
+    assert_eq!(sci.embodied_emissions_gco2eq, 125.0);
+    assert_eq!(sci.score_gco2eq, 78.125);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_selects_the_metrics_of_a_run_between_two_custom_events(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let run_id = uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae");
+
+    let records =
+        select_metrics_between_labels(pool.acquire().await?, run_id, "build done", "tests done")
+            .await?
+            .expect("Both custom events should be found.");
+    let timestamps: HashSet<_> = records.iter().map(|record| record.timestamp).collect();
+    assert_eq!(timestamps.len(), 20);
+
+    let unknown_label =
+        select_metrics_between_labels(pool.acquire().await?, run_id, "build done", "deploy done")
+            .await?;
+    assert!(unknown_label.is_none());
+
+    let reversed_labels =
+        select_metrics_between_labels(pool.acquire().await?, run_id, "tests done", "build done")
+            .await?;
+    assert!(reversed_labels.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures("../fixtures/metrics.sql"))]
+async fn it_lists_the_pipelines_of_a_project_a_page_at_a_time_with_their_totals(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    let project_id = uuid!("95dfae11-5cad-41d9-bcf9-fa6564c22dd6");
+    let earlier_pipeline_ids = [
+        uuid!("80d53828-dcb8-4f45-aa4c-bc666e3ee54c"),
+        uuid!("93a50da7-d390-43ed-92cf-192bc5e41eb2"),
+        uuid!("648f8be8-0646-453e-8e48-110338b5e398"),
+    ];
+    let measured_pipeline_id = uuid!("9d807f09-e006-4808-9fa2-70f67432d37b");
+    for pipeline_id in earlier_pipeline_ids {
+        let event = Event {
+            id: uuid::Uuid::new_v4(),
+            timestamp: Local::now(),
+            project_id,
+            workflow_id: uuid!("03c06a5e-a139-4a9e-a770-f69821b10faf"),
+            pipeline_id,
+            job_id: uuid!("6579f658-9286-493e-a3ed-0d92afa09edd"),
+            run_id: uuid!("e51076c8-5c47-4a47-a146-04625e77a6ae"),
+            task_id: uuid!("83e9b273-9aa9-4996-8141-751b91aa98b2"),
+            process_id: uuid!("df10c3cc-2033-4347-8394-1979d7ad57ec"),
+            device_id: uuid!("599d2042-98b9-46df-bcc1-8c03c85da332"),
+            event_type: EventType::Start,
+            user_label: None,
+        };
+        event.insert(pool.acquire().await?).await.unwrap();
+    }
+
+    let filter = DimensionFilter::Parent {
+        dimension: "project".to_string(),
+        id: project_id,
+    };
+    let mut params = ListParams {
+        sort: SortKey::StartDate,
+        order: SortOrder::Asc,
+        limit: Some(3),
+        cursor: None,
+    };
+    let first_page = select_dimension_summaries(pool.acquire().await?, "pipelines", &filter, &params)
+        .await
+        .unwrap();
+    assert_eq!(
+        first_page
+            .items
+            .iter()
+            .map(|pipeline| pipeline.id)
+            .collect::<Vec<uuid::Uuid>>(),
+        earlier_pipeline_ids
+    );
+    assert!(first_page.next_cursor.is_some());
+
+    params.cursor = first_page.next_cursor;
+    let last_page = select_dimension_summaries(pool.acquire().await?, "pipelines", &filter, &params)
+        .await
+        .unwrap();
+    assert_eq!(last_page.items.len(), 1);
+    assert_eq!(last_page.items[0].id, measured_pipeline_id);
+    assert!(last_page.items[0].emissions_gco2eq > 0.0);
+    assert_eq!(last_page.items[0].run_count, 1);
+    assert_eq!(last_page.next_cursor, None);
+
+    params.order = SortOrder::Desc;
+    params.cursor = None;
+    let latest_first = select_dimension_summaries(pool.acquire().await?, "pipelines", &filter, &params)
+        .await
+        .unwrap();
+    assert_eq!(latest_first.items[0].id, measured_pipeline_id);
+
+    Ok(())
+}
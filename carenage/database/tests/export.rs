@@ -0,0 +1,29 @@
+#![cfg(feature = "export")]
+
+use database::export::ExportFormat;
+use database::ingest::IngestionClient;
+use mockito::{Matcher, Server};
+
+#[sqlx::test]
+async fn it_exports_the_metrics_of_a_run_through_the_api() {
+    let run_id = uuid::Uuid::new_v4();
+    let mut api_server = Server::new_async().await;
+    let _mock = api_server
+        .mock("GET", format!("/runs/{}", run_id).as_str())
+        .match_query(Matcher::UrlEncoded("format".to_string(), "csv".to_string()))
+        .match_header("authorization", "Bearer crn_token")
+        .with_status(200)
+        .with_header("content-type", "text/csv")
+        .with_body("timestamp,pid,exe,metric,value,unit\n")
+        .create_async()
+        .await;
+
+    let client = IngestionClient::new(&api_server.url(), "crn_token");
+    let exported = client.get_run_export(run_id, ExportFormat::Csv).await.unwrap();
+    assert_eq!(exported, b"timestamp,pid,exe,metric,value,unit\n");
+
+    assert!(client
+        .get_run_export(uuid::Uuid::new_v4(), ExportFormat::Csv)
+        .await
+        .is_err());
+}
@@ -120,3 +120,97 @@ fn it_prints_stop_timestamp_in_iso_8601_format() -> Result<(), Box<dyn std::erro
 
     Ok(())
 }
+
+#[test]
+fn it_fails_to_stop_when_a_report_is_requested_with_the_api_transport(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args(["--session", "without-carenaged", "stop", "--metrics-report", "metrics.txt"])
+        .env("CARENAGE_API_URL", "http://127.0.0.1:1")
+        .env("CARENAGE_API_TOKEN", "secret")
+        .env("BOAGENT_URL", "http://127.0.0.1:8000")
+        .env("PROJECT_NAME", "hubblo/carenage")
+        .env("LOCATION", "FRA")
+        .env("LIFETIME", "5");
+    cmd.assert()
+        .failure()
+        .stderr(contains("unavailable with the API transport"));
+
+    Ok(())
+}
+
+// carenage event
+#[test]
+fn it_fails_when_no_carenaged_runs_for_the_session() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args(["--session", "without-carenaged", "event", "build done"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No carenaged running for session without-carenaged"));
+
+    Ok(())
+}
+
+// carenage run
+#[test]
+fn it_fails_when_no_command_is_given_to_run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.arg("run");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn it_fails_when_a_functional_unit_is_given_without_its_count() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args(["run", "--functional-unit", "test", "--", "true"]);
+    cmd.assert().failure().stderr(contains("--functional-unit-count"));
+
+    Ok(())
+}
+
+// carenage --ci
+#[test]
+fn it_fails_when_an_unknown_ci_platform_is_given() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args(["--ci", "travis", "start"]);
+    cmd.assert().failure().stderr(contains("Unknown CI platform"));
+
+    Ok(())
+}
+
+// carenage compare
+#[test]
+fn it_fails_when_pipelines_to_compare_are_not_given() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args(["compare", "--base", "9d807f09-e006-4808-9fa2-70f67432d37b"]);
+    cmd.assert().failure().stderr(contains("--head"));
+
+    Ok(())
+}
+
+// carenage export
+#[test]
+fn it_fails_when_an_unknown_export_format_is_given() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("carenage-cli")?;
+
+    cmd.args([
+        "export",
+        "--run",
+        "e51076c8-5c47-4a47-a146-04625e77a6ae",
+        "--format",
+        "xml",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Unknown export format: xml."));
+
+    Ok(())
+}
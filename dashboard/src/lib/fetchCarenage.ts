@@ -1,6 +1,13 @@
+import { env } from "$env/dynamic/private";
+
+// The Carenage API needs a token allowed to read the data of the requested projects.
+const carenageApiHeaders = () => ({ Authorization: `Bearer ${env.CARENAGE_API_TOKEN}` });
+
 export async function fetchProject(projectId: string) {
   try {
-    const response = await fetch(`https://api.carenage.hubblo.org/projects/${projectId}`);
+    const response = await fetch(`https://api.carenage.hubblo.org/projects/${projectId}`, {
+      headers: carenageApiHeaders()
+    });
     if (!response.ok) {
       throw new Error(
         `Failed to fetch project data with ID "${projectId}". Response status: ${response.status}`
@@ -15,7 +22,9 @@ export async function fetchProject(projectId: string) {
 
 export async function fetchPipeline(pipelineId: string) {
   try {
-    const response = await fetch(`https://api.carenage.hubblo.org/pipelines/${pipelineId}`);
+    const response = await fetch(`https://api.carenage.hubblo.org/pipelines/${pipelineId}`, {
+      headers: carenageApiHeaders()
+    });
     if (!response.ok) {
       throw new Error(
         `Failed to fetch pipeline data with ID "${pipelineId}". Response status: ${response.status}`
@@ -31,7 +40,9 @@ export async function fetchPipeline(pipelineId: string) {
 
 export async function fetchRun(runId: string) {
   try {
-    const response = await fetch(`https://api.carenage.hubblo.org/runs/${runId}`);
+    const response = await fetch(`https://api.carenage.hubblo.org/runs/${runId}`, {
+      headers: carenageApiHeaders()
+    });
     if (!response.ok) {
       throw new Error(
         `Failed to fetch run data with ID "${runId}". Response status: ${response.status}`
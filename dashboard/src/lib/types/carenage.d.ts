@@ -41,6 +41,20 @@ export declare type CiRun = CiRunMetadata & {
   processes: Process[];
 };
 
+export declare type VcsContext = {
+  commit_sha: string | null;
+  ref_name: string | null;
+  merge_request_iid: number | null;
+  tag: string | null;
+  commit_timestamp: string | null;
+  pipeline_url: string | null;
+};
+
+export declare type Energy = {
+  value: number;
+  unit: "Wh" | "kWh";
+};
+
 export declare type CiPipelineMetadata = {
   pipeline_id: string;
   pipeline_repo_id: number;
@@ -50,6 +64,8 @@ export declare type CiPipelineMetadata = {
   started_at: string;
   finished_at: string;
   duration: number;
+  vcs?: VcsContext | null;
+  energy?: Energy | null;
   runs: CiRunMetadata[];
 };
 
@@ -1,4 +1,5 @@
 import type { UUID } from "crypto";
+import type { VcsContext } from "$lib/types/carenage";
 
 export function formatDuration(duration_in_seconds: number) {
   const date = new Date(0);
@@ -32,3 +33,31 @@ export function formatTime(date: string) {
 export function isUUID(uuid: string): uuid is UUID {
   return /^[0-9a-f]{8}-[0-9a-f]{4}-[1-5][0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$/i.test(uuid);
 }
+
+/* Links to the commit and merge request of a pipeline are built from the URL of the pipeline, whose
+ * path tells its CI platform. Platforms whose pipeline URL does not lead to the repository, such as
+ * Jenkins, get no links. */
+export function vcsLinks(vcs: VcsContext) {
+  const links: { commit?: string; mergeRequest?: string } = {};
+  const pipelineUrl = vcs.pipeline_url ?? "";
+  const platforms = [
+    {
+      pipelinePath: "/-/pipelines/",
+      commitPath: "/-/commit/",
+      mergeRequestPath: "/-/merge_requests/"
+    },
+    { pipelinePath: "/actions/runs/", commitPath: "/commit/", mergeRequestPath: "/pull/" }
+  ];
+  const platform = platforms.find((platform) => pipelineUrl.includes(platform.pipelinePath));
+  if (!platform) {
+    return links;
+  }
+  const repoUrl = pipelineUrl.substring(0, pipelineUrl.indexOf(platform.pipelinePath));
+  if (vcs.commit_sha) {
+    links.commit = `${repoUrl}${platform.commitPath}${vcs.commit_sha}`;
+  }
+  if (vcs.merge_request_iid) {
+    links.mergeRequest = `${repoUrl}${platform.mergeRequestPath}${vcs.merge_request_iid}`;
+  }
+  return links;
+}
@@ -24,6 +24,36 @@ describe("pipeline test suite", () => {
     expect(pipelineLink).toBeVisible();
     expect(projectLink).toBeVisible();
   });
+  it("displays to the user links to the commit and merge request the pipeline was executed for", () => {
+    const pipelineMetadataLabel = `Pipeline #${pipeline.pipeline_repo_id} metadata`;
+    const pipelineMetadataSection = screen.getByRole("region", { name: pipelineMetadataLabel });
+    const commitLink = within(pipelineMetadataSection).getByRole("link", {
+      name: "Commit a1b2c3d4"
+    });
+    const mergeRequestLink = within(pipelineMetadataSection).getByRole("link", {
+      name: "Merge request !42"
+    });
+    const branch = within(pipelineMetadataSection).getByText(pipeline.vcs.ref_name, {
+      exact: false
+    });
+
+    expect(commitLink).toHaveAttribute(
+      "href",
+      `https://gitlab.com/hubblo/carenage/-/commit/${pipeline.vcs.commit_sha}`
+    );
+    expect(mergeRequestLink).toHaveAttribute(
+      "href",
+      "https://gitlab.com/hubblo/carenage/-/merge_requests/42"
+    );
+    expect(branch).toBeVisible();
+  });
+  it("displays to the user the energy consumed by the pipeline", () => {
+    const pipelineMetadataLabel = `Pipeline #${pipeline.pipeline_repo_id} metadata`;
+    const pipelineMetadataSection = screen.getByRole("region", { name: pipelineMetadataLabel });
+    const energy = within(pipelineMetadataSection).getByText("12.345 Wh", { exact: false });
+
+    expect(energy).toBeVisible();
+  });
   it("displays to the user a selection of metadata related to the pipeline", () => {
     const pipelineMetadataLabel = `Pipeline #${pipeline.pipeline_repo_id} metadata`;
     const pipelineMetadataSection = screen.getByRole("region", { name: pipelineMetadataLabel });
@@ -1,4 +1,4 @@
-import { getMetricUnit, isUUID } from "$lib/utils";
+import { getMetricUnit, isUUID, vcsLinks } from "$lib/utils";
 import { describe, it, expect } from "vitest";
 
 it("strips metricName to last digits to get the unit of the metric", () => {
@@ -21,3 +21,28 @@ describe("isUUID function test suite", () => {
     });
   });
 });
+
+describe("vcsLinks function test suite", () => {
+  const vcs = {
+    commit_sha: "a1b2c3d4",
+    ref_name: "main",
+    merge_request_iid: 42,
+    tag: null,
+    commit_timestamp: null,
+    pipeline_url: null
+  };
+  it("links to the commit and pull request of a GitHub Actions run", () => {
+    const links = vcsLinks({
+      ...vcs,
+      pipeline_url: "https://github.com/hubblo-org/carenage/actions/runs/1658821493"
+    });
+    expect(links.commit).toEqual("https://github.com/hubblo-org/carenage/commit/a1b2c3d4");
+    expect(links.mergeRequest).toEqual("https://github.com/hubblo-org/carenage/pull/42");
+  });
+  it("gives no links for pipelines whose URL does not lead to the repository", () => {
+    const urls = [null, "https://jenkins.example.org/job/carenage/17/"];
+    urls.forEach((pipeline_url) => {
+      expect(vcsLinks({ ...vcs, pipeline_url })).toEqual({});
+    });
+  });
+});
@@ -0,0 +1,7 @@
+ALTER TABLE pipelines
+  ADD COLUMN commit_sha VARCHAR(64),
+  ADD COLUMN ref_name VARCHAR(255),
+  ADD COLUMN merge_request_iid INTEGER,
+  ADD COLUMN tag VARCHAR(255),
+  ADD COLUMN commit_timestamp TIMESTAMPTZ,
+  ADD COLUMN pipeline_url TEXT;
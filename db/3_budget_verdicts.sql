@@ -0,0 +1,10 @@
+CREATE TABLE budget_verdicts (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  run_id UUID REFERENCES runs(id),
+  energy_budget_wh FLOAT8,
+  emissions_budget_gco2eq FLOAT8,
+  energy_wh FLOAT8,
+  emissions_gco2eq FLOAT8,
+  exceeded BOOLEAN,
+  evaluated_at TIMESTAMPTZ DEFAULT current_timestamp
+);
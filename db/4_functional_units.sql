@@ -0,0 +1,3 @@
+ALTER TABLE runs
+  ADD COLUMN functional_unit VARCHAR(255),
+  ADD COLUMN functional_unit_count FLOAT8;
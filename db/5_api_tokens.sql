@@ -0,0 +1,16 @@
+CREATE TYPE token_permission AS ENUM ('read', 'write');
+
+CREATE TABLE api_tokens (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  name VARCHAR(255),
+  token_hash CHAR(64) UNIQUE NOT NULL,
+  created_at TIMESTAMPTZ DEFAULT current_timestamp,
+  revoked_at TIMESTAMPTZ
+);
+
+CREATE TABLE api_token_scopes (
+  token_id UUID REFERENCES api_tokens(id),
+  project_id UUID REFERENCES projects(id),
+  permission token_permission,
+  PRIMARY KEY (token_id, project_id)
+);